@@ -52,6 +52,12 @@ impl AggregatedErrorRates {
         }
     }
 
+    pub fn merge(&mut self, other: &AggregatedErrorRates) {
+        self.total_count += other.total_count;
+        self.client_error_4xx_count += other.client_error_4xx_count;
+        self.server_error_5xx_count += other.server_error_5xx_count;
+    }
+
     pub fn result(&self) -> Option<ErrorRatesResult> {
         if self.total_count == 0 {
             return None;
@@ -143,6 +149,26 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_merge() {
+        let mut error_rates = AggregatedErrorRates::new();
+        error_rates.add(&String::from("200"));
+        error_rates.add(&String::from("500"));
+
+        let mut other = AggregatedErrorRates::new();
+        other.add(&String::from("403"));
+        other.add(&String::from("200"));
+
+        error_rates.merge(&other);
+
+        let result = error_rates.result();
+        let expected = Some(ErrorRatesResult {
+            client_error_4xx: 0.25,
+            server_error_5xx: 0.25,
+        });
+        assert_eq!(result, expected);
+    }
+
     #[test]
     fn test_empty() {
         let error_rates = AggregatedErrorRates::new();
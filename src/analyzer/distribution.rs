@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use chrono::{DateTime, FixedOffset};
+use crate::analyzer::Timing;
+use crate::filter::field_regex::LogField;
+use crate::request_response_matcher;
+
+/// What a request is counted under in a `Distribution`.
+pub trait Category {
+    fn status(&self) -> Option<u16>;
+
+    /// The values of the labels of a `grouping::Grouping`
+    fn labels(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// The request, to be shown as an example of its response time
+    fn exemplar(&self) -> Option<Exemplar> {
+        None
+    }
+}
+
+impl Category for request_response_matcher::RequestResponsePair {
+    fn status(&self) -> Option<u16> {
+        self.response.status
+    }
+
+    fn exemplar(&self) -> Option<Exemplar> {
+        Some(Exemplar {
+            request_id: self.request.id,
+            path: LogField::Path.values(self)[0].map(String::from),
+            time: self.request.time,
+        })
+    }
+}
+
+/// A request from the log, which can be looked up by its id and time. The
+/// path is only known if the original log lines are kept.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Exemplar {
+    pub request_id: i32,
+    pub path: Option<String>,
+    pub time: DateTime<FixedOffset>,
+}
+
+/// How many requests of each combination of labels fell into each bucket of
+/// response times and got each status.
+///
+/// Requests are only counted, so a distribution takes the same memory
+/// however many requests it describes.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Distribution {
+    /// The increasing upper bounds of the buckets in seconds
+    pub buckets: Vec<f64>,
+    /// The requests by the values of their labels
+    pub series: BTreeMap<Vec<String>, Series>,
+}
+
+/// The requests with the same labels.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Series {
+    /// Number of requests in each bucket, the `+Inf` bucket comes after the
+    /// last upper bound
+    pub counts: Vec<usize>,
+    /// Sum of the response times in microseconds
+    pub sum: i64,
+    /// Number of responses by status, `None` for responses without one
+    pub statuses: BTreeMap<Option<u16>, usize>,
+    /// The latest request in each bucket with its response time in
+    /// microseconds
+    pub exemplars: BTreeMap<usize, (i64, Exemplar)>,
+}
+
+impl Distribution {
+    pub fn with_buckets(buckets: &[f64]) -> Distribution {
+        Distribution {
+            buckets: buckets.to_vec(),
+            series: BTreeMap::new(),
+        }
+    }
+
+    pub fn add<T>(&mut self, value: &T)
+    where
+        T: Timing + Category,
+    {
+        let microseconds = value.num_microseconds();
+        let bucket = bucket(&self.buckets, microseconds as f64 / 1_000_000.0);
+        let size = self.buckets.len() + 1;
+        let series = self.series
+            .entry(value.labels())
+            .or_insert_with(|| Series::new(size));
+
+        series.counts[bucket] += 1;
+        series.sum += microseconds;
+        *series.statuses.entry(value.status()).or_insert(0) += 1;
+
+        if let Some(exemplar) = value.exemplar() {
+            series.add_exemplar(bucket, microseconds, exemplar);
+        }
+    }
+
+    pub fn merge(&mut self, other: &Distribution) {
+        let size = self.buckets.len() + 1;
+
+        for (labels, other) in &other.series {
+            let series = self.series.entry(labels.clone()).or_insert_with(
+                || Series::new(size),
+            );
+
+            for (count, other_count) in series.counts.iter_mut().zip(&other.counts) {
+                *count += other_count;
+            }
+
+            series.sum += other.sum;
+
+            for (status, count) in &other.statuses {
+                *series.statuses.entry(*status).or_insert(0) += count;
+            }
+
+            for (bucket, (microseconds, exemplar)) in &other.exemplars {
+                series.add_exemplar(*bucket, *microseconds, exemplar.clone());
+            }
+        }
+    }
+}
+
+impl Series {
+    fn new(size: usize) -> Series {
+        Series {
+            counts: vec![0; size],
+            ..Series::default()
+        }
+    }
+
+    fn add_exemplar(&mut self, bucket: usize, microseconds: i64, exemplar: Exemplar) {
+        match self.exemplars.entry(bucket) {
+            Entry::Occupied(mut entry) => {
+                if entry.get().1.time <= exemplar.time {
+                    entry.insert((microseconds, exemplar));
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((microseconds, exemplar));
+            }
+        }
+    }
+}
+
+/// The index of the bucket of `seconds` in `buckets`, the `+Inf` bucket after
+/// the last upper bound.
+pub fn bucket(buckets: &[f64], seconds: f64) -> usize {
+    buckets
+        .iter()
+        .position(|upper_bound| seconds <= *upper_bound)
+        .unwrap_or(buckets.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use super::*;
+
+    struct Pair(i64, Option<u16>, &'static str);
+
+    /// A request with an id and a time in seconds
+    struct Identified(Pair, i32, i64);
+
+    impl Timing for Pair {
+        fn num_microseconds(&self) -> i64 {
+            self.0
+        }
+    }
+
+    impl Category for Pair {
+        fn status(&self) -> Option<u16> {
+            self.1
+        }
+
+        fn labels(&self) -> Vec<String> {
+            vec![String::from(self.2)]
+        }
+    }
+
+    impl Timing for Identified {
+        fn num_microseconds(&self) -> i64 {
+            self.0.num_microseconds()
+        }
+    }
+
+    impl Category for Identified {
+        fn status(&self) -> Option<u16> {
+            self.0.status()
+        }
+
+        fn labels(&self) -> Vec<String> {
+            self.0.labels()
+        }
+
+        fn exemplar(&self) -> Option<Exemplar> {
+            Some(exemplar(self.1, self.2))
+        }
+    }
+
+    fn exemplar(request_id: i32, seconds: i64) -> Exemplar {
+        Exemplar {
+            request_id,
+            path: None,
+            time: FixedOffset::east_opt(7200).unwrap().timestamp_opt(seconds, 0).unwrap(),
+        }
+    }
+
+    fn series(counts: Vec<usize>, sum: i64, statuses: Vec<(Option<u16>, usize)>) -> Series {
+        Series {
+            counts,
+            sum,
+            statuses: statuses.into_iter().collect(),
+            exemplars: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_add_and_merge() {
+        let mut distribution = Distribution::with_buckets(&[0.001, 0.01]);
+        distribution.add(&Pair(7000, Some(200), "GET"));
+        distribution.add(&Pair(10000, Some(200), "GET"));
+
+        let mut other = Distribution::with_buckets(&[0.001, 0.01]);
+        other.add(&Pair(7000, Some(404), "GET"));
+        other.add(&Pair(7000, Some(200), "POST"));
+        other.add(&Pair(250, None, "GET"));
+        other.add(&Pair(20000, Some(200), "GET"));
+
+        distribution.merge(&other);
+
+        assert_eq!(
+            distribution.series,
+            vec![
+                (
+                    vec![String::from("GET")],
+                    series(vec![1, 3, 1], 44250, vec![(None, 1), (Some(200), 3), (Some(404), 1)]),
+                ),
+                (vec![String::from("POST")], series(vec![0, 1, 0], 7000, vec![(Some(200), 1)])),
+            ].into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn test_exemplars() {
+        let mut distribution = Distribution::with_buckets(&[0.008]);
+        distribution.add(&Identified(Pair(7000, Some(200), "GET"), 1, 100));
+        distribution.add(&Identified(Pair(6000, Some(200), "GET"), 2, 200));
+        distribution.add(&Identified(Pair(9000, Some(200), "GET"), 3, 300));
+
+        let mut other = Distribution::with_buckets(&[0.008]);
+        other.add(&Identified(Pair(7000, Some(404), "GET"), 4, 150));
+        other.add(&Identified(Pair(9000, Some(200), "GET"), 5, 400));
+
+        distribution.merge(&other);
+
+        assert_eq!(
+            distribution.series[&vec![String::from("GET")]].exemplars,
+            vec![(0, (6000, exemplar(2, 200))), (1, (9000, exemplar(5, 400)))]
+                .into_iter()
+                .collect()
+        );
+    }
+}
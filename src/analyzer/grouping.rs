@@ -0,0 +1,286 @@
+use regex::Regex;
+use crate::analyzer::Timing;
+use crate::analyzer::aggregated_error_rates::HttpErrorState;
+use crate::analyzer::distribution::{Category, Exemplar};
+use crate::filter::field_regex::LogField;
+use crate::log_parser::log_events::HttpError;
+use crate::request_response_matcher::RequestResponsePair;
+
+/// The methods that are labeled by name, all others are `other`. Clients can
+/// send any method, but every label value is a time series of its own.
+const METHODS: &[&str] = &[
+    "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH",
+];
+
+/// A category of requests, which becomes a label of the metrics.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum GroupBy {
+    /// Like `2xx`, `unknown` for responses without a status
+    StatusClass,
+    /// One of `METHODS`, or `other`
+    Method,
+    /// The name of the first matching `PathGroup`, or `other`
+    PathGroup,
+}
+
+impl GroupBy {
+    pub fn from_name(name: &str) -> Option<GroupBy> {
+        match name {
+            "status_class" => Some(GroupBy::StatusClass),
+            "method" => Some(GroupBy::Method),
+            "path_group" => Some(GroupBy::PathGroup),
+            _ => None,
+        }
+    }
+
+    /// Parse names like `method,path_group`, or return the unknown name.
+    pub fn parse_list(value: &str) -> Result<Vec<GroupBy>, &str> {
+        value
+            .split(',')
+            .map(|name| GroupBy::from_name(name.trim()).ok_or(name))
+            .collect()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GroupBy::StatusClass => "status_class",
+            GroupBy::Method => "method",
+            GroupBy::PathGroup => "path_group",
+        }
+    }
+}
+
+/// Requests with a path matching `regex` are in the group `name`, written
+/// as `NAME=REGEX`.
+#[derive(Debug, Clone)]
+pub struct PathGroup {
+    pub name: String,
+    pub regex: Regex,
+}
+
+impl PathGroup {
+    pub fn parse(value: &str) -> Option<Result<PathGroup, regex::Error>> {
+        let (name, pattern) = value.split_once('=')?;
+
+        Some(Regex::new(pattern).map(|regex| {
+            PathGroup {
+                name: String::from(name),
+                regex,
+            }
+        }))
+    }
+}
+
+impl PartialEq for PathGroup {
+    fn eq(&self, other: &PathGroup) -> bool {
+        self.name == other.name && self.regex.as_str() == other.regex.as_str()
+    }
+}
+
+/// How requests are grouped into categories, none by default.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Grouping {
+    pub group_by: Vec<GroupBy>,
+    pub path_groups: Vec<PathGroup>,
+    /// The name of the `--prometheus-source` the requests are from, which
+    /// comes before the other labels as `source`
+    pub source: Option<String>,
+}
+
+impl Grouping {
+    pub fn label_names(&self) -> Vec<&'static str> {
+        let source = self.source.as_ref().map(|_| "source");
+
+        source
+            .into_iter()
+            .chain(self.group_by.iter().map(|group_by| group_by.name()))
+            .collect()
+    }
+
+    /// Methods and paths are read from the original log lines.
+    pub fn needs_original_log_line(&self) -> bool {
+        self.group_by.iter().any(
+            |group_by| *group_by != GroupBy::StatusClass,
+        )
+    }
+
+    pub fn label(&self, pair: RequestResponsePair) -> Labeled {
+        let labels = self.source
+            .iter()
+            .cloned()
+            .chain(self.group_by.iter().map(|group_by| match group_by {
+                GroupBy::StatusClass => {
+                    match pair.response.status {
+                        Some(status) => format!("{}xx", status / 100),
+                        None => String::from("unknown"),
+                    }
+                }
+                GroupBy::Method => method(&pair),
+                GroupBy::PathGroup => self.path_group(&pair),
+            }))
+            .collect();
+
+        Labeled { pair, labels }
+    }
+
+    fn path_group(&self, pair: &RequestResponsePair) -> String {
+        let path = match LogField::Path.values(pair)[0] {
+            Some(path) => path,
+            None => return String::from("unknown"),
+        };
+
+        match self.path_groups.iter().find(
+            |path_group| path_group.regex.is_match(path),
+        ) {
+            Some(path_group) => path_group.name.clone(),
+            None => String::from("other"),
+        }
+    }
+}
+
+fn method(pair: &RequestResponsePair) -> String {
+    match LogField::Method.values(pair)[0] {
+        Some(method) if METHODS.contains(&method) => String::from(method),
+        Some(_) => String::from("other"),
+        None => String::from("unknown"),
+    }
+}
+
+/// A request/response pair with the values of its labels, in the order of
+/// `Grouping::label_names`.
+pub struct Labeled {
+    pub pair: RequestResponsePair,
+    pub labels: Vec<String>,
+}
+
+impl Timing for Labeled {
+    fn num_microseconds(&self) -> i64 {
+        self.pair.num_microseconds()
+    }
+}
+
+impl HttpErrorState for Labeled {
+    fn error(&self) -> Option<HttpError> {
+        self.pair.error()
+    }
+}
+
+impl Category for Labeled {
+    fn status(&self) -> Option<u16> {
+        self.pair.status()
+    }
+
+    fn labels(&self) -> Vec<String> {
+        self.labels.clone()
+    }
+
+    fn exemplar(&self) -> Option<Exemplar> {
+        self.pair.exemplar()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::log_parser::log_events::*;
+    use super::*;
+
+    fn get_fixture(path: &str, status: &str) -> RequestResponsePair {
+        RequestResponsePair {
+            request: Request::new_from_log_line(&format!(
+                "08/Apr/2016:09:57:47 +0200 [001] -> GET {} HTTP/1.1",
+                path
+            )).unwrap(),
+            response: Response::new_from_log_line(&format!(
+                "08/Apr/2016:09:57:47 +0200 [001] <- {} text/html 7ms",
+                status
+            )).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_label() {
+        let grouping = Grouping {
+            group_by: vec![GroupBy::Method, GroupBy::StatusClass, GroupBy::PathGroup],
+            path_groups: vec![],
+            source: None,
+        };
+
+        assert_eq!(grouping.label_names(), vec!["method", "status_class", "path_group"]);
+        assert_eq!(
+            grouping.label(get_fixture("/content/site/page.html", "404")).labels,
+            vec!["GET", "4xx", "other"]
+        );
+    }
+
+    #[test]
+    fn test_label_method() {
+        let grouping = Grouping {
+            group_by: vec![GroupBy::Method],
+            path_groups: vec![],
+            source: None,
+        };
+
+        let mut pair = get_fixture("/", "200");
+        assert_eq!(grouping.label(pair.clone()).labels, vec!["GET"]);
+
+        pair.request = Request::new_from_log_line(
+            "08/Apr/2016:09:57:47 +0200 [001] -> XYZZY-1 / HTTP/1.1",
+        ).unwrap();
+        assert_eq!(grouping.label(pair).labels, vec!["other"]);
+    }
+
+    #[test]
+    fn test_label_path_groups() {
+        let grouping = Grouping {
+            group_by: vec![GroupBy::PathGroup],
+            path_groups: vec![
+                PathGroup::parse(r"pages=^/content/.*\.html").unwrap().unwrap(),
+                PathGroup::parse("clientlibs=^/etc.clientlibs/").unwrap().unwrap(),
+            ],
+            source: None,
+        };
+
+        for &(path, expected) in &[
+            ("/content/site/page.html", "pages"),
+            ("/etc.clientlibs/site.css", "clientlibs"),
+            ("/content/site/page.model.json", "other"),
+        ]
+        {
+            assert_eq!(grouping.label(get_fixture(path, "200")).labels, vec![expected]);
+        }
+
+        assert!(PathGroup::parse("pages").is_none());
+        assert!(PathGroup::parse("pages=(").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_label_source() {
+        let mut grouping = Grouping {
+            group_by: vec![GroupBy::StatusClass],
+            path_groups: vec![],
+            source: Some(String::from("author")),
+        };
+
+        assert_eq!(grouping.label_names(), vec!["source", "status_class"]);
+        assert_eq!(
+            grouping.label(get_fixture("/content/site/page.html", "200")).labels,
+            vec!["author", "2xx"]
+        );
+
+        grouping.group_by.clear();
+        assert_eq!(grouping.label_names(), vec!["source"]);
+        assert_eq!(grouping.label(get_fixture("/", "200")).labels, vec!["author"]);
+    }
+
+    #[test]
+    fn test_needs_original_log_line() {
+        let mut grouping = Grouping::default();
+        assert!(!grouping.needs_original_log_line());
+
+        grouping.group_by.push(GroupBy::StatusClass);
+        assert!(!grouping.needs_original_log_line());
+
+        grouping.group_by.push(GroupBy::PathGroup);
+        assert!(grouping.needs_original_log_line());
+    }
+}
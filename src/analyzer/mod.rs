@@ -3,81 +3,239 @@ use crate::result;
 use crate::request_response_matcher;
 
 pub mod aggregated_error_rates;
+pub mod distribution;
+pub mod grouping;
+
+/// Unit in which the values of a `TimingResult` are reported.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TimeUnit {
+    Microseconds,
+    Milliseconds,
+    Seconds,
+}
+
+impl TimeUnit {
+    /// The name of the unit for `--time-unit`
+    pub fn name(self) -> &'static str {
+        match self {
+            TimeUnit::Microseconds => "us",
+            TimeUnit::Milliseconds => "ms",
+            TimeUnit::Seconds => "s",
+        }
+    }
+
+    /// Milliseconds are whole, like earlier versions reported them, only
+    /// seconds have fractions.
+    fn convert_microseconds(self, microseconds: f64) -> f64 {
+        match self {
+            TimeUnit::Microseconds => microseconds.round(),
+            TimeUnit::Milliseconds => (microseconds / 1_000.0).trunc(),
+            TimeUnit::Seconds => microseconds.round() / 1_000_000.0,
+        }
+    }
+}
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct TimingResult {
-    pub max: usize,
-    pub min: usize,
-    pub avg: usize,
-    pub median: usize,
-    pub percentile90: usize,
-    pub percentile99: usize,
+    pub max: f64,
+    pub min: f64,
+    pub avg: f64,
+    pub median: f64,
+    pub percentile90: f64,
+    pub percentile99: f64,
     pub count: usize,
 }
 
 pub trait Timing {
-    fn num_milliseconds(&self) -> i64;
+    fn num_microseconds(&self) -> i64;
 }
 
 impl Timing for request_response_matcher::RequestResponsePair {
-    fn num_milliseconds(&self) -> i64 {
-        self.response.response_time.num_milliseconds()
+    fn num_microseconds(&self) -> i64 {
+        self.response
+            .response_time
+            .num_microseconds()
+            .unwrap_or(i64::MAX)
     }
 }
 
 impl Timing for Box<dyn Timing> {
-    fn num_milliseconds(&self) -> i64 {
-        (**self).num_milliseconds()
+    fn num_microseconds(&self) -> i64 {
+        (**self).num_microseconds()
     }
 }
 
-pub fn analyze_iterator<I, T>(timings: I) -> result::RequestLogAnalyzerResult
+/// With `buckets`, the requests are also counted into a distribution by
+/// those upper bounds in seconds.
+pub fn analyze_iterator<I, T>(
+    timings: I,
+    time_unit: TimeUnit,
+    buckets: Option<&[f64]>,
+) -> result::RequestLogAnalyzerResult
 where
     I: Iterator<Item = T>,
-    T: Timing + aggregated_error_rates::HttpErrorState,
+    T: Timing + aggregated_error_rates::HttpErrorState + distribution::Category,
 {
     let mut stats = aggregated_stats::AggregatedStats::new();
     let mut error_rates = aggregated_error_rates::AggregatedErrorRates::new();
+    let mut distribution = buckets.map(distribution::Distribution::with_buckets);
 
     for timing in timings {
-        stats.add(timing.num_milliseconds() as usize);
+        stats.add(timing.num_microseconds() as usize);
         error_rates.add(&timing);
+
+        if let Some(ref mut distribution) = distribution {
+            distribution.add(&timing);
+        }
+    }
+
+    analysis_result(
+        &stats,
+        &error_rates,
+        distribution.unwrap_or_default(),
+        time_unit,
+        vec![],
+    )
+}
+
+/// Analysis state of a part of the input, e.g. a single file.
+///
+/// Unlike `aggregated_stats::AggregatedStats`, partial analyses can be
+/// merged. All timings are kept until the final result is calculated, so the
+/// merged result is the same as if everything had been analyzed in one go.
+pub struct PartialAnalysis {
+    timings: Vec<usize>,
+    error_rates: aggregated_error_rates::AggregatedErrorRates,
+    distribution: Option<distribution::Distribution>,
+    files: Vec<result::FileSummary>,
+}
+
+impl PartialAnalysis {
+    pub fn new() -> PartialAnalysis {
+        PartialAnalysis {
+            timings: vec![],
+            error_rates: aggregated_error_rates::AggregatedErrorRates::new(),
+            distribution: None,
+            files: vec![],
+        }
+    }
+
+    /// A partial analysis which also counts the requests into a distribution
+    /// by the upper bounds of `buckets` in seconds, for the Prometheus
+    /// renderers.
+    pub fn with_distribution(buckets: &[f64]) -> PartialAnalysis {
+        PartialAnalysis {
+            distribution: Some(distribution::Distribution::with_buckets(buckets)),
+            ..PartialAnalysis::new()
+        }
+    }
+
+    pub fn add<T>(&mut self, timing: &T)
+    where
+        T: Timing + aggregated_error_rates::HttpErrorState + distribution::Category,
+    {
+        self.timings.push(timing.num_microseconds() as usize);
+        self.error_rates.add(timing);
+
+        if let Some(ref mut distribution) = self.distribution {
+            distribution.add(timing);
+        }
+    }
+
+    /// Add the summary of an input file. Summaries of the same file, e.g.
+    /// from several chunks, are combined.
+    pub fn add_file(&mut self, summary: result::FileSummary) {
+        match self.files.iter_mut().find(
+            |file| file.filename == summary.filename,
+        ) {
+            Some(file) => {
+                file.lines += summary.lines;
+                file.error = file.error.take().or(summary.error);
+            }
+            None => self.files.push(summary),
+        }
+    }
+
+    pub fn merge(&mut self, other: PartialAnalysis) {
+        self.timings.extend(other.timings);
+        self.error_rates.merge(&other.error_rates);
+        self.distribution = match (self.distribution.take(), other.distribution) {
+            (Some(mut distribution), Some(other)) => {
+                distribution.merge(&other);
+                Some(distribution)
+            }
+            (distribution, other) => distribution.or(other),
+        };
+
+        for file in other.files {
+            self.add_file(file);
+        }
+    }
+
+    pub fn result(&self, time_unit: TimeUnit) -> result::RequestLogAnalyzerResult {
+        let mut stats = aggregated_stats::AggregatedStats::new();
+
+        for timing in &self.timings {
+            stats.add(*timing);
+        }
+
+        analysis_result(
+            &stats,
+            &self.error_rates,
+            self.distribution.clone().unwrap_or_default(),
+            time_unit,
+            self.files.clone(),
+        )
     }
+}
 
+fn analysis_result(
+    stats: &aggregated_stats::AggregatedStats,
+    error_rates: &aggregated_error_rates::AggregatedErrorRates,
+    distribution: distribution::Distribution,
+    time_unit: TimeUnit,
+    files: Vec<result::FileSummary>,
+) -> result::RequestLogAnalyzerResult {
     if stats.max().is_none() {
         return result::RequestLogAnalyzerResult {
             count: 0,
             timing: None,
             error: None,
+            distribution,
+            files,
         };
     }
 
     result::RequestLogAnalyzerResult {
         count: stats.count(),
         timing: Some(TimingResult {
-            max: stats.max().unwrap(),
-            min: stats.min().unwrap(),
-            avg: stats.average().unwrap() as usize,
-            median: stats.median().unwrap() as usize,
-            percentile90: stats.quantile(0.9).unwrap() as usize,
-            percentile99: stats.quantile(0.99).unwrap() as usize,
+            max: time_unit.convert_microseconds(stats.max().unwrap() as f64),
+            min: time_unit.convert_microseconds(stats.min().unwrap() as f64),
+            avg: time_unit.convert_microseconds(stats.average().unwrap()),
+            median: time_unit.convert_microseconds(stats.median().unwrap()),
+            percentile90: time_unit.convert_microseconds(stats.quantile(0.9).unwrap()),
+            percentile99: time_unit.convert_microseconds(stats.quantile(0.99).unwrap()),
             count: stats.count(),
         }),
         error: error_rates.result(),
+        distribution,
+        files,
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
     use crate::result;
     use crate::analyzer::aggregated_error_rates::HttpErrorState;
     use crate::analyzer::aggregated_error_rates::ErrorRatesResult;
     use crate::log_parser::log_events::HttpError;
     use super::*;
 
+    // Test values are given in milliseconds
     impl Timing for i64 {
-        fn num_milliseconds(&self) -> i64 {
-            self.clone()
+        fn num_microseconds(&self) -> i64 {
+            self * 1000
         }
     }
 
@@ -87,28 +245,48 @@ mod tests {
         }
     }
 
+    impl distribution::Category for i64 {
+        fn status(&self) -> Option<u16> {
+            Some(200)
+        }
+    }
+
     #[test]
     fn test_analyze_iterator() {
         let times: Vec<i64> = vec![1, 10, 100];
         let times_iterator = times.into_iter();
 
-        let result = analyze_iterator(times_iterator);
+        let buckets = [0.005, 0.05];
+        let result = analyze_iterator(times_iterator, TimeUnit::Milliseconds, Some(&buckets));
 
         let expected = result::RequestLogAnalyzerResult {
             count: 3,
             timing: Some(TimingResult {
-                max: 100,
-                min: 1,
-                avg: 37,
-                median: 10,
-                percentile90: 100,
-                percentile99: 100,
+                max: 100.0,
+                min: 1.0,
+                avg: 37.0,
+                median: 10.0,
+                percentile90: 100.0,
+                percentile99: 100.0,
                 count: 3,
             }),
             error: Some(ErrorRatesResult {
                 client_error_4xx: 0.0,
                 server_error_5xx: 0.0,
             }),
+            distribution: distribution::Distribution {
+                buckets: buckets.to_vec(),
+                series: vec![
+                    (vec![], distribution::Series {
+                        counts: vec![1, 1, 1],
+                        sum: 111000,
+                        statuses: vec![(Some(200), 3)].into_iter().collect(),
+                        exemplars: BTreeMap::new(),
+                    }),
+                ].into_iter()
+                    .collect(),
+            },
+            files: vec![],
         };
 
         assert_eq!(result, expected);
@@ -119,14 +297,88 @@ mod tests {
         let times: Vec<i64> = vec![];
         let times_iterator = times.into_iter();
 
-        let result = analyze_iterator(times_iterator);
+        let result = analyze_iterator(times_iterator, TimeUnit::Milliseconds, None);
 
         let expected = result::RequestLogAnalyzerResult {
             count: 0,
             timing: None,
             error: None,
+            distribution: distribution::Distribution::default(),
+            files: vec![],
         };
 
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_analyze_iterator_time_unit() {
+        let times: Vec<i64> = vec![1, 10, 100];
+
+        let result = analyze_iterator(times.clone().into_iter(), TimeUnit::Seconds, None);
+        let timing = result.timing.unwrap();
+        assert_eq!(timing.max, 0.1);
+        assert_eq!(timing.min, 0.001);
+
+        let result = analyze_iterator(times.into_iter(), TimeUnit::Microseconds, None);
+        let timing = result.timing.unwrap();
+        assert_eq!(timing.max, 100000.0);
+        assert_eq!(timing.min, 1000.0);
+    }
+
+    #[test]
+    fn test_partial_analysis_merge() {
+        let mut first = PartialAnalysis::with_distribution(&[0.005]);
+        let mut second = PartialAnalysis::with_distribution(&[0.005]);
+        let mut empty = PartialAnalysis::new();
+
+        first.add(&1i64);
+        second.add(&100i64);
+        second.add(&10i64);
+        empty.merge(PartialAnalysis::new());
+
+        first.merge(second);
+        first.merge(empty);
+
+        let times: Vec<i64> = vec![1, 10, 100];
+        let expected = analyze_iterator(times.into_iter(), TimeUnit::Milliseconds, Some(&[0.005]));
+
+        assert_eq!(first.result(TimeUnit::Milliseconds), expected);
+    }
+
+    #[test]
+    fn test_partial_analysis_merge_files() {
+        let summary = |filename: &str, lines, error: Option<&str>| {
+            result::FileSummary {
+                filename: String::from(filename),
+                lines,
+                error: error.map(String::from),
+            }
+        };
+
+        let mut first = PartialAnalysis::new();
+        let mut second = PartialAnalysis::new();
+
+        first.add_file(summary("one.log", 10, None));
+        second.add_file(summary("two.log", 5, None));
+        second.add_file(summary("one.log", 3, Some("one.log: read error")));
+
+        first.merge(second);
+
+        assert_eq!(
+            first.result(TimeUnit::Milliseconds).files,
+            vec![
+                summary("one.log", 13, Some("one.log: read error")),
+                summary("two.log", 5, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_partial_analysis_empty() {
+        let result = PartialAnalysis::new().result(TimeUnit::Milliseconds);
+
+        assert_eq!(result.count, 0);
+        assert_eq!(result.timing, None);
+        assert_eq!(result.error, None);
+    }
 }
@@ -1,9 +1,13 @@
 use clap::{Arg, App, crate_authors, crate_version, crate_description};
 use chrono::*;
+use crate::analyzer;
+use crate::config;
 use crate::filter;
+use crate::filter::expression::Expression;
+use crate::multi_file;
 use failure::{Error, err_msg};
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct RequestLogAnalyzerArgs {
     pub filenames: Vec<String>,
     pub conditions: filter::FilterConditions,
@@ -11,9 +15,62 @@ pub struct RequestLogAnalyzerArgs {
     pub graphite_port: Option<u16>,
     pub graphite_prefix: Option<String>,
     pub prometheus_listen: Option<String>,
+    pub prometheus_buckets: Vec<f64>,
+    pub prometheus_legacy_gauges: bool,
+    pub prometheus_labels: Vec<(String, String)>,
+    pub prometheus_pushgateway: Option<String>,
+    pub prometheus_job: String,
+    pub prometheus_grouping: Vec<(String, String)>,
+    /// The names and log files of the sources, which are analyzed on their
+    /// own
+    pub prometheus_sources: Vec<(String, Vec<String>)>,
+    pub healthz_max_age: std::time::Duration,
+    pub http_timeout: std::time::Duration,
+    pub exporter: config::ExporterConfig,
+    pub grouping: analyzer::grouping::Grouping,
     pub influxdb_write_url: Option<String>,
     pub influxdb_tags: Option<String>,
     pub quiet: bool,
+    pub time_unit: analyzer::TimeUnit,
+    pub jobs: usize,
+    pub follow: bool,
+    pub follow_interval: std::time::Duration,
+    pub state_file: Option<String>,
+    pub read_error_policy: multi_file::ErrorPolicy,
+}
+
+impl RequestLogAnalyzerArgs {
+    /// The args of each `--prometheus-source`, with its log files and its
+    /// name as the `source` label, or these args without sources.
+    pub fn sources(&self) -> Vec<RequestLogAnalyzerArgs> {
+        if self.prometheus_sources.is_empty() {
+            return vec![self.clone()];
+        }
+
+        self.prometheus_sources
+            .iter()
+            .map(|(name, filenames)| {
+                let mut args = self.clone();
+                args.filenames = filenames.clone();
+                args.grouping.source = Some(name.clone());
+                args
+            })
+            .collect()
+    }
+
+    pub fn needs_original_log_line(&self) -> bool {
+        self.conditions.needs_original_log_line() || self.grouping.needs_original_log_line()
+    }
+
+    /// The upper bounds of the buckets the requests are counted into for the
+    /// Prometheus renderers, if there are any.
+    pub fn distribution_buckets(&self) -> Option<&[f64]> {
+        if self.prometheus_listen.is_some() || self.prometheus_pushgateway.is_some() {
+            Some(&self.prometheus_buckets)
+        } else {
+            None
+        }
+    }
 }
 
 pub fn parse_args<T>(args: T) -> Result<RequestLogAnalyzerArgs, Error>
@@ -30,7 +87,10 @@ where
                 .value_name("FILES")
                 .multiple(true)
                 .required(false)
-                .help("Log files to analyze, defaults to stdin")
+                .help(
+                    "Log files, directories of request.log files or glob patterns like \
+                     'logs/request.log*' to analyze, defaults to stdin",
+                )
                 .takes_value(true),
         )
         .arg(
@@ -40,6 +100,34 @@ where
                 .help("Limit to the last n minutes")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("relative-to")
+                .value_name("ANCHOR")
+                .long("relative-to")
+                .help(
+                    "What -t counts back from: 'now' or 'log-end', the latest request in \
+                     the log",
+                )
+                .takes_value(true)
+                .default_value("now"),
+        )
+        .arg(
+            Arg::with_name("since")
+                .value_name("TIME")
+                .long("since")
+                .help(
+                    "Only include requests at or after this time, like '2024-03-05 14:00' \
+                     (local time) or '2024-03-05T14:00:00+01:00'",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("until")
+                .value_name("TIME")
+                .long("until")
+                .help("Only include requests before this time, formatted like --since")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("include_term")
                 .value_name("TERM")
@@ -58,6 +146,88 @@ where
                 .multiple(true)
                 .number_of_values(1),
         )
+        .arg(
+            Arg::with_name("filter")
+                .value_name("EXPRESSION")
+                .long("filter")
+                .help(
+                    "Only include requests matching this expression, like \
+                     'status >= 500 and (method = POST or path ~ \"^/bin/\")'",
+                )
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("min-duration")
+                .value_name("DURATION")
+                .long("min-duration")
+                .help("Only include requests that took at least this long, like '5s' or '250ms'")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-duration")
+                .value_name("DURATION")
+                .long("max-duration")
+                .help("Only include requests that took at most this long")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("status")
+                .value_name("STATUS")
+                .long("status")
+                .help("Only include responses with these statuses, like '5xx' or '404,410'")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("include_regex")
+                .value_name("[FIELD:]REGEX")
+                .long("include-regex")
+                .help(
+                    "Only include requests matching one of these regular expressions, on the \
+                     full lines or on a field: method, path, protocol, status, content-type",
+                )
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("exclude_regex")
+                .value_name("[FIELD:]REGEX")
+                .long("exclude-regex")
+                .help("Exclude requests matching one of these regular expressions")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("filter-file")
+                .value_name("FILE")
+                .long("filter-file")
+                .help("Apply the filters defined in this TOML file")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .value_name("NAME")
+                .long("profile")
+                .help("Apply the filters of this profile from the config file")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("config")
+                .value_name("FILE")
+                .long("config")
+                .help(
+                    "Config file with the profiles and the TLS and auth of the exporter, defaults \
+                     to $REQUEST_LOG_ANALYZER_CONFIG or ~/.config/request_log_analyzer/config.toml",
+                )
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("graphite-server")
                 .value_name("GRAPHITE_SERVER")
@@ -88,6 +258,108 @@ where
                 )
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("prometheus-buckets")
+                .value_name("SECONDS")
+                .long("prometheus-buckets")
+                .help("Upper bounds of the request_duration_seconds histogram buckets")
+                .takes_value(true)
+                .default_value("0.005,0.01,0.025,0.05,0.1,0.25,0.5,1,2.5,5,10"),
+        )
+        .arg(Arg::with_name("prometheus-legacy-gauges")
+            .long("prometheus-legacy-gauges")
+            .help(
+                "Also expose the request_time_* and request_error_* gauges of earlier versions",
+            ))
+        .arg(
+            Arg::with_name("prometheus-labels")
+                .value_name("LABELS")
+                .long("prometheus-labels")
+                .help("Constant labels of all metrics, e.g. 'instance=publish1,env=prod'")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("prometheus-group-by")
+                .value_name("LABELS")
+                .long("prometheus-group-by")
+                .help(
+                    "Label the histogram and counters by status_class, method and/or \
+                     path_group, e.g. 'method,path_group'",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("prometheus-path-group")
+                .value_name("NAME=REGEX")
+                .long("prometheus-path-group")
+                .help(
+                    "Requests with a path matching REGEX have the path_group NAME, those \
+                     matching none have 'other'",
+                )
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("prometheus-pushgateway")
+                .value_name("URL")
+                .long("prometheus-pushgateway")
+                .help(
+                    "Push the Prometheus metrics to a Pushgateway, e.g. 'http://localhost:9091'",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("prometheus-source")
+                .value_name("NAME=FILES")
+                .long("prometheus-source")
+                .help(
+                    "Analyze these log files on their own for the exporter, with the label \
+                     source=NAME, e.g. 'publish=/opt/publish/logs/request.log'",
+                )
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("prometheus-job")
+                .value_name("JOB")
+                .long("prometheus-job")
+                .help("Job name of the metrics pushed to the Pushgateway")
+                .takes_value(true)
+                .default_value("request_log_analyzer"),
+        )
+        .arg(
+            Arg::with_name("prometheus-grouping")
+                .value_name("LABELS")
+                .long("prometheus-grouping")
+                .help(
+                    "Grouping labels of the metrics pushed to the Pushgateway besides the job, \
+                     e.g. 'instance=publish1'",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("healthz-max-age")
+                .value_name("SECONDS")
+                .long("healthz-max-age")
+                .help(
+                    "The exporter's /healthz fails if the log file was not modified for this long",
+                )
+                .takes_value(true)
+                .default_value("300"),
+        )
+        .arg(
+            Arg::with_name("http-timeout")
+                .value_name("SECONDS")
+                .long("http-timeout")
+                .help(
+                    "Timeout for answering and sending HTTP requests, and for finishing open \
+                     requests when the exporter is stopped",
+                )
+                .takes_value(true)
+                .default_value("30"),
+        )
         .arg(
             Arg::with_name("influxdb-write-url")
                 .value_name("INFLUXDB_WRITE_URL")
@@ -108,25 +380,147 @@ where
                 )
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("time-unit")
+                .value_name("UNIT")
+                .long("time-unit")
+                .help("Unit for reported times: 'us', 'ms' or 's'")
+                .takes_value(true)
+                .default_value("ms"),
+        )
+        .arg(
+            Arg::with_name("jobs")
+                .value_name("JOBS")
+                .short("j")
+                .long("jobs")
+                .help(
+                    "Analyze multiple files in parallel on this many threads, \
+                   matching requests and responses within each file",
+                )
+                .takes_value(true)
+                .default_value("1"),
+        )
+        .arg(Arg::with_name("follow").short("f").long("follow").help(
+            "Keep reading lines appended to the log file, surviving log rotation, \
+             and output results for every interval",
+        ))
+        .arg(
+            Arg::with_name("follow-interval")
+                .value_name("SECONDS")
+                .long("follow-interval")
+                .help("Interval for outputting results in --follow mode")
+                .takes_value(true)
+                .default_value("60"),
+        )
+        .arg(
+            Arg::with_name("state-file")
+                .value_name("FILE")
+                .long("state-file")
+                .help(
+                    "Only analyze what was appended to the log files since the previous \
+                     run with the same state file",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("on-read-error")
+                .value_name("POLICY")
+                .long("on-read-error")
+                .help(
+                    "What to do when an input file can not be read: 'fail' or 'skip' it \
+                     with a warning",
+                )
+                .takes_value(true)
+                .default_value("fail"),
+        )
         .arg(Arg::with_name("quiet").short("q").long("quiet").help(
             "Don't output results to stdout",
         ))
         .get_matches_from(args);
 
-    let filenames: Vec<String> = match app.values_of("filenames") {
-        Some(values) => values.map(|x| x.to_string()).collect(),
-        None => vec![String::from("-")],
+    let mut prometheus_sources: Vec<(String, Vec<String>)> = vec![];
+
+    if let Some(values) = app.values_of("prometheus-source") {
+        if app.is_present("filenames") {
+            return Err(err_msg(
+                "--prometheus-source can not be combined with log files as arguments",
+            ));
+        }
+
+        for value in values {
+            let (name, input) = match value.split_once('=') {
+                Some((name, input)) if !name.is_empty() && !input.is_empty() && input != "-" => {
+                    (name, input)
+                }
+                _ => {
+                    return Err(err_msg(format!(
+                        "--prometheus-source must be like 'publish=/opt/publish/logs/request.log' \
+                         ({})",
+                        value
+                    )))
+                }
+            };
+
+            let files = multi_file::discover::expand_inputs(&[String::from(input)])?;
+
+            match prometheus_sources.iter_mut().find(|(other, _)| other == name) {
+                Some((_, filenames)) => filenames.extend(files),
+                None => prometheus_sources.push((String::from(name), files)),
+            }
+        }
+    }
+
+    // With sources, these are the files of all sources
+    let filenames: Vec<String> = if prometheus_sources.is_empty() {
+        let filenames: Vec<String> = match app.values_of("filenames") {
+            Some(values) => values.map(|x| x.to_string()).collect(),
+            None => vec![String::from("-")],
+        };
+
+        multi_file::discover::expand_inputs(&filenames)?
+    } else {
+        prometheus_sources.iter().flat_map(|(_, filenames)| filenames.clone()).collect()
     };
 
+    let mut filters = vec![];
+
+    for &(arg, name) in &[
+        ("include_term", "include"),
+        ("exclude_term", "exclude"),
+        ("include_regex", "include-regex"),
+        ("exclude_regex", "exclude-regex"),
+        ("min-duration", "min-duration"),
+        ("max-duration", "max-duration"),
+        ("status", "status"),
+        ("filter", "filter"),
+    ]
+    {
+        if let Some(values) = app.values_of(arg) {
+            let values: Vec<&str> = values.collect();
+            let label = format!("--{}", name);
+            filters.extend(filter::options::expression(name, &label, &values)?);
+        }
+    }
+
+    if let Some(paths) = app.values_of("filter-file") {
+        for path in paths {
+            filters.extend(config::load_filter_file(path)?);
+        }
+    }
+
+    if let Some(names) = app.values_of("profile") {
+        let config = match app.value_of("config") {
+            Some(path) => config::Config::load(path)?,
+            None => config::Config::load_default()?,
+        };
+
+        for name in names {
+            filters.extend(config.profile(name)?);
+        }
+    }
+
     let conditions = filter::FilterConditions {
-        include_terms: match app.values_of("include_term") {
-            Some(values) => Some(values.map(|v| v.to_string()).collect()),
-            None => None,
-        },
-        exclude_terms: match app.values_of("exclude_term") {
-            Some(values) => Some(values.map(|v| v.to_string()).collect()),
-            None => None,
-        },
+        expression: Expression::all(filters),
         latest_time: match app.value_of("time_filter_minutes") {
             Some(minutes) => {
                 match minutes.parse() {
@@ -136,8 +530,31 @@ where
             }
             None => None,
         },
+        since: match app.value_of("since") {
+            Some(value) => Some(parse_time("--since", value)?),
+            None => None,
+        },
+        until: match app.value_of("until") {
+            Some(value) => Some(parse_time("--until", value)?),
+            None => None,
+        },
+        relative_to: match app.value_of("relative-to") {
+            Some("now") | None => filter::TimeAnchor::Now,
+            Some("log-end") => filter::TimeAnchor::LogEnd,
+            Some(other) => {
+                return Err(err_msg(
+                    format!("--relative-to must be one of now, log-end ({})", other),
+                ))
+            }
+        },
     };
 
+    if let (Some(since), Some(until)) = (conditions.since, conditions.until) {
+        if until <= since {
+            return Err(err_msg("--until must be after --since"));
+        }
+    }
+
     let graphite_server = match app.value_of("graphite-server") {
         Some(value) => Some(String::from(value)),
         None => None,
@@ -167,6 +584,122 @@ where
         None => None,
     };
 
+    let prometheus_buckets = match app.value_of("prometheus-buckets") {
+        Some(value) => parse_buckets(value)?,
+        None => prometheus::DEFAULT_BUCKETS.to_vec(),
+    };
+
+    let prometheus_legacy_gauges = app.is_present("prometheus-legacy-gauges");
+
+    if !prometheus_sources.is_empty() {
+        if prometheus_listen.is_none() {
+            return Err(err_msg("--prometheus-source needs --prometheus-listen"));
+        }
+
+        // The gauges have no labels to tell the sources apart
+        if prometheus_legacy_gauges {
+            return Err(err_msg(
+                "--prometheus-source can not be combined with --prometheus-legacy-gauges",
+            ));
+        }
+    }
+
+    let mut grouping = analyzer::grouping::Grouping::default();
+
+    if let Some(value) = app.value_of("prometheus-group-by") {
+        grouping.group_by = analyzer::grouping::GroupBy::parse_list(value).map_err(|name| {
+            err_msg(format!(
+                "--prometheus-group-by must be some of status_class, method, path_group ({})",
+                name
+            ))
+        })?;
+    }
+
+    if let Some(values) = app.values_of("prometheus-path-group") {
+        for value in values {
+            match analyzer::grouping::PathGroup::parse(value) {
+                Some(Ok(path_group)) => grouping.path_groups.push(path_group),
+                Some(Err(err)) => {
+                    return Err(err_msg(
+                        format!("--prometheus-path-group {} is invalid ({})", value, err),
+                    ))
+                }
+                None => {
+                    return Err(err_msg(format!(
+                        "--prometheus-path-group must be like 'pages=^/content/.*\\.html$' ({})",
+                        value
+                    )))
+                }
+            }
+        }
+    }
+
+    let prometheus_labels = match app.value_of("prometheus-labels") {
+        Some(value) => {
+            let mut reserved = vec!["le", "status"];
+            reserved.extend(grouping.label_names());
+
+            if !prometheus_sources.is_empty() {
+                reserved.push("source");
+            }
+
+            parse_labels("--prometheus-labels", value, &reserved)?
+        }
+        None => vec![],
+    };
+
+    let prometheus_pushgateway = app.value_of("prometheus-pushgateway").map(|value| {
+        String::from(value.trim_end_matches('/'))
+    });
+
+    let prometheus_job = match app.value_of("prometheus-job") {
+        Some(value) if !value.is_empty() => String::from(value),
+        _ => return Err(err_msg("--prometheus-job must not be empty")),
+    };
+
+    let prometheus_grouping = match app.value_of("prometheus-grouping") {
+        Some(value) => parse_labels("--prometheus-grouping", value, &["job"])?,
+        None => vec![],
+    };
+
+    let healthz_max_age = match app.value_of("healthz-max-age") {
+        Some(value) => {
+            match value.parse() {
+                Ok(value) if value > 0 => std::time::Duration::from_secs(value),
+                Ok(_) => return Err(err_msg("--healthz-max-age must be at least 1")),
+                Err(err) => {
+                    return Err(err_msg(
+                        format!("--healthz-max-age must be numeric ({})", err),
+                    ))
+                }
+            }
+        }
+        None => std::time::Duration::from_secs(300),
+    };
+
+    let http_timeout = match app.value_of("http-timeout") {
+        Some(value) => {
+            match value.parse() {
+                Ok(value) if value > 0 => std::time::Duration::from_secs(value),
+                Ok(_) => return Err(err_msg("--http-timeout must be at least 1")),
+                Err(err) => {
+                    return Err(err_msg(
+                        format!("--http-timeout must be numeric ({})", err),
+                    ))
+                }
+            }
+        }
+        None => std::time::Duration::from_secs(30),
+    };
+
+    // The secrets of the exporter are not taken from the command line
+    let exporter = if prometheus_listen.is_some() {
+        let config = config::Config::load_optional(app.value_of("config"))?;
+        config::ExporterConfig::load(config.as_ref(), &|name| std::env::var(name).ok())?
+    } else {
+        config::ExporterConfig::default()
+    };
+
     let influxdb_write_url = match app.value_of("influxdb-write-url") {
         Some(value) => Some(String::from(value)),
         None => None,
@@ -179,6 +712,80 @@ where
 
     let quiet = app.is_present("quiet");
 
+    let time_unit = match app.value_of("time-unit") {
+        Some("us") | Some("µs") => analyzer::TimeUnit::Microseconds,
+        Some("ms") | None => analyzer::TimeUnit::Milliseconds,
+        Some("s") => analyzer::TimeUnit::Seconds,
+        Some(other) => {
+            return Err(err_msg(
+                format!("--time-unit must be one of us, ms, s ({})", other),
+            ))
+        }
+    };
+
+    let jobs: usize = match app.value_of("jobs") {
+        Some(value) => {
+            match value.parse() {
+                Ok(value) if value > 0 => value,
+                Ok(_) => return Err(err_msg("--jobs must be at least 1")),
+                Err(err) => return Err(err_msg(format!("--jobs must be numeric ({})", err))),
+            }
+        }
+        None => 1,
+    };
+
+    let follow = app.is_present("follow");
+
+    if follow && prometheus_sources.is_empty() && (filenames.len() != 1 || filenames[0] == "-") {
+        return Err(err_msg("--follow needs exactly one log file"));
+    }
+
+    if follow && prometheus_sources.iter().any(|(_, filenames)| filenames.len() != 1) {
+        return Err(err_msg("--follow needs exactly one log file for each --prometheus-source"));
+    }
+
+    let follow_interval = match app.value_of("follow-interval") {
+        Some(value) => {
+            match value.parse() {
+                Ok(value) if value > 0 => std::time::Duration::from_secs(value),
+                Ok(_) => return Err(err_msg("--follow-interval must be at least 1")),
+                Err(err) => {
+                    return Err(err_msg(
+                        format!("--follow-interval must be numeric ({})", err),
+                    ))
+                }
+            }
+        }
+        None => std::time::Duration::from_secs(60),
+    };
+
+    let state_file = app.value_of("state-file").map(String::from);
+
+    if state_file.is_some() {
+        if follow {
+            return Err(err_msg("--state-file can not be combined with --follow"));
+        }
+
+        if filenames.iter().any(|filename| {
+            filename == "-" || multi_file::compression::is_compressed(filename).unwrap_or(false)
+        })
+        {
+            return Err(err_msg(
+                "--state-file needs uncompressed log files, not stdin",
+            ));
+        }
+    }
+
+    let read_error_policy = match app.value_of("on-read-error") {
+        Some("fail") | None => multi_file::ErrorPolicy::Fail,
+        Some("skip") => multi_file::ErrorPolicy::Skip,
+        Some(other) => {
+            return Err(err_msg(
+                format!("--on-read-error must be one of fail, skip ({})", other),
+            ))
+        }
+    };
+
     Ok(RequestLogAnalyzerArgs {
         filenames,
         conditions,
@@ -186,15 +793,127 @@ where
         graphite_port,
         graphite_prefix,
         prometheus_listen,
+        prometheus_buckets,
+        prometheus_legacy_gauges,
+        prometheus_labels,
+        prometheus_pushgateway,
+        prometheus_job,
+        prometheus_grouping,
+        prometheus_sources,
+        healthz_max_age,
+        http_timeout,
+        exporter,
+        grouping,
         influxdb_write_url,
         influxdb_tags,
         quiet,
+        time_unit,
+        jobs,
+        follow,
+        follow_interval,
+        state_file,
+        read_error_policy,
     })
 }
 
+/// Parse the bucket bounds of a histogram, which have to be increasing.
+fn parse_buckets(value: &str) -> Result<Vec<f64>, Error> {
+    let buckets: Vec<f64> = value
+        .split(',')
+        .map(|bound| bound.trim().parse().ok().filter(|bound: &f64| bound.is_finite()))
+        .collect::<Option<_>>()
+        .filter(|buckets: &Vec<f64>| buckets.windows(2).all(|pair| pair[0] < pair[1]))
+        .ok_or_else(|| {
+            err_msg(format!(
+                "--prometheus-buckets must be increasing numbers of seconds like 0.1,0.5,1 ({})",
+                value
+            ))
+        })?;
+
+    Ok(buckets)
+}
+
+/// Parse labels like `instance=publish1,env=prod` for `option`, which may not
+/// use the `reserved` label names.
+fn parse_labels(
+    option: &str,
+    value: &str,
+    reserved: &[&str],
+) -> Result<Vec<(String, String)>, Error> {
+    let mut labels = vec![];
+
+    for label in value.split(',') {
+        let (name, label_value) = match label.split_once('=') {
+            Some((name, label_value)) if is_label_name(name) => (name, label_value),
+            _ => {
+                return Err(err_msg(format!(
+                    "{} must be like 'instance=publish1,env=prod' ({})",
+                    option,
+                    label
+                )))
+            }
+        };
+
+        if reserved.contains(&name) || labels.iter().any(|(other, _)| other == name) {
+            return Err(err_msg(
+                format!("{} can not use the label {} twice", option, name),
+            ));
+        }
+
+        labels.push((String::from(name), String::from(label_value)));
+    }
+
+    Ok(labels)
+}
+
+fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            !name.starts_with("__") && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Parse a time given on the command line. Times without an offset are in
+/// the local timezone.
+fn parse_time(option: &str, value: &str) -> Result<DateTime<FixedOffset>, Error> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time);
+    }
+
+    for format in &["%Y-%m-%d %H:%M:%S %z", "%Y-%m-%d %H:%M %z", "%d/%b/%Y:%H:%M:%S %z"] {
+        if let Ok(time) = DateTime::parse_from_str(value, format) {
+            return Ok(time);
+        }
+    }
+
+    let naive_time = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        });
+
+    match naive_time.and_then(|time| Local.from_local_datetime(&time).earliest()) {
+        Some(time) => Ok(time.fixed_offset()),
+        None => Err(err_msg(format!(
+            "{} must be a time like '2024-03-05 14:00' or '2024-03-05T14:00:00+01:00' ({})",
+            option,
+            value
+        ))),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::filter;
+    use crate::filter::expression::Comparison;
+    use crate::filter::field_regex::FieldRegex;
     use chrono::*;
     use super::*;
 
@@ -205,17 +924,36 @@ mod tests {
         let expected = RequestLogAnalyzerArgs {
             filenames: vec![String::from("-")],
             conditions: filter::FilterConditions {
-                include_terms: None,
-                exclude_terms: None,
+                expression: None,
                 latest_time: None,
+                since: None,
+                until: None,
+                relative_to: filter::TimeAnchor::Now,
             },
             graphite_server: None,
             graphite_port: Some(2003),
             graphite_prefix: None,
             prometheus_listen: None,
+            prometheus_buckets: prometheus::DEFAULT_BUCKETS.to_vec(),
+            prometheus_legacy_gauges: false,
+            prometheus_labels: vec![],
+            prometheus_pushgateway: None,
+            prometheus_job: String::from("request_log_analyzer"),
+            prometheus_grouping: vec![],
+            prometheus_sources: vec![],
+            healthz_max_age: std::time::Duration::from_secs(300),
+            http_timeout: std::time::Duration::from_secs(30),
+            exporter: config::ExporterConfig::default(),
+            grouping: analyzer::grouping::Grouping::default(),
             influxdb_write_url: None,
             influxdb_tags: None,
             quiet: false,
+            time_unit: analyzer::TimeUnit::Milliseconds,
+            jobs: 1,
+            follow: false,
+            follow_interval: std::time::Duration::from_secs(60),
+            state_file: None,
+            read_error_policy: multi_file::ErrorPolicy::Fail,
         };
 
         let result = parse_args(raw_args).unwrap();
@@ -231,8 +969,24 @@ mod tests {
             String::from("one"),
             String::from("--exclude"),
             String::from("this other"),
+            String::from("--include-regex"),
+            String::from(r"path:^/content/.*\.html$"),
+            String::from("--exclude-regex"),
+            String::from("status:^404$"),
+            String::from("--exclude-regex"),
+            String::from("debug=true"),
+            String::from("--filter"),
+            String::from("status >= 500 or duration > 1s"),
+            String::from("--min-duration"),
+            String::from("250ms"),
+            String::from("--max-duration"),
+            String::from("5s"),
+            String::from("--status"),
+            String::from("5xx,404"),
             String::from("-t"),
             String::from("10"),
+            String::from("--relative-to"),
+            String::from("log-end"),
             String::from("my-logfile.log"),
             String::from("--graphite-server"),
             String::from("localhost"),
@@ -242,27 +996,106 @@ mod tests {
             String::from("prod"),
             String::from("--prometheus-listen"),
             String::from("0.0.0.0:9898"),
+            String::from("--prometheus-buckets"),
+            String::from("0.1, 1,10"),
+            String::from("--prometheus-legacy-gauges"),
+            String::from("--prometheus-labels"),
+            String::from("instance=publish1,env=prod"),
+            String::from("--prometheus-group-by"),
+            String::from("status_class,path_group"),
+            String::from("--prometheus-path-group"),
+            String::from("pages=^/content/"),
+            String::from("--prometheus-pushgateway"),
+            String::from("http://localhost:9091/"),
+            String::from("--prometheus-job"),
+            String::from("nightly"),
+            String::from("--prometheus-grouping"),
+            String::from("instance=publish1"),
+            String::from("--healthz-max-age"),
+            String::from("120"),
+            String::from("--http-timeout"),
+            String::from("10"),
             String::from("--influxdb-write-url"),
             String::from("https://example.com/write?db=metrics_prod"),
             String::from("--influxdb-tags"),
             String::from("host=prod3,type=worker"),
             String::from("--quiet"),
+            String::from("--time-unit"),
+            String::from("s"),
+            String::from("--jobs"),
+            String::from("4"),
+            String::from("--follow"),
+            String::from("--follow-interval"),
+            String::from("30"),
+            String::from("--on-read-error"),
+            String::from("skip"),
+            String::from("--since"),
+            String::from("2016-04-08 09:00:00 +0200"),
+            String::from("--until"),
+            String::from("2016-04-08T10:30:00+02:00"),
         ];
 
         let expected = RequestLogAnalyzerArgs {
             filenames: vec![String::from("my-logfile.log")],
             conditions: filter::FilterConditions {
-                include_terms: Some(vec![String::from("one")]),
-                exclude_terms: Some(vec![String::from("this other")]),
+                expression: Expression::all(vec![
+                    Expression::line_contains("one"),
+                    Expression::negate(Expression::line_contains("this other")),
+                    Expression::Matches(FieldRegex::parse(r"path:^/content/.*\.html$").unwrap()),
+                    Expression::negate(Expression::Or(
+                        Box::new(Expression::Matches(FieldRegex::parse("status:^404$").unwrap())),
+                        Box::new(Expression::Matches(FieldRegex::parse("debug=true").unwrap())),
+                    )),
+                    Expression::Duration(Comparison::GreaterOrEqual, Duration::milliseconds(250)),
+                    Expression::Duration(Comparison::LessOrEqual, Duration::seconds(5)),
+                    Expression::status_list("5xx,404").unwrap(),
+                    Expression::parse("status >= 500 or duration > 1s").unwrap(),
+                ]),
                 latest_time: Some(Duration::minutes(10)),
+                since: Some(
+                    DateTime::parse_from_rfc3339("2016-04-08T09:00:00+02:00").unwrap(),
+                ),
+                until: Some(
+                    DateTime::parse_from_rfc3339("2016-04-08T10:30:00+02:00").unwrap(),
+                ),
+                relative_to: filter::TimeAnchor::LogEnd,
             },
             graphite_server: Some(String::from("localhost")),
             graphite_port: Some(4000),
             graphite_prefix: Some(String::from("prod")),
             prometheus_listen: Some(String::from("0.0.0.0:9898")),
+            prometheus_buckets: vec![0.1, 1.0, 10.0],
+            prometheus_legacy_gauges: true,
+            prometheus_labels: vec![
+                (String::from("instance"), String::from("publish1")),
+                (String::from("env"), String::from("prod")),
+            ],
+            prometheus_pushgateway: Some(String::from("http://localhost:9091")),
+            prometheus_job: String::from("nightly"),
+            prometheus_grouping: vec![(String::from("instance"), String::from("publish1"))],
+            prometheus_sources: vec![],
+            healthz_max_age: std::time::Duration::from_secs(120),
+            http_timeout: std::time::Duration::from_secs(10),
+            exporter: config::ExporterConfig::default(),
+            grouping: analyzer::grouping::Grouping {
+                group_by: vec![
+                    analyzer::grouping::GroupBy::StatusClass,
+                    analyzer::grouping::GroupBy::PathGroup,
+                ],
+                path_groups: vec![
+                    analyzer::grouping::PathGroup::parse("pages=^/content/").unwrap().unwrap(),
+                ],
+                source: None,
+            },
             influxdb_write_url: Some(String::from("https://example.com/write?db=metrics_prod")),
             influxdb_tags: Some(String::from("host=prod3,type=worker")),
             quiet: true,
+            time_unit: analyzer::TimeUnit::Seconds,
+            jobs: 4,
+            follow: true,
+            follow_interval: std::time::Duration::from_secs(30),
+            state_file: None,
+            read_error_policy: multi_file::ErrorPolicy::Skip,
         };
 
         let result = parse_args(raw_args).unwrap();
@@ -288,17 +1121,45 @@ mod tests {
         let expected = RequestLogAnalyzerArgs {
             filenames: vec![String::from("my-logfile.log")],
             conditions: filter::FilterConditions {
-                include_terms: Some(vec![String::from("one"), String::from("two")]),
-                exclude_terms: Some(vec![String::from("this other"), String::from("more")]),
+                expression: Some(Expression::And(
+                    Box::new(Expression::Or(
+                        Box::new(Expression::line_contains("one")),
+                        Box::new(Expression::line_contains("two")),
+                    )),
+                    Box::new(Expression::negate(Expression::Or(
+                        Box::new(Expression::line_contains("this other")),
+                        Box::new(Expression::line_contains("more")),
+                    ))),
+                )),
                 latest_time: None,
+                since: None,
+                until: None,
+                relative_to: filter::TimeAnchor::Now,
             },
             graphite_server: None,
             graphite_port: Some(2003),
             graphite_prefix: None,
             prometheus_listen: None,
+            prometheus_buckets: prometheus::DEFAULT_BUCKETS.to_vec(),
+            prometheus_legacy_gauges: false,
+            prometheus_labels: vec![],
+            prometheus_pushgateway: None,
+            prometheus_job: String::from("request_log_analyzer"),
+            prometheus_grouping: vec![],
+            prometheus_sources: vec![],
+            healthz_max_age: std::time::Duration::from_secs(300),
+            http_timeout: std::time::Duration::from_secs(30),
+            exporter: config::ExporterConfig::default(),
+            grouping: analyzer::grouping::Grouping::default(),
             influxdb_write_url: None,
             influxdb_tags: None,
             quiet: false,
+            time_unit: analyzer::TimeUnit::Milliseconds,
+            jobs: 1,
+            follow: false,
+            follow_interval: std::time::Duration::from_secs(60),
+            state_file: None,
+            read_error_policy: multi_file::ErrorPolicy::Fail,
         };
 
         let result = parse_args(raw_args).unwrap();
@@ -339,6 +1200,475 @@ mod tests {
 
         assert!(error_message.contains("-t must be numeric"));
     }
+
+    #[test]
+    fn test_invalid_time_unit() {
+        let raw_args = vec![
+            String::from("request_log_analyzer"),
+            String::from("--time-unit"),
+            String::from("hours"),
+        ];
+
+        let error_message = match parse_args(raw_args) {
+            Err(fail) => format!("{}", fail),
+            Ok(_) => unreachable!(),
+        };
+
+        assert!(error_message.contains("--time-unit must be one of"));
+    }
+
+    #[test]
+    fn test_invalid_jobs() {
+        let raw_args = vec![
+            String::from("request_log_analyzer"),
+            String::from("--jobs"),
+            String::from("0"),
+        ];
+
+        let error_message = match parse_args(raw_args) {
+            Err(fail) => format!("{}", fail),
+            Ok(_) => unreachable!(),
+        };
+
+        assert!(error_message.contains("--jobs must be at least 1"));
+    }
+
+    #[test]
+    fn test_invalid_read_error_policy() {
+        let raw_args = vec![
+            String::from("request_log_analyzer"),
+            String::from("--on-read-error"),
+            String::from("ignore"),
+        ];
+
+        let error_message = match parse_args(raw_args) {
+            Err(err) => format!("{}", err),
+            Ok(_) => unreachable!(),
+        };
+
+        assert!(error_message.contains("--on-read-error must be one of fail, skip"));
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let expected = DateTime::parse_from_rfc3339("2024-03-05T14:00:00+01:00").unwrap();
+
+        for value in &[
+            "2024-03-05T14:00:00+01:00",
+            "2024-03-05 14:00:00 +0100",
+            "2024-03-05 14:00 +0100",
+            "05/Mar/2024:14:00:00 +0100",
+        ]
+        {
+            assert_eq!(parse_time("--since", value).unwrap(), expected, "{}", value);
+        }
+
+        let local = Local.with_ymd_and_hms(2024, 3, 5, 14, 0, 0).unwrap();
+        assert_eq!(parse_time("--since", "2024-03-05 14:00").unwrap(), local);
+        assert_eq!(parse_time("--since", "2024-03-05T14:00:00").unwrap(), local);
+        assert_eq!(
+            parse_time("--since", "2024-03-05").unwrap(),
+            Local.with_ymd_and_hms(2024, 3, 5, 0, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invalid_since() {
+        let raw_args = vec![
+            String::from("request_log_analyzer"),
+            String::from("--since"),
+            String::from("yesterday"),
+        ];
+
+        let error_message = match parse_args(raw_args) {
+            Err(err) => format!("{}", err),
+            Ok(_) => unreachable!(),
+        };
+
+        assert!(error_message.contains("--since must be a time like"));
+        assert!(error_message.contains("(yesterday)"));
+    }
+
+    #[test]
+    fn test_until_before_since() {
+        let raw_args = vec![
+            String::from("request_log_analyzer"),
+            String::from("--since"),
+            String::from("2024-03-05T14:00:00+01:00"),
+            String::from("--until"),
+            String::from("2024-03-05T13:00:00Z"),
+        ];
+
+        let error_message = match parse_args(raw_args) {
+            Err(err) => format!("{}", err),
+            Ok(_) => unreachable!(),
+        };
+
+        assert!(error_message.contains("--until must be after --since"));
+    }
+
+    #[test]
+    fn test_invalid_relative_to() {
+        let raw_args = vec![
+            String::from("request_log_analyzer"),
+            String::from("--relative-to"),
+            String::from("start"),
+        ];
+
+        let error_message = match parse_args(raw_args) {
+            Err(err) => format!("{}", err),
+            Ok(_) => unreachable!(),
+        };
+
+        assert!(error_message.contains("--relative-to must be one of now, log-end (start)"));
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        let raw_args = vec![
+            String::from("request_log_analyzer"),
+            String::from("--exclude-regex"),
+            String::from("path:[a-"),
+        ];
+
+        let error_message = match parse_args(raw_args) {
+            Err(err) => format!("{}", err),
+            Ok(_) => unreachable!(),
+        };
+
+        assert!(error_message.contains("--exclude-regex path:[a- is invalid"));
+    }
+
+    #[test]
+    fn test_invalid_filter() {
+        let raw_args = vec![
+            String::from("request_log_analyzer"),
+            String::from("--filter"),
+            String::from("status >= 500 and"),
+        ];
+
+        let error_message = match parse_args(raw_args) {
+            Err(err) => format!("{}", err),
+            Ok(_) => unreachable!(),
+        };
+
+        assert!(error_message.contains("Invalid filter at character 18: expected a field"));
+    }
+
+    #[test]
+    fn test_parse_args_filter_file_and_profile() {
+        let raw_args = vec![
+            String::from("request_log_analyzer"),
+            String::from("--status"),
+            String::from("200"),
+            String::from("--filter-file"),
+            String::from("src/test/filters.toml"),
+            String::from("--config"),
+            String::from("src/test/config.toml"),
+            String::from("--profile"),
+            String::from("errors"),
+        ];
+
+        let mut expected = vec![Expression::status_list("200").unwrap()];
+        expected.extend(config::load_filter_file("src/test/filters.toml").unwrap());
+        expected.extend(
+            config::Config::load("src/test/config.toml")
+                .unwrap()
+                .profile("errors")
+                .unwrap(),
+        );
+
+        assert_eq!(
+            parse_args(raw_args).unwrap().conditions.expression,
+            Expression::all(expected)
+        );
+    }
+
+    #[test]
+    fn test_parse_args_exporter() {
+        let raw_args = |listen: bool| {
+            let mut raw_args = vec![
+                String::from("request_log_analyzer"),
+                String::from("--config"),
+                String::from("src/test/config.toml"),
+            ];
+
+            if listen {
+                raw_args.push(String::from("--prometheus-listen"));
+                raw_args.push(String::from("localhost:9898"));
+            }
+
+            raw_args
+        };
+
+        assert_eq!(
+            parse_args(raw_args(true)).unwrap().exporter.tls,
+            Some((String::from("cert.pem"), String::from("key.pem")))
+        );
+        assert_eq!(
+            parse_args(raw_args(false)).unwrap().exporter,
+            config::ExporterConfig::default()
+        );
+    }
+
+    #[test]
+    fn test_invalid_profile() {
+        let raw_args = vec![
+            String::from("request_log_analyzer"),
+            String::from("--config"),
+            String::from("src/test/config.toml"),
+            String::from("--profile"),
+            String::from("publish"),
+        ];
+
+        let error_message = match parse_args(raw_args) {
+            Err(err) => format!("{}", err),
+            Ok(_) => unreachable!(),
+        };
+
+        assert!(error_message.contains("there is no profile publish (profiles: errors,"));
+    }
+
+    #[test]
+    fn test_invalid_prometheus_buckets() {
+        for value in &["0.1,1,0.5", "0.1,,1", "fast"] {
+            let raw_args = vec![
+                String::from("request_log_analyzer"),
+                String::from("--prometheus-buckets"),
+                String::from(*value),
+            ];
+
+            let error_message = match parse_args(raw_args) {
+                Err(err) => format!("{}", err),
+                Ok(_) => unreachable!(),
+            };
+
+            assert!(error_message.contains("--prometheus-buckets must be increasing numbers"));
+        }
+    }
+
+    #[test]
+    fn test_invalid_prometheus_labels() {
+        for &(option, value, expected) in &[
+            ("--prometheus-labels", "env:prod", "--prometheus-labels must be like"),
+            ("--prometheus-labels", "1st=a", "--prometheus-labels must be like"),
+            ("--prometheus-labels", "env=prod,env=test", "can not use the label env twice"),
+            ("--prometheus-labels", "status=x", "can not use the label status twice"),
+            ("--prometheus-group-by", "method,host", "--prometheus-group-by must be some of"),
+            ("--prometheus-path-group", "^/content/", "--prometheus-path-group must be like"),
+            ("--prometheus-path-group", "pages=(", "--prometheus-path-group pages=( is invalid"),
+            ("--prometheus-grouping", "instance", "--prometheus-grouping must be like"),
+            ("--prometheus-grouping", "job=nightly", "can not use the label job twice"),
+            ("--prometheus-job", "", "--prometheus-job must not be empty"),
+        ]
+        {
+            let raw_args = vec![
+                String::from("request_log_analyzer"),
+                String::from(option),
+                String::from(value),
+            ];
+
+            let error_message = match parse_args(raw_args) {
+                Err(err) => format!("{}", err),
+                Ok(_) => unreachable!(),
+            };
+
+            assert!(error_message.contains(expected), "{}", error_message);
+        }
+    }
+
+    #[test]
+    fn test_parse_args_prometheus_sources() {
+        let raw_args = vec![
+            String::from("request_log_analyzer"),
+            String::from("--prometheus-listen"),
+            String::from("localhost:9898"),
+            String::from("--prometheus-group-by"),
+            String::from("method"),
+            String::from("--prometheus-source"),
+            String::from("author=src/test/simple-1.log"),
+            String::from("--prometheus-source"),
+            String::from("publish=src/test/simple-2.log"),
+            String::from("--prometheus-source"),
+            String::from("author=src/test/empty.log"),
+        ];
+
+        let args = parse_args(raw_args).unwrap();
+
+        assert_eq!(
+            args.prometheus_sources,
+            vec![
+                (
+                    String::from("author"),
+                    vec![String::from("src/test/simple-1.log"), String::from("src/test/empty.log")],
+                ),
+                (String::from("publish"), vec![String::from("src/test/simple-2.log")]),
+            ]
+        );
+        assert_eq!(
+            args.filenames,
+            vec!["src/test/simple-1.log", "src/test/empty.log", "src/test/simple-2.log"]
+        );
+
+        let sources = args.sources();
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[1].filenames, vec!["src/test/simple-2.log"]);
+        assert_eq!(sources[1].grouping.source, Some(String::from("publish")));
+        assert_eq!(sources[1].grouping.label_names(), vec!["source", "method"]);
+    }
+
+    #[test]
+    fn test_invalid_prometheus_sources() {
+        for &(extra_args, expected) in &[
+            (&["publish"][..], "--prometheus-source must be like"),
+            (&["publish=-"][..], "--prometheus-source must be like"),
+            (&["=src/test/simple-1.log"][..], "--prometheus-source must be like"),
+            (
+                &["publish=src/test/simple-1.log", "src/test/simple-2.log"][..],
+                "--prometheus-source can not be combined with log files as arguments",
+            ),
+            (
+                &["publish=src/test/simple-1.log", "--prometheus-legacy-gauges"][..],
+                "--prometheus-source can not be combined with --prometheus-legacy-gauges",
+            ),
+            (
+                &["publish=src/test/simple-1.log", "--prometheus-labels", "source=x"][..],
+                "can not use the label source twice",
+            ),
+            (
+                &["publish=src/test/simple-1.log", "--prometheus-source",
+                  "publish=src/test/simple-2.log", "--follow"][..],
+                "--follow needs exactly one log file for each --prometheus-source",
+            ),
+        ]
+        {
+            let mut raw_args = vec![
+                String::from("request_log_analyzer"),
+                String::from("--prometheus-listen"),
+                String::from("localhost:9898"),
+                String::from("--prometheus-source"),
+            ];
+            raw_args.extend(extra_args.iter().map(|arg| String::from(*arg)));
+
+            let error_message = match parse_args(raw_args) {
+                Err(err) => format!("{}", err),
+                Ok(_) => unreachable!(),
+            };
+
+            assert!(error_message.contains(expected), "{}", error_message);
+        }
+
+        let raw_args = vec![
+            String::from("request_log_analyzer"),
+            String::from("--prometheus-source"),
+            String::from("publish=src/test/simple-1.log"),
+        ];
+
+        let error_message = match parse_args(raw_args) {
+            Err(err) => format!("{}", err),
+            Ok(_) => unreachable!(),
+        };
+
+        assert_eq!(error_message, "--prometheus-source needs --prometheus-listen");
+    }
+
+    #[test]
+    fn test_invalid_duration_and_status() {
+        for &(option, value, expected) in &[
+            ("--min-duration", "5 minutes", "--min-duration must be a duration like 5s"),
+            ("--max-duration", "1h", "--max-duration must be a duration like 5s"),
+            ("--status", "server-error", "--status must be status codes or classes"),
+        ]
+        {
+            let raw_args = vec![
+                String::from("request_log_analyzer"),
+                String::from(option),
+                String::from(value),
+            ];
+
+            let error_message = match parse_args(raw_args) {
+                Err(err) => format!("{}", err),
+                Ok(_) => unreachable!(),
+            };
+
+            assert!(error_message.contains(expected), "{}", error_message);
+        }
+    }
+
+    #[test]
+    fn test_follow_needs_single_file() {
+        let raw_args = vec![
+            String::from("request_log_analyzer"),
+            String::from("--follow"),
+            String::from("one.log"),
+            String::from("two.log"),
+        ];
+
+        let error_message = match parse_args(raw_args) {
+            Err(fail) => format!("{}", fail),
+            Ok(_) => unreachable!(),
+        };
+
+        assert!(error_message.contains("--follow needs exactly one log file"));
+    }
+
+    #[test]
+    fn test_follow_not_stdin() {
+        let raw_args = vec![String::from("request_log_analyzer"), String::from("-f")];
+
+        assert!(parse_args(raw_args).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_glob() {
+        let raw_args = vec![
+            String::from("request_log_analyzer"),
+            String::from("src/test/simple-*.log"),
+        ];
+
+        let result = parse_args(raw_args).unwrap();
+
+        assert_eq!(
+            result.filenames,
+            vec![
+                String::from("src/test/simple-1.log"),
+                String::from("src/test/simple-2.log"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_state_file() {
+        let raw_args = vec![
+            String::from("request_log_analyzer"),
+            String::from("--state-file"),
+            String::from("analyzer.state"),
+            String::from("one.log"),
+        ];
+
+        let result = parse_args(raw_args).unwrap();
+
+        assert_eq!(result.state_file, Some(String::from("analyzer.state")));
+    }
+
+    #[test]
+    fn test_state_file_needs_uncompressed_files() {
+        for filename in &["-", "src/test/simple-1.log.zst"] {
+            let raw_args = vec![
+                String::from("request_log_analyzer"),
+                String::from("--state-file"),
+                String::from("analyzer.state"),
+                String::from(*filename),
+            ];
+
+            let error_message = match parse_args(raw_args) {
+                Err(err) => format!("{}", err),
+                Ok(_) => unreachable!(),
+            };
+
+            assert!(error_message.contains("--state-file needs uncompressed log files"));
+        }
+    }
 }
 
 #[test]
@@ -352,17 +1682,36 @@ fn test_parse_args_multiple_files() {
     let expected = RequestLogAnalyzerArgs {
         filenames: vec![String::from("one.log"), String::from("two.log")],
         conditions: filter::FilterConditions {
-            include_terms: None,
-            exclude_terms: None,
+            expression: None,
             latest_time: None,
+            since: None,
+            until: None,
+            relative_to: filter::TimeAnchor::Now,
         },
         graphite_server: None,
         graphite_port: Some(2003),
         graphite_prefix: None,
         prometheus_listen: None,
+        prometheus_buckets: prometheus::DEFAULT_BUCKETS.to_vec(),
+        prometheus_legacy_gauges: false,
+        prometheus_labels: vec![],
+        prometheus_pushgateway: None,
+        prometheus_job: String::from("request_log_analyzer"),
+        prometheus_grouping: vec![],
+        prometheus_sources: vec![],
+        healthz_max_age: std::time::Duration::from_secs(300),
+        http_timeout: std::time::Duration::from_secs(30),
+        exporter: config::ExporterConfig::default(),
+        grouping: analyzer::grouping::Grouping::default(),
         influxdb_write_url: None,
         influxdb_tags: None,
         quiet: false,
+        time_unit: analyzer::TimeUnit::Milliseconds,
+        jobs: 1,
+        follow: false,
+        follow_interval: std::time::Duration::from_secs(60),
+        state_file: None,
+        read_error_policy: multi_file::ErrorPolicy::Fail,
     };
 
     let result = parse_args(raw_args).unwrap();
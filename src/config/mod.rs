@@ -0,0 +1,437 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use failure::{Error, err_msg};
+use crate::filter::expression::Expression;
+use crate::filter::options;
+
+/// Environment variable with the path of the config file, instead of the
+/// default location.
+pub const CONFIG_ENV: &str = "REQUEST_LOG_ANALYZER_CONFIG";
+
+/// The options of the `[exporter]` table, each of which can be set by an
+/// environment variable like `REQUEST_LOG_ANALYZER_TLS_CERT` as well.
+pub const EXPORTER_OPTIONS: [&str; 5] = [
+    "tls-cert",
+    "tls-key",
+    "basic-auth-user",
+    "basic-auth-password",
+    "bearer-token",
+];
+
+/// The config file, a TOML file with named filter profiles and the
+/// security of the exporter:
+///
+/// ```toml
+/// [profile.publish-pages]
+/// exclude = ["/system/health", "/bin/ping"]
+/// status = "2xx"
+///
+/// [exporter]
+/// tls-cert = "/etc/request_log_analyzer/cert.pem"
+/// tls-key = "/etc/request_log_analyzer/key.pem"
+/// bearer-token = "..."
+/// ```
+#[derive(Debug)]
+pub struct Config {
+    path: String,
+    table: toml::Table,
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Config, Error> {
+        Ok(Config {
+            path: String::from(path),
+            table: read_table(path)?,
+        })
+    }
+
+    /// Load the config file from `$REQUEST_LOG_ANALYZER_CONFIG` or
+    /// `~/.config/request_log_analyzer/config.toml`.
+    pub fn load_default() -> Result<Config, Error> {
+        let path = default_path().ok_or_else(|| {
+            err_msg(format!("No config file, set --config or {}", CONFIG_ENV))
+        })?;
+
+        Config::load(&path.to_string_lossy()).map_err(|err| match err.downcast::<io::Error>() {
+            Ok(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                err_msg(format!(
+                    "No config file at {}, set --config or {}",
+                    path.display(),
+                    CONFIG_ENV
+                ))
+            }
+            Ok(err) => err.into(),
+            Err(err) => err,
+        })
+    }
+
+    /// Load `path`, or the default config file if there is one.
+    pub fn load_optional(path: Option<&str>) -> Result<Option<Config>, Error> {
+        if let Some(path) = path {
+            return Config::load(path).map(Some);
+        }
+
+        match default_path() {
+            Some(ref path) if path.exists() => Config::load(&path.to_string_lossy()).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// The filters of a `[profile.<name>]` table.
+    pub fn profile(&self, name: &str) -> Result<Vec<Expression>, Error> {
+        let profiles = match self.table.get("profile") {
+            Some(toml::Value::Table(profiles)) => Some(profiles),
+            Some(_) => return Err(err_msg(format!("{}: profile must be a table", self.path))),
+            None => None,
+        };
+
+        match profiles.and_then(|profiles| profiles.get(name)) {
+            Some(toml::Value::Table(profile)) => {
+                filters(profile, &format!("{} profile {}", self.path, name))
+            }
+            Some(_) => Err(err_msg(format!("{}: profile {} must be a table", self.path, name))),
+            None => {
+                let names: Vec<&str> = profiles
+                    .map(|profiles| profiles.keys().map(String::as_str).collect())
+                    .unwrap_or_default();
+
+                Err(err_msg(format!(
+                    "{}: there is no profile {} (profiles: {})",
+                    self.path,
+                    name,
+                    names.join(", ")
+                )))
+            }
+        }
+    }
+}
+
+/// How `--prometheus-listen` is secured. It is not taken from the command
+/// line, where the secrets would be visible to every user of the host.
+#[derive(Clone, Default, PartialEq)]
+pub struct ExporterConfig {
+    /// The PEM files of the certificate chain and the private key, to serve
+    /// HTTPS
+    pub tls: Option<(String, String)>,
+    /// The user and password of HTTP basic auth
+    pub basic_auth: Option<(String, String)>,
+    /// A token the requests have to send as `Authorization: Bearer <token>`
+    pub bearer_token: Option<String>,
+}
+
+impl ExporterConfig {
+    /// The `[exporter]` table of `config`, with the environment variables
+    /// given by `env` taking precedence.
+    pub fn load(
+        config: Option<&Config>,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<ExporterConfig, Error> {
+        let table = match config.and_then(|config| config.table.get("exporter")) {
+            Some(toml::Value::Table(table)) => Some(table),
+            Some(_) => {
+                return Err(err_msg(format!(
+                    "{}: exporter must be a table",
+                    config.unwrap().path
+                )))
+            }
+            None => None,
+        };
+
+        if let Some(name) = table.and_then(|table| {
+            table.keys().find(|name| !EXPORTER_OPTIONS.contains(&name.as_str()))
+        })
+        {
+            return Err(err_msg(format!(
+                "{}: unknown exporter option {}, expected one of {}",
+                config.unwrap().path,
+                name,
+                EXPORTER_OPTIONS.join(", ")
+            )));
+        }
+
+        let option = |name: &str| -> Result<Option<String>, Error> {
+            let variable = exporter_env(name);
+
+            if let Some(value) = env(&variable).filter(|value| !value.is_empty()) {
+                return Ok(Some(value));
+            }
+
+            match table.and_then(|table| table.get(name)) {
+                Some(toml::Value::String(value)) => Ok(Some(value.clone())),
+                Some(_) => {
+                    Err(err_msg(format!(
+                        "{}: exporter {} must be a string",
+                        config.unwrap().path,
+                        name
+                    )))
+                }
+                None => Ok(None),
+            }
+        };
+
+        let tls = match (option("tls-cert")?, option("tls-key")?) {
+            (Some(cert), Some(key)) => Some((cert, key)),
+            (None, None) => None,
+            _ => return Err(err_msg("TLS needs both tls-cert and tls-key")),
+        };
+
+        let basic_auth = match (option("basic-auth-user")?, option("basic-auth-password")?) {
+            (Some(user), Some(password)) => Some((user, password)),
+            (None, None) => None,
+            _ => {
+                return Err(err_msg(
+                    "Basic auth needs both basic-auth-user and basic-auth-password",
+                ))
+            }
+        };
+
+        if basic_auth.as_ref().is_some_and(|(user, _)| user.contains(':')) {
+            return Err(err_msg("basic-auth-user must not contain ':'"));
+        }
+
+        Ok(ExporterConfig {
+            tls,
+            basic_auth,
+            bearer_token: option("bearer-token")?,
+        })
+    }
+
+    /// Whether requests have to authenticate.
+    pub fn needs_auth(&self) -> bool {
+        self.basic_auth.is_some() || self.bearer_token.is_some()
+    }
+}
+
+/// Shows which options are set, but not the secrets.
+impl fmt::Debug for ExporterConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ExporterConfig")
+            .field("tls", &self.tls)
+            .field(
+                "basic_auth",
+                &self.basic_auth.as_ref().map(|(user, _)| (user, "***")),
+            )
+            .field("bearer_token", &self.bearer_token.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+/// The environment variable of an `[exporter]` option
+pub fn exporter_env(name: &str) -> String {
+    format!("REQUEST_LOG_ANALYZER_{}", name.to_uppercase().replace('-', "_"))
+}
+
+/// The filters of a filter file, which has the keys of a profile at the top
+/// level.
+pub fn load_filter_file(path: &str) -> Result<Vec<Expression>, Error> {
+    filters(&read_table(path)?, path)
+}
+
+fn default_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(CONFIG_ENV) {
+        return Some(PathBuf::from(path));
+    }
+
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(config_dir.join("request_log_analyzer").join("config.toml"))
+}
+
+fn read_table(path: &str) -> Result<toml::Table, Error> {
+    let text = fs::read_to_string(path)?;
+
+    text.parse().map_err(|err| err_msg(format!("{}: {}", path, err)))
+}
+
+/// Turn the filter options of a table into expressions. Every option takes a
+/// string or a list of strings, like the repeated command line option.
+fn filters(table: &toml::Table, context: &str) -> Result<Vec<Expression>, Error> {
+    if let Some(name) = table.keys().find(|name| !options::NAMES.contains(&name.as_str())) {
+        return Err(err_msg(format!(
+            "{}: unknown filter option {}, expected one of {}",
+            context,
+            name,
+            options::NAMES.join(", ")
+        )));
+    }
+
+    let mut expressions = vec![];
+
+    // In the order of the command line options, not of the file
+    for name in &options::NAMES {
+        if let Some(value) = table.get(*name) {
+            let label = format!("{} {}", context, name);
+            let values = strings(value).ok_or_else(|| {
+                err_msg(format!("{} must be a string or a list of strings", label))
+            })?;
+            let values: Vec<&str> = values.iter().map(String::as_str).collect();
+
+            expressions.extend(options::expression(name, &label, &values)?);
+        }
+    }
+
+    Ok(expressions)
+}
+
+/// Numbers are allowed as well, for statuses like `status = [404, 410]`.
+fn strings(value: &toml::Value) -> Option<Vec<String>> {
+    match value {
+        toml::Value::String(value) => Some(vec![value.clone()]),
+        toml::Value::Integer(value) => Some(vec![value.to_string()]),
+        toml::Value::Array(values) => {
+            values
+                .iter()
+                .map(|value| match value {
+                    toml::Value::String(value) => Some(value.clone()),
+                    toml::Value::Integer(value) => Some(value.to_string()),
+                    _ => None,
+                })
+                .collect()
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::expression::Comparison;
+    use super::*;
+
+    fn error_message<T: std::fmt::Debug>(result: Result<T, Error>) -> String {
+        format!("{}", result.unwrap_err())
+    }
+
+    #[test]
+    fn test_load_filter_file() {
+        let filters = load_filter_file("src/test/filters.toml").unwrap();
+
+        assert_eq!(
+            filters,
+            vec![
+                Expression::negate(
+                    Expression::any(vec![
+                        Expression::line_contains("/system/health"),
+                        Expression::line_contains("/bin/ping"),
+                        Expression::line_contains("/etc.clientlibs/"),
+                    ]).unwrap(),
+                ),
+                Expression::all(vec![
+                    Expression::status_list("2xx").unwrap(),
+                    Expression::status_list("304").unwrap(),
+                ]).unwrap(),
+            ]
+        );
+
+        assert!(load_filter_file("src/test/non-existent.toml").is_err());
+    }
+
+    #[test]
+    fn test_profile() {
+        let config = Config::load("src/test/config.toml").unwrap();
+
+        let filters = config.profile("publish-pages").unwrap();
+        assert_eq!(filters.len(), 3);
+        assert_eq!(
+            filters[2],
+            Expression::Duration(Comparison::LessOrEqual, chrono::Duration::seconds(30))
+        );
+
+        let filters = config.profile("errors").unwrap();
+        assert_eq!(
+            filters,
+            vec![
+                Expression::status_list("5xx").unwrap(),
+                Expression::parse("method = POST or path ~ '^/bin/'").unwrap(),
+            ]
+        );
+
+        assert!(error_message(config.profile("author")).contains(
+            "src/test/config.toml: there is no profile author (profiles: errors, publish-pages)",
+        ));
+    }
+
+    #[test]
+    fn test_exporter_config() {
+        let config = Config::load("src/test/config.toml").unwrap();
+        let no_env = |_: &str| None;
+
+        assert_eq!(
+            ExporterConfig::load(Some(&config), &no_env).unwrap(),
+            ExporterConfig {
+                tls: Some((String::from("cert.pem"), String::from("key.pem"))),
+                basic_auth: Some((String::from("prometheus"), String::from("secret"))),
+                bearer_token: None,
+            }
+        );
+        assert_eq!(ExporterConfig::load(None, &no_env).unwrap(), ExporterConfig::default());
+
+        let env = |name: &str| match name {
+            "REQUEST_LOG_ANALYZER_BASIC_AUTH_PASSWORD" => Some(String::from("from env")),
+            "REQUEST_LOG_ANALYZER_BEARER_TOKEN" => Some(String::from("token")),
+            "REQUEST_LOG_ANALYZER_TLS_CERT" => Some(String::new()),
+            _ => None,
+        };
+        let exporter = ExporterConfig::load(Some(&config), &env).unwrap();
+
+        assert_eq!(exporter.tls, Some((String::from("cert.pem"), String::from("key.pem"))));
+        assert_eq!(
+            exporter.basic_auth,
+            Some((String::from("prometheus"), String::from("from env")))
+        );
+        assert_eq!(exporter.bearer_token, Some(String::from("token")));
+        assert_eq!(
+            format!("{:?}", exporter),
+            "ExporterConfig { tls: Some((\"cert.pem\", \"key.pem\")), \
+             basic_auth: Some((\"prometheus\", \"***\")), bearer_token: Some(\"***\") }"
+        );
+    }
+
+    #[test]
+    fn test_invalid_exporter_config() {
+        let env = |name: &'static str, value: &'static str| {
+            move |variable: &str| if variable == exporter_env(name) {
+                Some(String::from(value))
+            } else {
+                None
+            }
+        };
+
+        assert_eq!(
+            error_message(ExporterConfig::load(None, &env("tls-cert", "cert.pem"))),
+            "TLS needs both tls-cert and tls-key"
+        );
+        assert_eq!(
+            error_message(ExporterConfig::load(None, &env("basic-auth-user", "prometheus"))),
+            "Basic auth needs both basic-auth-user and basic-auth-password"
+        );
+
+        let config = Config {
+            path: String::from("c.toml"),
+            table: "[exporter]\nbearer_token = 'x'".parse().unwrap(),
+        };
+        assert!(error_message(ExporterConfig::load(Some(&config), &|_| None)).contains(
+            "c.toml: unknown exporter option bearer_token",
+        ));
+    }
+
+    #[test]
+    fn test_invalid_filters() {
+        let table = |text: &str| text.parse::<toml::Table>().unwrap();
+
+        assert!(error_message(filters(&table("exlude = 'x'"), "f.toml")).contains(
+            "f.toml: unknown filter option exlude",
+        ));
+        assert!(error_message(filters(&table("status = true"), "f.toml")).contains(
+            "f.toml status must be a string or a list of strings",
+        ));
+        assert!(error_message(filters(&table("status = 'server'"), "f.toml")).contains(
+            "f.toml status must be status codes or classes like 404,410 or 5xx (server)",
+        ));
+    }
+}
@@ -0,0 +1,584 @@
+use chrono::Duration;
+use failure::{Error, err_msg};
+use regex::Regex;
+use crate::filter::field_regex::{FieldRegex, LogField};
+use crate::log_parser::log_events;
+use crate::request_response_matcher::*;
+
+/// A filter like `status >= 500 and not path ~ "^/bin/"`.
+///
+/// `--include`, `--exclude` and the regular expression options are
+/// expressions as well, all of them are combined with `and`.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Expression {
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    /// `method = POST`
+    Equals(LogField, String),
+    /// `line contains "text/html"`
+    Contains(LogField, String),
+    /// `path ~ "^/bin/"`
+    Matches(FieldRegex),
+    /// `status >= 500`
+    Status(Comparison, u16),
+    /// `duration > 1s`, the response time
+    Duration(Comparison, Duration),
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn from_operator(operator: &str) -> Option<Comparison> {
+        match operator {
+            "=" | "==" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessOrEqual),
+            ">" => Some(Comparison::Greater),
+            ">=" => Some(Comparison::GreaterOrEqual),
+            _ => None,
+        }
+    }
+
+    fn compare<T: PartialOrd>(self, actual: T, expected: T) -> bool {
+        match self {
+            Comparison::Equal => actual == expected,
+            Comparison::NotEqual => actual != expected,
+            Comparison::Less => actual < expected,
+            Comparison::LessOrEqual => actual <= expected,
+            Comparison::Greater => actual > expected,
+            Comparison::GreaterOrEqual => actual >= expected,
+        }
+    }
+}
+
+impl Expression {
+    /// Parse an expression as given to `--filter`.
+    pub fn parse(source: &str) -> Result<Expression, Error> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+            end: source.chars().count(),
+        };
+
+        let expression = parser.or()?;
+
+        match parser.tokens.get(parser.position) {
+            Some(&(offset, ref token)) => {
+                Err(parse_error(offset, &format!("unexpected {}", token)))
+            }
+            None => Ok(expression),
+        }
+    }
+
+    /// The expression that matches if the line contains `term`.
+    pub fn line_contains(term: &str) -> Expression {
+        Expression::Contains(LogField::Line, String::from(term))
+    }
+
+    pub fn negate(expression: Expression) -> Expression {
+        Expression::Not(Box::new(expression))
+    }
+
+    /// Combine expressions with `and`, `None` if there are none.
+    pub fn all(expressions: Vec<Expression>) -> Option<Expression> {
+        expressions.into_iter().fold(None, |all, expression| match all {
+            Some(all) => Some(Expression::And(Box::new(all), Box::new(expression))),
+            None => Some(expression),
+        })
+    }
+
+    /// Combine expressions with `or`, `None` if there are none.
+    pub fn any(expressions: Vec<Expression>) -> Option<Expression> {
+        expressions.into_iter().fold(None, |any, expression| match any {
+            Some(any) => Some(Expression::Or(Box::new(any), Box::new(expression))),
+            None => Some(expression),
+        })
+    }
+
+    /// Parse status codes and classes like `404,410` or `5xx`, any of which
+    /// matches.
+    pub fn status_list(value: &str) -> Option<Expression> {
+        let statuses = value
+            .split(',')
+            .map(|status| match status.trim().as_bytes() {
+                &[class @ b'1'..=b'5', b'x', b'x'] |
+                &[class @ b'1'..=b'5', b'X', b'X'] => {
+                    let lowest = u16::from(class - b'0') * 100;
+
+                    Some(Expression::And(
+                        Box::new(Expression::Status(Comparison::GreaterOrEqual, lowest)),
+                        Box::new(Expression::Status(Comparison::Less, lowest + 100)),
+                    ))
+                }
+                _ => {
+                    status.trim().parse().ok().map(|status| {
+                        Expression::Status(Comparison::Equal, status)
+                    })
+                }
+            })
+            .collect::<Option<Vec<Expression>>>()?;
+
+        Expression::any(statuses)
+    }
+
+    pub fn matches(&self, pair: &RequestResponsePair) -> bool {
+        match *self {
+            Expression::And(ref left, ref right) => left.matches(pair) && right.matches(pair),
+            Expression::Or(ref left, ref right) => left.matches(pair) || right.matches(pair),
+            Expression::Not(ref expression) => !expression.matches(pair),
+            Expression::Equals(field, ref expected) => {
+                field.values(pair).iter().flatten().any(
+                    |value| value == expected,
+                )
+            }
+            Expression::Contains(field, ref term) => {
+                field.values(pair).iter().flatten().any(
+                    |value| value.contains(term.as_str()),
+                )
+            }
+            Expression::Matches(ref field_regex) => field_regex.is_match(pair),
+            Expression::Status(comparison, expected) => {
+                match pair.response.status {
+                    Some(status) => comparison.compare(status, expected),
+                    None => false,
+                }
+            }
+            Expression::Duration(comparison, expected) => {
+                comparison.compare(pair.response.response_time, expected)
+            }
+        }
+    }
+
+    /// Whether matching needs the raw log lines, only the status and the
+    /// response time are available without them.
+    pub fn needs_original_log_line(&self) -> bool {
+        match *self {
+            Expression::And(ref left, ref right) |
+            Expression::Or(ref left, ref right) => {
+                left.needs_original_log_line() || right.needs_original_log_line()
+            }
+            Expression::Not(ref expression) => expression.needs_original_log_line(),
+            Expression::Status(..) |
+            Expression::Duration(..) => false,
+            _ => true,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+enum Token {
+    /// A field name, keyword or unquoted value
+    Word(String),
+    /// A quoted value
+    Quoted(String),
+    Operator(String),
+    Open,
+    Close,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Token::Word(ref word) => write!(f, "'{}'", word),
+            Token::Quoted(ref text) => write!(f, "\"{}\"", text),
+            Token::Operator(ref operator) => write!(f, "'{}'", operator),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+        }
+    }
+}
+
+fn parse_error(offset: usize, message: &str) -> Error {
+    err_msg(format!("Invalid filter at character {}: {}", offset + 1, message))
+}
+
+fn is_operator_char(c: char) -> bool {
+    "=!<>~".contains(c)
+}
+
+/// Split an expression into tokens, each with its character offset.
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, Error> {
+    let mut tokens = vec![];
+    let mut chars = source.chars().enumerate().peekable();
+
+    while let Some(&(offset, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' || c == ')' {
+            chars.next();
+            tokens.push((offset, if c == '(' { Token::Open } else { Token::Close }));
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut text = String::new();
+
+            loop {
+                match chars.next() {
+                    // Only quotes and backslashes are escaped, so regular
+                    // expressions keep their backslashes
+                    Some((_, '\\')) => {
+                        match chars.peek() {
+                            Some(&(_, escaped)) if escaped == c || escaped == '\\' => {
+                                text.push(escaped);
+                                chars.next();
+                            }
+                            _ => text.push('\\'),
+                        }
+                    }
+                    Some((_, quote)) if quote == c => break,
+                    Some((_, other)) => text.push(other),
+                    None => return Err(parse_error(offset, "unterminated string")),
+                }
+            }
+
+            tokens.push((offset, Token::Quoted(text)));
+        } else if is_operator_char(c) {
+            let mut operator = String::new();
+
+            while let Some(&(_, c)) = chars.peek() {
+                if !is_operator_char(c) {
+                    break;
+                }
+                operator.push(c);
+                chars.next();
+            }
+
+            tokens.push((offset, Token::Operator(operator)));
+        } else {
+            let mut word = String::new();
+
+            while let Some(&(_, c)) = chars.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' || c == '"' || c == '\'' ||
+                    is_operator_char(c)
+                {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+
+            tokens.push((offset, Token::Word(word)));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser, `not` binds tighter than `and`, which binds
+/// tighter than `or`.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    /// The offset after the last character, for errors about missing tokens
+    end: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<(usize, Token)> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_is_keyword(&self, keyword: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(&(_, Token::Word(ref word))) => word.eq_ignore_ascii_case(keyword),
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expression, Error> {
+        let mut expression = self.and()?;
+
+        while self.next_is_keyword("or") {
+            self.position += 1;
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> Result<Expression, Error> {
+        let mut expression = self.not()?;
+
+        while self.next_is_keyword("and") {
+            self.position += 1;
+            expression = Expression::And(Box::new(expression), Box::new(self.not()?));
+        }
+
+        Ok(expression)
+    }
+
+    fn not(&mut self) -> Result<Expression, Error> {
+        if self.next_is_keyword("not") {
+            self.position += 1;
+            return Ok(Expression::negate(self.not()?));
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expression, Error> {
+        match self.next() {
+            Some((_, Token::Open)) => {
+                let expression = self.or()?;
+
+                match self.next() {
+                    Some((_, Token::Close)) => Ok(expression),
+                    Some((offset, token)) => {
+                        Err(parse_error(offset, &format!("expected ')' instead of {}", token)))
+                    }
+                    None => Err(parse_error(self.end, "missing ')'")),
+                }
+            }
+            Some((offset, Token::Word(field))) => self.comparison(offset, &field),
+            Some((offset, token)) => {
+                Err(parse_error(offset, &format!("expected a field instead of {}", token)))
+            }
+            None => Err(parse_error(self.end, "expected a field")),
+        }
+    }
+
+    fn comparison(&mut self, field_offset: usize, field: &str) -> Result<Expression, Error> {
+        let (operator_offset, operator) = match self.next() {
+            Some((offset, Token::Operator(operator))) => (offset, operator),
+            Some((offset, Token::Word(ref word))) if word == "contains" => {
+                (offset, String::from("contains"))
+            }
+            Some((offset, token)) => {
+                return Err(parse_error(
+                    offset,
+                    &format!("expected an operator after '{}' instead of {}", field, token),
+                ))
+            }
+            None => {
+                return Err(parse_error(
+                    self.end,
+                    &format!("expected an operator after '{}'", field),
+                ))
+            }
+        };
+
+        let (value_offset, value) = match self.next() {
+            Some((offset, Token::Word(value))) |
+            Some((offset, Token::Quoted(value))) => (offset, value),
+            Some((offset, token)) => {
+                return Err(parse_error(
+                    offset,
+                    &format!("expected a value instead of {}", token),
+                ))
+            }
+            None => {
+                return Err(parse_error(
+                    self.end,
+                    &format!("expected a value after '{} {}'", field, operator),
+                ))
+            }
+        };
+
+        let invalid_operator = || {
+            parse_error(
+                operator_offset,
+                &format!("'{}' can not be used with {}", operator, field),
+            )
+        };
+
+        if field == "duration" {
+            let comparison = Comparison::from_operator(&operator).ok_or_else(invalid_operator)?;
+            let duration = log_events::parse_duration(&value).map_err(|_| {
+                parse_error(value_offset, &format!("'{}' is not a duration", value))
+            })?;
+
+            return Ok(Expression::Duration(comparison, duration));
+        }
+
+        let log_field = LogField::from_name(field).ok_or_else(|| {
+            parse_error(
+                field_offset,
+                &format!(
+                    "unknown field '{}', expected one of method, path, protocol, status, \
+                     content-type, duration, line",
+                    field
+                ),
+            )
+        })?;
+
+        let regex = || {
+            Regex::new(&value).map_err(|err| parse_error(value_offset, &format!("{}", err)))
+        };
+
+        if log_field == LogField::Status {
+            if let (Some(comparison), Ok(status)) =
+                (Comparison::from_operator(&operator), value.parse())
+            {
+                return Ok(Expression::Status(comparison, status));
+            }
+
+            match (operator.as_str(), Expression::status_list(&value)) {
+                ("=", Some(expression)) | ("==", Some(expression)) => return Ok(expression),
+                ("!=", Some(expression)) => return Ok(Expression::negate(expression)),
+                _ => {}
+            }
+        }
+
+        match (log_field, operator.as_str()) {
+            (_, "~") => Ok(Expression::Matches(FieldRegex {
+                field: log_field,
+                regex: regex()?,
+            })),
+            (_, "!~") => Ok(Expression::negate(Expression::Matches(FieldRegex {
+                field: log_field,
+                regex: regex()?,
+            }))),
+            (_, "contains") => Ok(Expression::Contains(log_field, value)),
+            (LogField::Line, _) => Err(invalid_operator()),
+            (_, "=") | (_, "==") => Ok(Expression::Equals(log_field, value)),
+            (_, "!=") => Ok(Expression::negate(Expression::Equals(log_field, value))),
+            _ => Err(invalid_operator()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::log_parser::log_events::{Request, Response};
+    use super::*;
+
+    fn get_fixture() -> RequestResponsePair {
+        RequestResponsePair {
+            request: Request::new_from_log_line(
+                "08/Apr/2016:09:57:47 +0200 [001] -> POST /bin/receive HTTP/1.1",
+            ).unwrap(),
+            response: Response::new_from_log_line(
+                "08/Apr/2016:09:57:47 +0200 [001] <- 503 text/html 1200ms",
+            ).unwrap(),
+        }
+    }
+
+    fn error_message(source: &str) -> String {
+        match Expression::parse(source) {
+            Err(err) => format!("{}", err),
+            Ok(expression) => panic!("{} parsed as {:?}", source, expression),
+        }
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        let expression = Expression::parse("not method = GET or status >= 500 and duration > 1s")
+            .unwrap();
+
+        let expected = Expression::Or(
+            Box::new(Expression::negate(
+                Expression::Equals(LogField::Method, String::from("GET")),
+            )),
+            Box::new(Expression::And(
+                Box::new(Expression::Status(Comparison::GreaterOrEqual, 500)),
+                Box::new(Expression::Duration(Comparison::Greater, Duration::seconds(1))),
+            )),
+        );
+
+        assert_eq!(expression, expected);
+    }
+
+    #[test]
+    fn test_matches() {
+        let pair = get_fixture();
+
+        for &(source, expected) in &[
+            (r#"status >= 500 and method = "POST" and path ~ "^/bin/" and duration > 1000"#, true),
+            ("status >= 500 AND NOT (method = POST or method = PUT)", false),
+            ("status = 404 or content-type = text/html", true),
+            ("status != 503", false),
+            ("status ~ ^5", true),
+            ("status = 5xx and status != 404,410", true),
+            ("status = 4xx,501", false),
+            ("method != GET and path !~ '\\.html$'", true),
+            ("duration <= 1.2s and duration > 1199999us", true),
+            ("line contains \"[001] <- 503\"", true),
+            ("path contains /content/", false),
+            ("protocol == HTTP/1.1", true),
+            (r#"line ~ "\[001\] <- \d{3}" and path != "say \"hi\"""#, true),
+        ]
+        {
+            let expression = Expression::parse(source).unwrap();
+            assert_eq!(expression.matches(&pair), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_needs_original_log_line() {
+        assert!(!Expression::parse("duration > 1s or not duration < 5").unwrap()
+            .needs_original_log_line());
+        assert!(!Expression::parse("duration > 1s or status = 500").unwrap()
+            .needs_original_log_line());
+        assert!(Expression::parse("duration > 1s or method = GET").unwrap()
+            .needs_original_log_line());
+    }
+
+    #[test]
+    fn test_all_and_any() {
+        assert_eq!(Expression::all(vec![]), None);
+        assert_eq!(
+            Expression::any(vec![Expression::line_contains("a"), Expression::line_contains("b")]),
+            Some(Expression::Or(
+                Box::new(Expression::line_contains("a")),
+                Box::new(Expression::line_contains("b")),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_status_list() {
+        let class = |lowest| {
+            Expression::And(
+                Box::new(Expression::Status(Comparison::GreaterOrEqual, lowest)),
+                Box::new(Expression::Status(Comparison::Less, lowest + 100)),
+            )
+        };
+
+        assert_eq!(Expression::status_list("5xx"), Some(class(500)));
+        assert_eq!(
+            Expression::status_list("404, 410,2XX"),
+            Expression::any(vec![
+                Expression::Status(Comparison::Equal, 404),
+                Expression::Status(Comparison::Equal, 410),
+                class(200),
+            ])
+        );
+        assert_eq!(Expression::status_list("6xx"), None);
+        assert_eq!(Expression::status_list("404,"), None);
+        assert_eq!(Expression::status_list("error"), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for &(source, expected) in &[
+            ("", "character 1: expected a field"),
+            ("status >=", "expected a value after 'status >='"),
+            ("status 500", "character 8: expected an operator after 'status' instead of '500'"),
+            ("size > 10", "character 1: unknown field 'size'"),
+            ("duration ~ 10", "'~' can not be used with duration"),
+            ("duration > 10min", "character 12: '10min' is not a duration"),
+            ("duration > 10 minutes", "character 15: unexpected 'minutes'"),
+            ("line = x", "'=' can not be used with line"),
+            ("method > GET", "'>' can not be used with method"),
+            ("(status = 500", "missing ')'"),
+            ("status = 500)", "character 13: unexpected ')'"),
+            ("path ~ '(unclosed'", "character 8: regex parse error"),
+            ("path = \"/bin", "character 8: unterminated string"),
+        ]
+        {
+            let message = error_message(source);
+            assert!(message.contains(expected), "{}: {}", source, message);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use regex::Regex;
+use crate::request_response_matcher::*;
+
+/// Part of a request/response pair that filters are matched against.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum LogField {
+    /// The full request and response lines, either of them can match
+    Line,
+    Method,
+    Path,
+    Protocol,
+    Status,
+    ContentType,
+}
+
+impl LogField {
+    pub fn from_name(name: &str) -> Option<LogField> {
+        match name {
+            "line" => Some(LogField::Line),
+            "method" => Some(LogField::Method),
+            "path" => Some(LogField::Path),
+            "protocol" => Some(LogField::Protocol),
+            "status" => Some(LogField::Status),
+            "content-type" => Some(LogField::ContentType),
+            _ => None,
+        }
+    }
+
+    /// The values of the field, read from the original log lines. Only `Line`
+    /// has two, the request and the response line.
+    pub fn values(self, pair: &RequestResponsePair) -> [Option<&str>; 2] {
+        match self {
+            LogField::Line => {
+                [
+                    pair.request.original_log_line.as_deref(),
+                    pair.response.original_log_line.as_deref(),
+                ]
+            }
+            field => [field.value(pair), None],
+        }
+    }
+
+    /// The value of a field within one of the lines.
+    fn value(self, pair: &RequestResponsePair) -> Option<&str> {
+        // 08/Apr/2016:09:57:47 +0200 [001] -> GET /content/some/page.html HTTP/1.1
+        // 08/Apr/2016:09:57:47 +0200 [001] <- 200 text/html; charset=utf-8 7ms
+        let (line, position) = match self {
+            LogField::Line => return None,
+            LogField::Method => (&pair.request.original_log_line, 4),
+            LogField::Path => (&pair.request.original_log_line, 5),
+            LogField::Protocol => (&pair.request.original_log_line, 6),
+            LogField::Status => (&pair.response.original_log_line, 4),
+            LogField::ContentType => (&pair.response.original_log_line, 5),
+        };
+
+        let value = line.as_ref()?.split(' ').nth(position)?;
+
+        if self == LogField::ContentType {
+            // Without parameters like the charset
+            value.split(';').next()
+        } else {
+            Some(value)
+        }
+    }
+}
+
+/// A regular expression for `--include-regex` and `--exclude-regex`, written
+/// as `[FIELD:]REGEX`. Without a field, it is matched against the full lines.
+#[derive(Debug, Clone)]
+pub struct FieldRegex {
+    pub field: LogField,
+    pub regex: Regex,
+}
+
+impl FieldRegex {
+    pub fn parse(value: &str) -> Result<FieldRegex, regex::Error> {
+        let (field, pattern) = match value.split_once(':') {
+            Some((name, pattern)) => {
+                match LogField::from_name(name) {
+                    Some(field) => (field, pattern),
+                    None => (LogField::Line, value),
+                }
+            }
+            None => (LogField::Line, value),
+        };
+
+        Ok(FieldRegex {
+            field,
+            regex: Regex::new(pattern)?,
+        })
+    }
+
+    pub fn is_match(&self, pair: &RequestResponsePair) -> bool {
+        self.field.values(pair).iter().flatten().any(
+            |value| self.regex.is_match(value),
+        )
+    }
+}
+
+impl PartialEq for FieldRegex {
+    fn eq(&self, other: &FieldRegex) -> bool {
+        self.field == other.field && self.regex.as_str() == other.regex.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::log_parser::log_events::*;
+    use super::*;
+
+    fn get_fixture() -> RequestResponsePair {
+        RequestResponsePair {
+            request: Request::new_from_log_line(
+                "08/Apr/2016:09:57:47 +0200 [001] -> GET /content/site/page.model.json HTTP/1.1",
+            ).unwrap(),
+            response: Response::new_from_log_line(
+                "08/Apr/2016:09:57:47 +0200 [001] <- 404 application/json 1ms",
+            ).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let field_regex = FieldRegex::parse(r"path:^/content/site/.*\.html$").unwrap();
+        assert_eq!(field_regex.field, LogField::Path);
+        assert_eq!(field_regex.regex.as_str(), r"^/content/site/.*\.html$");
+
+        // Not a field name, so part of the regex
+        let field_regex = FieldRegex::parse("GET:x").unwrap();
+        assert_eq!(field_regex.field, LogField::Line);
+        assert_eq!(field_regex.regex.as_str(), "GET:x");
+
+        let field_regex = FieldRegex::parse("line:path:x").unwrap();
+        assert_eq!(field_regex.field, LogField::Line);
+        assert_eq!(field_regex.regex.as_str(), "path:x");
+
+        assert!(FieldRegex::parse("path:(unclosed").is_err());
+    }
+
+    #[test]
+    fn test_is_match() {
+        let pair = get_fixture();
+
+        for &(value, expected) in &[
+            (r"\[001\] <- 404", true),
+            (r"^/content/site/", false),
+            (r"path:^/content/site/", true),
+            (r"path:\.html$", false),
+            (r"method:^(GET|HEAD)$", true),
+            (r"protocol:HTTP/2", false),
+            (r"status:^4\d\d$", true),
+            (r"content-type:^text/", false),
+        ]
+        {
+            let field_regex = FieldRegex::parse(value).unwrap();
+            assert_eq!(field_regex.is_match(&pair), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_value_content_type_charset() {
+        let mut pair = get_fixture();
+        pair.response = Response::new_from_log_line(
+            "08/Apr/2016:09:57:47 +0200 [001] <- 200 text/html; charset=utf-8 7ms",
+        ).unwrap();
+
+        assert_eq!(LogField::ContentType.values(&pair), [Some("text/html"), None]);
+        assert!(FieldRegex::parse("content-type:^text/html$").unwrap().is_match(&pair));
+    }
+}
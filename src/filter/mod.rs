@@ -1,51 +1,113 @@
+use std::cmp;
+use std::collections::VecDeque;
 use crate::request_response_matcher::*;
 use chrono::*;
+use self::expression::Expression;
+
+pub mod expression;
+pub mod field_regex;
+pub mod options;
+
+/// The time that `-t` counts back from.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TimeAnchor {
+    /// The current time
+    Now,
+    /// The latest request in the input, to be resolved to `Time` before
+    /// filtering. Until then, and in `--follow` mode, it is the current time.
+    LogEnd,
+    Time(DateTime<FixedOffset>),
+}
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct FilterConditions {
-    pub include_terms: Option<Vec<String>>,
-    pub exclude_terms: Option<Vec<String>>,
+    /// `--filter`, `--include`, `--exclude` and the regular expressions
+    pub expression: Option<Expression>,
     pub latest_time: Option<Duration>,
+    /// Only requests at or after this time
+    pub since: Option<DateTime<FixedOffset>>,
+    /// Only requests before this time
+    pub until: Option<DateTime<FixedOffset>>,
+    pub relative_to: TimeAnchor,
 }
 
-pub fn matches_filter(pair: &RequestResponsePair, conditions: &FilterConditions) -> bool {
-    let matches_include_terms: bool = match conditions.include_terms {
-        Some(ref include_terms) => {
-            include_terms.iter().fold(false, |result, include_term| {
-                result || pair.request.original_log_line.contains(include_term) ||
-                    pair.response.original_log_line.contains(include_term)
-            })
-        }
-        None => true,
-    };
+impl FilterConditions {
+    /// Whether filtering needs the raw log lines, which are otherwise not
+    /// kept after parsing.
+    pub fn needs_original_log_line(&self) -> bool {
+        self.expression.as_ref().is_some_and(
+            |expression| expression.needs_original_log_line(),
+        )
+    }
+}
 
-    let matches_exclude_terms: bool = match conditions.exclude_terms {
-        Some(ref exclude_terms) => {
-            !exclude_terms.iter().fold(false, |result, exclude_term| {
-                result || pair.request.original_log_line.contains(exclude_term) ||
-                    pair.response.original_log_line.contains(exclude_term)
-            })
-        }
+pub fn matches_filter(pair: &RequestResponsePair, conditions: &FilterConditions) -> bool {
+    let matches_expression: bool = match conditions.expression {
+        Some(ref expression) => expression.matches(pair),
         None => true,
     };
 
     let matches_time: bool = match conditions.latest_time {
         Some(latest_time) => {
             let timezone = pair.request.time.timezone();
-            let now = Utc::now().with_timezone(&timezone);
+            let now = match conditions.relative_to {
+                TimeAnchor::Now | TimeAnchor::LogEnd => Utc::now().with_timezone(&timezone),
+                TimeAnchor::Time(time) => time.with_timezone(&timezone),
+            };
             let include_since_time = now - latest_time;
             pair.request.time >= include_since_time
         }
         None => true,
     };
 
-    matches_include_terms && matches_exclude_terms && matches_time
+    let matches_since: bool = match conditions.since {
+        Some(since) => pair.request.time >= since,
+        None => true,
+    };
+
+    let matches_until: bool = match conditions.until {
+        Some(until) => pair.request.time < until,
+        None => true,
+    };
+
+    matches_expression && matches_time && matches_since && matches_until
+}
+
+/// Collect the pairs that can be within `latest_time` of the latest request,
+/// returning them with the time of that request.
+///
+/// This allows anchoring `-t` on the end of a log that can only be read
+/// once, like stdin, while keeping only the last minutes of it in memory.
+pub fn latest_pairs<I>(
+    pairs: I,
+    latest_time: Duration,
+) -> (Vec<RequestResponsePair>, Option<DateTime<FixedOffset>>)
+where
+    I: Iterator<Item = RequestResponsePair>,
+{
+    let mut buffer: VecDeque<RequestResponsePair> = VecDeque::new();
+    let mut end: Option<DateTime<FixedOffset>> = None;
+
+    for pair in pairs {
+        let latest = end.map_or(pair.request.time, |end| cmp::max(end, pair.request.time));
+        end = Some(latest);
+        buffer.push_back(pair);
+
+        let oldest_allowed = latest - latest_time;
+
+        while buffer.front().filter(|oldest| oldest.request.time < oldest_allowed).is_some() {
+            buffer.pop_front();
+        }
+    }
+
+    (buffer.into_iter().collect(), end)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::log_parser::log_events::*;
     use chrono::*;
+    use super::field_regex::FieldRegex;
     use super::*;
 
     fn get_fixture() -> RequestResponsePair {
@@ -64,9 +126,11 @@ mod tests {
         let pair = get_fixture();
 
         let conditions = FilterConditions {
-            include_terms: None,
-            exclude_terms: None,
+            expression: None,
             latest_time: None,
+            since: None,
+            until: None,
+            relative_to: TimeAnchor::Now,
         };
 
         let result = matches_filter(&pair, &conditions);
@@ -79,9 +143,11 @@ mod tests {
         let pair = get_fixture();
 
         let conditions = FilterConditions {
-            include_terms: Some(vec!["page.html".to_string()]),
-            exclude_terms: None,
+            expression: Expression::any(vec![Expression::line_contains("page.html")]),
             latest_time: None,
+            since: None,
+            until: None,
+            relative_to: TimeAnchor::Now,
         };
 
         let result = matches_filter(&pair, &conditions);
@@ -94,9 +160,11 @@ mod tests {
         let pair = get_fixture();
 
         let conditions = FilterConditions {
-            include_terms: Some(vec!["text/html".to_string()]),
-            exclude_terms: None,
+            expression: Expression::any(vec![Expression::line_contains("text/html")]),
             latest_time: None,
+            since: None,
+            until: None,
+            relative_to: TimeAnchor::Now,
         };
 
         let result = matches_filter(&pair, &conditions);
@@ -109,9 +177,12 @@ mod tests {
         let pair = get_fixture();
 
         let conditions = FilterConditions {
-            include_terms: None,
-            exclude_terms: Some(vec!["page.html".to_string()]),
+            expression: Expression::any(vec![Expression::line_contains("page.html")])
+                .map(Expression::negate),
             latest_time: None,
+            since: None,
+            until: None,
+            relative_to: TimeAnchor::Now,
         };
 
         let result = matches_filter(&pair, &conditions);
@@ -124,9 +195,12 @@ mod tests {
         let pair = get_fixture();
 
         let conditions = FilterConditions {
-            include_terms: None,
-            exclude_terms: Some(vec!["text/html".to_string()]),
+            expression: Expression::any(vec![Expression::line_contains("text/html")])
+                .map(Expression::negate),
             latest_time: None,
+            since: None,
+            until: None,
+            relative_to: TimeAnchor::Now,
         };
 
         let result = matches_filter(&pair, &conditions);
@@ -139,9 +213,14 @@ mod tests {
         let pair = get_fixture();
 
         let conditions = FilterConditions {
-            include_terms: Some(vec!["irrelevant.html".to_string(), "page.html".to_string()]),
-            exclude_terms: None,
+            expression: Expression::any(vec![
+                Expression::line_contains("irrelevant.html"),
+                Expression::line_contains("page.html"),
+            ]),
             latest_time: None,
+            since: None,
+            until: None,
+            relative_to: TimeAnchor::Now,
         };
 
         let result = matches_filter(&pair, &conditions);
@@ -154,9 +233,14 @@ mod tests {
         let pair = get_fixture();
 
         let conditions = FilterConditions {
-            include_terms: None,
-            exclude_terms: Some(vec!["irrelevant.html".to_string(), "page.html".to_string()]),
+            expression: Expression::any(vec![
+                Expression::line_contains("irrelevant.html"),
+                Expression::line_contains("page.html"),
+            ]).map(Expression::negate),
             latest_time: None,
+            since: None,
+            until: None,
+            relative_to: TimeAnchor::Now,
         };
 
         let result = matches_filter(&pair, &conditions);
@@ -164,15 +248,60 @@ mod tests {
         assert_eq!(result, false);
     }
 
+    #[test]
+    fn test_filter_regexes() {
+        let pair = get_fixture();
+        let regexes = |values: &[&str]| {
+            Expression::any(
+                values
+                    .iter()
+                    .map(|value| Expression::Matches(FieldRegex::parse(value).unwrap()))
+                    .collect(),
+            )
+        };
+
+        for &(include, exclude, expected) in &[
+            (&[r"path:^/content/some/.*\.html$"][..], &[][..], true),
+            (&[r"path:\.model\.json$", "text/html"][..], &[][..], true),
+            (&[r"path:\.model\.json$"][..], &[][..], false),
+            (&[r"path:^/content/"][..], &["status:^200$"][..], false),
+            (&[r"path:^/content/"][..], &[r"<- 5\d\d"][..], true),
+        ]
+        {
+            let conditions = FilterConditions {
+                expression: Expression::all(
+                    regexes(include)
+                        .into_iter()
+                        .chain(regexes(exclude).map(Expression::negate))
+                        .collect(),
+                ),
+                latest_time: None,
+                since: None,
+                until: None,
+                relative_to: TimeAnchor::Now,
+            };
+
+            assert_eq!(
+                matches_filter(&pair, &conditions),
+                expected,
+                "{:?} {:?}",
+                include,
+                exclude
+            );
+        }
+    }
+
     #[test]
     fn test_filter_time_matches() {
         let mut pair = get_fixture();
         pair.request.time = Utc::now().with_timezone(&pair.request.time.timezone());
 
         let conditions = FilterConditions {
-            include_terms: None,
-            exclude_terms: None,
+            expression: None,
             latest_time: Some(Duration::minutes(10)),
+            since: None,
+            until: None,
+            relative_to: TimeAnchor::Now,
         };
 
         let result = matches_filter(&pair, &conditions);
@@ -187,13 +316,103 @@ mod tests {
             Duration::minutes(12);
 
         let conditions = FilterConditions {
-            include_terms: None,
-            exclude_terms: None,
+            expression: None,
             latest_time: Some(Duration::minutes(10)),
+            since: None,
+            until: None,
+            relative_to: TimeAnchor::Now,
         };
 
         let result = matches_filter(&pair, &conditions);
 
         assert_eq!(result, false);
     }
+
+    #[test]
+    fn test_filter_time_range() {
+        let pair = get_fixture();
+        let time = |value| DateTime::parse_from_rfc3339(value).unwrap();
+
+        // The request is at 07:57:47 UTC, the range includes since but not until
+        for &(since, until, expected) in &[
+            (Some("2016-04-08T07:57:47Z"), None, true),
+            (Some("2016-04-08T09:57:48+02:00"), None, false),
+            (None, Some("2016-04-08T07:57:48Z"), true),
+            (None, Some("2016-04-08T07:57:47Z"), false),
+            (Some("2016-04-08T07:00:00Z"), Some("2016-04-08T08:00:00Z"), true),
+        ]
+        {
+            let conditions = FilterConditions {
+                expression: None,
+                latest_time: None,
+                since: since.map(time),
+                until: until.map(time),
+                relative_to: TimeAnchor::Now,
+            };
+
+            assert_eq!(
+                matches_filter(&pair, &conditions),
+                expected,
+                "{:?} - {:?}",
+                since,
+                until
+            );
+        }
+    }
+
+    #[test]
+    fn test_filter_time_relative_to() {
+        let pair = get_fixture();
+        let time = |value| DateTime::parse_from_rfc3339(value).unwrap();
+
+        let mut conditions = FilterConditions {
+            expression: None,
+            latest_time: Some(Duration::minutes(10)),
+            since: None,
+            until: None,
+            relative_to: TimeAnchor::Time(time("2016-04-08T08:07:47Z")),
+        };
+
+        assert!(matches_filter(&pair, &conditions));
+
+        conditions.relative_to = TimeAnchor::Time(time("2016-04-08T08:07:48Z"));
+        assert!(!matches_filter(&pair, &conditions));
+
+        conditions.relative_to = TimeAnchor::LogEnd;
+        assert!(!matches_filter(&pair, &conditions));
+    }
+
+    #[test]
+    fn test_latest_pairs() {
+        let pair_at = |time: &str| {
+            let mut pair = get_fixture();
+            pair.request.time = DateTime::parse_from_rfc3339(time).unwrap();
+            pair
+        };
+
+        let pairs = vec![
+            pair_at("2016-04-08T08:00:00Z"),
+            pair_at("2016-04-08T08:05:00Z"),
+            pair_at("2016-04-08T08:20:00Z"),
+            pair_at("2016-04-08T08:12:00Z"),
+            pair_at("2016-04-08T08:09:59Z"),
+        ];
+
+        let (latest, end) = latest_pairs(pairs.into_iter(), Duration::minutes(10));
+
+        assert_eq!(end, Some(DateTime::parse_from_rfc3339("2016-04-08T08:20:00Z").unwrap()));
+        assert_eq!(
+            latest
+                .iter()
+                .map(|pair| pair.request.time.to_rfc3339())
+                .collect::<Vec<String>>(),
+            vec![
+                "2016-04-08T08:20:00+00:00",
+                "2016-04-08T08:12:00+00:00",
+                "2016-04-08T08:09:59+00:00",
+            ]
+        );
+
+        assert_eq!(latest_pairs(vec![].into_iter(), Duration::minutes(10)), (vec![], None));
+    }
 }
@@ -0,0 +1,135 @@
+use failure::{Error, err_msg};
+use crate::filter::expression::{Comparison, Expression};
+use crate::filter::field_regex::FieldRegex;
+use crate::log_parser::log_events;
+
+/// The filter options that can be given on the command line as `--<name>`,
+/// and in filter files and profiles as `<name> = ...`.
+pub const NAMES: [&str; 8] = [
+    "include",
+    "exclude",
+    "include-regex",
+    "exclude-regex",
+    "min-duration",
+    "max-duration",
+    "status",
+    "filter",
+];
+
+/// Turn the values of a filter option into an expression, `None` without
+/// values. `label` names the option in errors, like `--status`.
+pub fn expression(name: &str, label: &str, values: &[&str]) -> Result<Option<Expression>, Error> {
+    let expression = match name {
+        "include" => Expression::any(terms(values)),
+        "exclude" => Expression::any(terms(values)).map(Expression::negate),
+        "include-regex" => Expression::any(regexes(label, values)?),
+        "exclude-regex" => Expression::any(regexes(label, values)?).map(Expression::negate),
+        "min-duration" => {
+            Expression::all(each(values, |value| {
+                Ok(Expression::Duration(Comparison::GreaterOrEqual, duration(label, value)?))
+            })?)
+        }
+        "max-duration" => {
+            Expression::all(each(values, |value| {
+                Ok(Expression::Duration(Comparison::LessOrEqual, duration(label, value)?))
+            })?)
+        }
+        "status" => {
+            Expression::all(each(values, |value| {
+                Expression::status_list(value).ok_or_else(|| {
+                    err_msg(format!(
+                        "{} must be status codes or classes like 404,410 or 5xx ({})",
+                        label,
+                        value
+                    ))
+                })
+            })?)
+        }
+        "filter" => {
+            Expression::all(each(values, |value| {
+                Expression::parse(value).map_err(|err| err_msg(format!("{}: {}", label, err)))
+            })?)
+        }
+        _ => {
+            return Err(err_msg(format!(
+                "Unknown filter option {}, expected one of {}",
+                label,
+                NAMES.join(", ")
+            )))
+        }
+    };
+
+    Ok(expression)
+}
+
+fn each<F>(values: &[&str], parse: F) -> Result<Vec<Expression>, Error>
+where
+    F: Fn(&str) -> Result<Expression, Error>,
+{
+    values.iter().map(|value| parse(value)).collect()
+}
+
+fn terms(values: &[&str]) -> Vec<Expression> {
+    values.iter().map(|value| Expression::line_contains(value)).collect()
+}
+
+/// Compile regular expressions once, before any lines are read.
+fn regexes(label: &str, values: &[&str]) -> Result<Vec<Expression>, Error> {
+    each(values, |value| match FieldRegex::parse(value) {
+        Ok(field_regex) => Ok(Expression::Matches(field_regex)),
+        Err(err) => Err(err_msg(format!("{} {} is invalid ({})", label, value, err))),
+    })
+}
+
+fn duration(label: &str, value: &str) -> Result<chrono::Duration, Error> {
+    log_events::parse_duration(value).map_err(|_| {
+        err_msg(format!("{} must be a duration like 5s or 250ms ({})", label, value))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_message(name: &str, values: &[&str]) -> String {
+        match expression(name, &format!("--{}", name), values) {
+            Err(err) => format!("{}", err),
+            Ok(expression) => panic!("{} parsed as {:?}", name, expression),
+        }
+    }
+
+    #[test]
+    fn test_expression() {
+        assert_eq!(
+            expression("exclude", "--exclude", &["/system/health", "/bin/ping"]).unwrap(),
+            Expression::any(vec![
+                Expression::line_contains("/system/health"),
+                Expression::line_contains("/bin/ping"),
+            ]).map(Expression::negate)
+        );
+        assert_eq!(
+            expression("min-duration", "--min-duration", &["1s"]).unwrap(),
+            Some(Expression::Duration(
+                Comparison::GreaterOrEqual,
+                chrono::Duration::seconds(1),
+            ))
+        );
+        assert_eq!(expression("status", "--status", &[]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_expression_errors() {
+        assert!(error_message("include-regex", &["path:[a-"]).contains(
+            "--include-regex path:[a- is invalid",
+        ));
+        assert!(error_message("status", &["5xx", "server-error"]).contains(
+            "--status must be status codes or classes like 404,410 or 5xx (server-error)",
+        ));
+        assert!(error_message("filter", &["status >="]).contains(
+            "--filter: Invalid filter at character 10",
+        ));
+        assert!(error_message("exlude", &["x"]).contains(
+            "Unknown filter option --exlude, expected one of include, exclude,",
+        ));
+    }
+}
@@ -0,0 +1,203 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use log::info;
+
+/// Reads lines appended to a file, like `tail -F`.
+///
+/// Only complete lines are returned, a line that is still being written is
+/// held back until its line break arrives. When there is no new data,
+/// `read` returns `Ok(0)` and can be called again later.
+///
+/// If the file is truncated, reading starts over at its beginning. If the
+/// file is replaced by a new one (log rotation), the rest of the old file is
+/// read and then the new file is read from its beginning.
+pub struct FollowReader {
+    filename: String,
+    file: File,
+    position: u64,
+    pending: Vec<u8>,
+    complete_until: usize,
+}
+
+impl FollowReader {
+    /// Open a file and start following it at its end.
+    pub fn open(filename: &str) -> io::Result<FollowReader> {
+        let mut file = File::open(filename)?;
+        let position = file.seek(SeekFrom::End(0))?;
+
+        Ok(FollowReader {
+            filename: String::from(filename),
+            file,
+            position,
+            pending: vec![],
+            complete_until: 0,
+        })
+    }
+
+    fn read_more(&mut self) -> io::Result<usize> {
+        let mut buffer = [0; 64 * 1024];
+        let read_size = self.file.read(&mut buffer)?;
+
+        self.position += read_size as u64;
+        self.pending.extend_from_slice(&buffer[..read_size]);
+
+        if let Some(line_end) = self.pending.iter().rposition(|byte| *byte == b'\n') {
+            self.complete_until = line_end + 1;
+        }
+
+        Ok(read_size)
+    }
+
+    /// Check for truncation or rotation after reaching the end of the file.
+    fn reopen_if_replaced(&mut self) -> io::Result<()> {
+        let current = match fs::metadata(&self.filename) {
+            Ok(metadata) => metadata,
+            // In the middle of a rotation, try again later
+            Err(_) => return Ok(()),
+        };
+
+        if is_other_file(&self.file.metadata()?, &current) {
+            info!("{} was rotated, reopening", self.filename);
+            self.file = File::open(&self.filename)?;
+            self.position = 0;
+            self.discard_incomplete_line();
+        } else if current.len() < self.position {
+            info!("{} was truncated, reading from the start", self.filename);
+            self.position = self.file.seek(SeekFrom::Start(0))?;
+            self.discard_incomplete_line();
+        }
+
+        Ok(())
+    }
+
+    fn discard_incomplete_line(&mut self) {
+        self.pending.clear();
+        self.complete_until = 0;
+    }
+}
+
+impl io::Read for FollowReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.complete_until == 0 && self.read_more()? == 0 {
+            self.reopen_if_replaced()?;
+
+            if self.read_more()? == 0 {
+                return Ok(0);
+            }
+        }
+
+        let size = buf.len().min(self.complete_until);
+        buf[..size].copy_from_slice(&self.pending[..size]);
+        self.pending.drain(..size);
+        self.complete_until -= size;
+
+        Ok(size)
+    }
+}
+
+#[cfg(unix)]
+fn is_other_file(open: &fs::Metadata, current: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    open.dev() != current.dev() || open.ino() != current.ino()
+}
+
+#[cfg(not(unix))]
+fn is_other_file(open: &fs::Metadata, current: &fs::Metadata) -> bool {
+    // Without inodes, a rotated file can only be told apart by its size
+    current.len() < open.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::temp_files::{append, temp_path};
+    use super::*;
+
+    fn read_available(reader: &mut FollowReader) -> String {
+        let mut text = vec![];
+        let mut buffer = [0; 16];
+
+        loop {
+            match reader.read(&mut buffer).unwrap() {
+                0 => break,
+                size => text.extend_from_slice(&buffer[..size]),
+            }
+        }
+
+        String::from_utf8(text).unwrap()
+    }
+
+    #[test]
+    fn test_follow_from_end() {
+        let path = temp_path("follow-end.log");
+        append(&path, "old line\n");
+
+        let mut reader = FollowReader::open(&path).unwrap();
+        assert_eq!(read_available(&mut reader), "");
+
+        append(&path, "new line\n");
+        assert_eq!(read_available(&mut reader), "new line\n");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_follow_holds_back_incomplete_lines() {
+        let path = temp_path("follow-incomplete.log");
+        append(&path, "");
+
+        let mut reader = FollowReader::open(&path).unwrap();
+        append(&path, "first\nsec");
+        assert_eq!(read_available(&mut reader), "first\n");
+
+        append(&path, "ond\nthi");
+        assert_eq!(read_available(&mut reader), "second\n");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_follow_truncation() {
+        let path = temp_path("follow-truncation.log");
+        append(&path, "");
+
+        let mut reader = FollowReader::open(&path).unwrap();
+        append(&path, "a long line before truncation\n");
+        assert_eq!(read_available(&mut reader), "a long line before truncation\n");
+
+        File::create(&path).unwrap();
+        append(&path, "short\n");
+        assert_eq!(read_available(&mut reader), "short\n");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_follow_rotation() {
+        let path = temp_path("follow-rotation.log");
+        let rotated_path = temp_path("follow-rotation-rotated.log");
+        append(&path, "");
+
+        let mut reader = FollowReader::open(&path).unwrap();
+        append(&path, "before rotation\n");
+        assert_eq!(read_available(&mut reader), "before rotation\n");
+
+        append(&path, "late line in old file\n");
+        fs::rename(&path, &rotated_path).unwrap();
+        assert_eq!(read_available(&mut reader), "late line in old file\n");
+
+        append(&path, "after rotation, a longer line\n");
+        assert_eq!(read_available(&mut reader), "after rotation, a longer line\n");
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&rotated_path).unwrap();
+    }
+
+    #[test]
+    fn test_follow_non_existent() {
+        assert!(FollowReader::open("src/test/non-existent.log").is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use std::time::Duration;
+use failure::{Error, err_msg};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::{Method, Request, StatusCode};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+
+/// Sends the data of renderers over HTTP or HTTPS, blocking until the
+/// response arrives.
+///
+/// Connections are kept alive between requests, like the ones of every
+/// `--follow-interval`.
+pub struct HttpClient {
+    runtime: tokio::runtime::Runtime,
+    client: Client<HttpsConnector<HttpConnector>, Full<Bytes>>,
+    timeout: Duration,
+}
+
+impl HttpClient {
+    /// Requests fail if they are not answered within `timeout`.
+    pub fn new(timeout: Duration) -> HttpClient {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to start the HTTP client.");
+
+        let connector = HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+
+        HttpClient {
+            runtime,
+            client: Client::builder(TokioExecutor::new()).build(connector),
+            timeout,
+        }
+    }
+
+    /// Send `body` to `url`, returning the status of the response.
+    pub fn send(
+        &self,
+        method: Method,
+        url: &str,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<StatusCode, Error> {
+        let request = Request::builder()
+            .method(method)
+            .uri(url)
+            .header(hyper::header::CONTENT_TYPE, content_type)
+            .body(Full::new(Bytes::from(body)))?;
+
+        let response = async {
+            let response = self.client.request(request).await?;
+            let status = response.status();
+
+            // Read the whole response, so the connection can be used again
+            response.into_body().collect().await?;
+
+            Ok(status)
+        };
+
+        self.runtime.block_on(async {
+            match tokio::time::timeout(self.timeout, response).await {
+                Ok(result) => result,
+                Err(_) => {
+                    Err(err_msg(
+                        format!("{} did not answer within {:?}", url, self.timeout),
+                    ))
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use super::*;
+
+    /// A server accepting one connection, which answers every request on it
+    /// with `status_line` after `delay`.
+    fn server(status_line: &'static str, delay: Duration) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/write", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+
+            loop {
+                let mut line = String::new();
+
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+
+                // The requests of the tests have no body
+                if line == "\r\n" {
+                    thread::sleep(delay);
+                    let response = format!("{}\r\nContent-Length: 2\r\n\r\nok", status_line);
+                    let _ = reader.get_mut().write_all(response.as_bytes());
+                }
+            }
+        });
+
+        url
+    }
+
+    #[test]
+    fn test_send() {
+        let url = server("HTTP/1.1 202 Accepted", Duration::from_millis(0));
+        let client = HttpClient::new(Duration::from_secs(5));
+
+        // Both requests use the only connection the server accepts
+        for _ in 0..2 {
+            assert_eq!(
+                client.send(Method::POST, &url, "text/plain", vec![]).unwrap(),
+                StatusCode::ACCEPTED
+            );
+        }
+    }
+
+    #[test]
+    fn test_send_timeout() {
+        let url = server("HTTP/1.1 200 OK", Duration::from_secs(2));
+        let client = HttpClient::new(Duration::from_millis(100));
+
+        let error = client.send(Method::POST, &url, "text/plain", vec![]).unwrap_err();
+        assert_eq!(format!("{}", error), format!("{} did not answer within 100ms", url));
+    }
+
+    #[test]
+    fn test_send_unreachable() {
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/write", listener.local_addr().unwrap())
+        };
+
+        let client = HttpClient::new(Duration::from_secs(5));
+        assert!(client.send(Method::PUT, &url, "text/plain", vec![]).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use crate::config::ExporterConfig;
+
+/// Whether the `Authorization` header of a request matches the basic auth
+/// or the bearer token of `config`. Without either, all requests are.
+pub fn authorized(config: &ExporterConfig, authorization: Option<&[u8]>) -> bool {
+    if !config.needs_auth() {
+        return true;
+    }
+
+    let authorization = match authorization.and_then(|value| std::str::from_utf8(value).ok()) {
+        Some(authorization) => authorization.trim(),
+        None => return false,
+    };
+
+    let (scheme, credentials) = authorization.split_once(' ').unwrap_or((authorization, ""));
+    let credentials = credentials.trim();
+
+    if scheme.eq_ignore_ascii_case("basic") {
+        let (user, password) = match config.basic_auth {
+            Some((ref user, ref password)) => (user, password),
+            None => return false,
+        };
+
+        match STANDARD.decode(credentials) {
+            Ok(decoded) => {
+                let expected = format!("{}:{}", user, password);
+                constant_time_eq(&decoded, expected.as_bytes())
+            }
+            Err(_) => false,
+        }
+    } else if scheme.eq_ignore_ascii_case("bearer") {
+        match config.bearer_token {
+            Some(ref token) => constant_time_eq(credentials.as_bytes(), token.as_bytes()),
+            None => false,
+        }
+    } else {
+        false
+    }
+}
+
+/// The `WWW-Authenticate` header of a 401 response, asking browsers for a
+/// user and password if basic auth is configured.
+pub fn challenge(config: &ExporterConfig) -> &'static str {
+    if config.basic_auth.is_some() {
+        "Basic realm=\"request_log_analyzer\", charset=\"UTF-8\""
+    } else {
+        "Bearer realm=\"request_log_analyzer\""
+    }
+}
+
+/// Compare secrets without returning at the first difference, which would
+/// tell how much of a guess was right by the response time.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(basic_auth: bool, bearer_token: bool) -> ExporterConfig {
+        ExporterConfig {
+            tls: None,
+            basic_auth: if basic_auth {
+                Some((String::from("prometheus"), String::from("s3cr3t")))
+            } else {
+                None
+            },
+            bearer_token: if bearer_token {
+                Some(String::from("abc.def"))
+            } else {
+                None
+            },
+        }
+    }
+
+    #[test]
+    fn test_authorized() {
+        // prometheus:s3cr3t
+        let basic = Some(&b"Basic cHJvbWV0aGV1czpzM2NyM3Q="[..]);
+        let bearer = Some(&b"Bearer abc.def"[..]);
+
+        assert!(authorized(&config(false, false), None));
+        assert!(authorized(&config(true, false), basic));
+        assert!(authorized(&config(true, true), basic));
+        assert!(authorized(&config(true, true), bearer));
+        assert!(authorized(&config(false, true), Some(&b"bearer abc.def"[..])));
+
+        assert!(!authorized(&config(true, false), None));
+        assert!(!authorized(&config(true, false), bearer));
+        assert!(!authorized(&config(false, true), basic));
+        // prometheus:secret
+        assert!(!authorized(&config(true, false), Some(&b"Basic cHJvbWV0aGV1czpzZWNyZXQ="[..])));
+        assert!(!authorized(&config(true, false), Some(&b"Basic not base64!"[..])));
+        assert!(!authorized(&config(false, true), Some(&b"Bearer abc"[..])));
+        assert!(!authorized(&config(false, true), Some(&b"abc.def"[..])));
+    }
+
+    #[test]
+    fn test_challenge() {
+        assert!(challenge(&config(true, true)).starts_with("Basic "));
+        assert!(challenge(&config(false, true)).starts_with("Bearer "));
+    }
+}
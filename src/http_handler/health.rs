@@ -0,0 +1,82 @@
+use std::fs::File;
+use std::time::{Duration, SystemTime};
+
+/// Check that the log files can be read, and that the latest modified one
+/// was modified within `max_age`, so a stopped or rotated away log is noticed.
+///
+/// Returns a line about each file, as `Err` if the check failed.
+pub fn check(filenames: &[String], max_age: Duration, now: SystemTime) -> Result<String, String> {
+    let mut lines = vec![];
+    let mut healthy = true;
+    let mut latest_age: Option<Duration> = None;
+
+    for filename in filenames.iter().filter(|filename| *filename != "-") {
+        let modified = File::open(filename)
+            .and_then(|file| file.metadata())
+            .and_then(|metadata| metadata.modified());
+
+        match modified {
+            Ok(modified) => {
+                let age = now.duration_since(modified).unwrap_or_default();
+                lines.push(format!("{}: modified {}s ago", filename, age.as_secs()));
+                latest_age = Some(latest_age.map_or(age, |latest| latest.min(age)));
+            }
+            Err(err) => {
+                lines.push(format!("{}: can not be read ({})", filename, err));
+                healthy = false;
+            }
+        }
+    }
+
+    match latest_age {
+        Some(age) if age > max_age => {
+            lines.push(format!("no log file was modified within {}s", max_age.as_secs()));
+            healthy = false;
+        }
+        None if healthy && lines.is_empty() => lines.push(String::from("reading from stdin")),
+        _ => {}
+    }
+
+    let report = format!("{}\n", lines.join("\n"));
+
+    if healthy { Ok(report) } else { Err(report) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    const LOG: &str = "src/test/simple-1.log";
+
+    fn modified() -> SystemTime {
+        fs::metadata(LOG).unwrap().modified().unwrap()
+    }
+
+    #[test]
+    fn test_check() {
+        let filenames = vec![String::from(LOG)];
+        let now = modified() + Duration::from_secs(10);
+
+        assert_eq!(
+            check(&filenames, Duration::from_secs(300), now),
+            Ok(String::from("src/test/simple-1.log: modified 10s ago\n"))
+        );
+        assert_eq!(
+            check(&filenames, Duration::from_secs(5), now),
+            Err(String::from(
+                "src/test/simple-1.log: modified 10s ago\n\
+                 no log file was modified within 5s\n",
+            ))
+        );
+    }
+
+    #[test]
+    fn test_check_unreadable() {
+        let filenames = vec![String::from(LOG), String::from("src/test/missing.log")];
+        let report = check(&filenames, Duration::from_secs(300), modified()).unwrap_err();
+
+        assert!(report.starts_with("src/test/simple-1.log: modified 0s ago\n"));
+        assert!(report.contains("src/test/missing.log: can not be read ("));
+    }
+}
@@ -1,53 +1,523 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
 use crate::args;
+use crate::follow;
 use crate::result;
-use log::info;
-use hyper;
-use prometheus::Encoder;
-use crate::render;
+use failure::Error;
+use log::{info, warn};
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE};
+use hyper::header::HeaderValue;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::{TokioIo, TokioTimer};
+use hyper_util::server::graceful::{GracefulShutdown, Watcher};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::task::JoinError;
+use tokio_rustls::TlsAcceptor;
 use crate::render::Renderer;
-use crate::run;
+use crate::render::json::JsonRenderer;
+use crate::render::prometheus::Format;
+use crate::{prometheus_renderer, run};
+
+mod auth;
+mod health;
+mod query;
+mod tailer;
+mod tls;
+
+type Body = Full<Bytes>;
+
+/// Where the metrics and summaries of a request come from.
+enum Metrics {
+    /// Analyze the logs again for every request
+    Run(fn(&args::RequestLogAnalyzerArgs) -> Result<result::RequestLogAnalyzerResult, Error>),
+    /// Serve what a background tailer keeps up to date
+    Tailed(Arc<Mutex<tailer::Tailed>>),
+}
 
 struct HttpHandler {
     args: args::RequestLogAnalyzerArgs,
-    run: fn(&args::RequestLogAnalyzerArgs) -> result::RequestLogAnalyzerResult,
+    metrics: Metrics,
+    /// A single permit for analyzing the logs, which is only given back once
+    /// an analysis is finished, even if its request has timed out
+    analysis: Arc<Semaphore>,
 }
 
-impl hyper::server::Handler for HttpHandler {
-    fn handle(&self, _: hyper::server::Request, mut res: hyper::server::Response) {
-        let result = (self.run)(&self.args);
+impl HttpHandler {
+    fn new(args: args::RequestLogAnalyzerArgs, metrics: Metrics) -> HttpHandler {
+        HttpHandler {
+            args,
+            metrics,
+            analysis: Arc::new(Semaphore::new(1)),
+        }
+    }
 
-        let mut renderer = render::prometheus::PrometheusRenderer::new();
-        renderer.render(result);
-        res.headers_mut().set(hyper::header::ContentType(
-            renderer
-                .encoder
-                .format_type()
-                .parse::<hyper::mime::Mime>()
-                .unwrap(),
-        ));
-        res.send(&renderer.buffer).unwrap();
+    /// Whether answering a request to `path` analyzes the logs.
+    fn analyzes(&self, path: &str) -> bool {
+        match self.metrics {
+            Metrics::Run(_) => path == "/metrics" || path == "/api/summary",
+            Metrics::Tailed(_) => false,
+        }
+    }
+
+    fn route(&self, path: &str, query: &str, accept: &str) -> Response<Body> {
+        match path {
+            "/metrics" => self.metrics(query, negotiate(accept)),
+            "/api/summary" => self.summary(query),
+            "/healthz" => self.health(),
+            _ => {
+                respond(
+                    StatusCode::NOT_FOUND,
+                    "Not found, try /metrics, /api/summary or /healthz",
+                )
+            }
+        }
+    }
+
+    fn metrics(&self, query: &str, format: Format) -> Response<Body> {
+        match self.metrics {
+            Metrics::Run(run) => {
+                let args = match query::scrape_args(&self.args, query) {
+                    Ok(args) => args,
+                    Err(err) => return bad_request(&err.to_string()),
+                };
+
+                let sources = args.sources();
+
+                // The sources add up in the metric families of one renderer
+                let mut renderer = prometheus_renderer(&sources[0], true);
+                renderer.format = format;
+
+                for source in &sources {
+                    match run(source) {
+                        Ok(result) => renderer.render(result),
+                        Err(err) => return analysis_failed(&err),
+                    }
+                }
+
+                send(&renderer.content_type(), &renderer.buffer)
+            }
+            Metrics::Tailed(_) if !query.is_empty() => tailed_query(),
+            Metrics::Tailed(ref tailed) => {
+                let renderer = &mut tailed.lock().unwrap().renderer;
+                renderer.format = format;
+                renderer.encode();
+                send(&renderer.content_type(), &renderer.buffer)
+            }
+        }
+    }
+
+    /// The results like in the terminal as JSON, with `--follow` those of the
+    /// latest `--follow-interval`. With sources, there is one by the name of
+    /// each.
+    fn summary(&self, query: &str) -> Response<Body> {
+        let mut summaries: Vec<result::RequestLogAnalyzerResult> = match self.metrics {
+            Metrics::Run(run) => {
+                let args = match query::scrape_args(&self.args, query) {
+                    Ok(args) => args,
+                    Err(err) => return bad_request(&err.to_string()),
+                };
+
+                match args.sources().iter().map(run).collect() {
+                    Ok(summaries) => summaries,
+                    Err(err) => return analysis_failed(&err),
+                }
+            }
+            Metrics::Tailed(_) if !query.is_empty() => return tailed_query(),
+            Metrics::Tailed(ref tailed) => tailed.lock().unwrap().summaries.clone(),
+        };
+
+        let mut renderer = JsonRenderer::new(self.args.time_unit);
+
+        if self.args.prometheus_sources.is_empty() {
+            renderer.render(summaries.remove(0));
+        } else {
+            let names = self.args.prometheus_sources.iter().map(|(name, _)| name.clone());
+            renderer.render_sources(names.zip(summaries).collect());
+        }
+
+        send("application/json", &renderer.buffer)
+    }
+
+    /// Healthy if the log files of every source are.
+    fn health(&self) -> Response<Body> {
+        let now = SystemTime::now();
+        let mut healthy = true;
+        let mut report = String::new();
+
+        for source in self.args.sources() {
+            let lines = match health::check(&source.filenames, self.args.healthz_max_age, now) {
+                Ok(lines) => lines,
+                Err(lines) => {
+                    healthy = false;
+                    lines
+                }
+            };
+
+            match source.grouping.source {
+                Some(ref name) => {
+                    for line in lines.lines() {
+                        report.push_str(&format!("{}: {}\n", name, line));
+                    }
+                }
+                None => report.push_str(&lines),
+            }
+        }
+
+        if healthy {
+            respond(StatusCode::OK, &report)
+        } else {
+            respond(StatusCode::SERVICE_UNAVAILABLE, &report)
+        }
     }
 }
 
-pub fn listen_http(args: args::RequestLogAnalyzerArgs, binding_address: &str) {
-    let handler = HttpHandler { args, run };
+/// Answer `req` on a blocking thread, as analyzing the logs for it reads
+/// files, giving up after `timeout`. `/healthz` is answered without
+/// credentials, for liveness probes.
+///
+/// The logs are analyzed for one request at a time, others wait for their
+/// turn within `timeout`. An analysis whose request has timed out still runs
+/// to its end, but no more analyses pile up behind it.
+async fn handle(
+    handler: Arc<HttpHandler>,
+    req: Request<Incoming>,
+    timeout: Duration,
+) -> Result<Response<Body>, Infallible> {
+    let authorization = req.headers().get(AUTHORIZATION).map(HeaderValue::as_bytes);
+
+    if req.uri().path() != "/healthz" &&
+        !auth::authorized(&handler.args.exporter, authorization)
+    {
+        let mut response = respond(StatusCode::UNAUTHORIZED, "Unauthorized");
+        response.headers_mut().insert(
+            WWW_AUTHENTICATE,
+            HeaderValue::from_static(auth::challenge(&handler.args.exporter)),
+        );
+        return Ok(response);
+    }
+
+    let path = String::from(req.uri().path());
+    let query = String::from(req.uri().query().unwrap_or(""));
+    let accept: Vec<String> = req.headers()
+        .get_all(ACCEPT)
+        .iter()
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+        .collect();
+
+    let answer = answer(handler, path, query, accept.join(","));
+
+    Ok(match tokio::time::timeout(timeout, answer).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(err)) => {
+            respond(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Answering the request failed: {}", err),
+            )
+        }
+        Err(_) => {
+            respond(
+                StatusCode::SERVICE_UNAVAILABLE,
+                &format!("No answer within {:?}, try a larger --http-timeout", timeout),
+            )
+        }
+    })
+}
+
+/// Route a request on a blocking thread, once it may analyze the logs.
+async fn answer(
+    handler: Arc<HttpHandler>,
+    path: String,
+    query: String,
+    accept: String,
+) -> Result<Response<Body>, JoinError> {
+    let permit = if handler.analyzes(&path) {
+        Some(handler.analysis.clone().acquire_owned().await.expect(
+            "The analysis semaphore is never closed.",
+        ))
+    } else {
+        None
+    };
+
+    tokio::task::spawn_blocking(move || {
+        let response = handler.route(&path, &query, &accept);
+        drop(permit);
+        response
+    }).await
+}
+
+/// HTTP/1 connections which have to send their request headers within
+/// `timeout`.
+fn builder(timeout: Duration) -> http1::Builder {
+    let mut builder = http1::Builder::new();
+    builder.timer(TokioTimer::new()).header_read_timeout(timeout).title_case_headers(true);
+    builder
+}
 
-    info!("listening addr {:?}", binding_address);
-    hyper::server::Server::http(binding_address)
+fn send(content_type: &str, body: &[u8]) -> Response<Body> {
+    Response::builder()
+        .header(CONTENT_TYPE, content_type)
+        .body(Full::new(Bytes::copy_from_slice(body)))
         .unwrap()
-        .handle(handler)
-        .unwrap();
+}
+
+/// Respond with a text message.
+fn respond(status: StatusCode, message: &str) -> Response<Body> {
+    let body = format!("{}\n", message.trim_end());
+    let mut response = send("text/plain; charset=utf-8", body.as_bytes());
+    *response.status_mut() = status;
+    response
+}
+
+fn tailed_query() -> Response<Body> {
+    bad_request(
+        "Query parameters need the log to be analyzed for every request, \
+         which --follow does not do",
+    )
+}
+
+fn bad_request(message: &str) -> Response<Body> {
+    respond(StatusCode::BAD_REQUEST, message)
+}
+
+/// The logs could not be read, e.g. because a file was moved away. The next
+/// scrape tries again.
+fn analysis_failed(err: &Error) -> Response<Body> {
+    respond(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        &format!("Analyzing the logs failed: {}", err),
+    )
+}
+
+/// The OpenMetrics format if the scraper prefers it to the text format, as
+/// Prometheus does with exemplar storage enabled.
+fn negotiate(accept: &str) -> Format {
+    let mut openmetrics_quality: f32 = 0.0;
+    let mut text_quality: f32 = 0.0;
+
+    for media_range in accept.split(',') {
+        let mut params = media_range.split(';').map(str::trim);
+        let media_type = params.next().unwrap_or("").to_lowercase();
+        let quality = params
+            .find_map(|param| param.strip_prefix("q="))
+            .and_then(|quality| quality.parse().ok())
+            .unwrap_or(1.0);
+
+        match media_type.as_str() {
+            "application/openmetrics-text" => {
+                openmetrics_quality = openmetrics_quality.max(quality)
+            }
+            "text/plain" | "text/*" | "*/*" => text_quality = text_quality.max(quality),
+            _ => {}
+        }
+    }
+
+    if openmetrics_quality > 0.0 && openmetrics_quality >= text_quality {
+        Format::OpenMetrics
+    } else {
+        Format::Text
+    }
+}
+
+/// Serve the metrics on `/metrics`, a JSON summary on `/api/summary` and
+/// the state of the log file on `/healthz`. With `--follow`, the log file is
+/// tailed in the background, otherwise it is analyzed again for every
+/// request. Each `--prometheus-source` is analyzed or tailed on its own.
+///
+/// With a certificate in the exporter config it serves HTTPS, and with basic
+/// auth or a bearer token requests without them are rejected.
+///
+/// On SIGTERM or Ctrl-C no more connections are accepted, and open requests
+/// are finished within `--http-timeout`.
+pub fn listen_http(args: args::RequestLogAnalyzerArgs, binding_address: &str) {
+    let metrics = if args.follow {
+        let tailed = Arc::new(Mutex::new(tailer::Tailed::new(&args)));
+
+        for (index, source) in args.sources().into_iter().enumerate() {
+            let reader = match follow::FollowReader::open(&source.filenames[0]) {
+                Ok(reader) => reader,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                }
+            };
+
+            let tailer_tailed = tailed.clone();
+
+            thread::spawn(move || {
+                tailer::tail(reader, &source, &tailer_tailed, index)
+            });
+        }
+
+        Metrics::Tailed(tailed)
+    } else {
+        Metrics::Run(run)
+    };
+
+    let tls = match args.exporter.tls {
+        Some((ref cert, ref key)) => {
+            match tls::acceptor(cert, key) {
+                Ok(acceptor) => Some(acceptor),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                }
+            }
+        }
+        None => None,
+    };
+
+    if tls.is_none() && args.exporter.needs_auth() {
+        warn!("The exporter has no TLS certificate, its credentials are sent in plain text");
+    }
+
+    let timeout = args.http_timeout;
+    let handler = Arc::new(HttpHandler::new(args, metrics));
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start the HTTP server.");
+
+    runtime.block_on(async {
+        let listener = match TcpListener::bind(binding_address).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                eprintln!("Could not listen on {}: {}", binding_address, err);
+                process::exit(1);
+            }
+        };
+
+        info!("listening addr {:?}", binding_address);
+        serve(handler, listener, tls, timeout, shutdown_signal()).await;
+    });
+
+    // Requests that timed out may still be analyzing the logs
+    runtime.shutdown_background();
+}
+
+/// Answer the connections of `listener` until `shutdown` completes, then
+/// wait up to `timeout` for the open requests.
+async fn serve<F>(
+    handler: Arc<HttpHandler>,
+    listener: TcpListener,
+    tls: Option<TlsAcceptor>,
+    timeout: Duration,
+    shutdown: F,
+) where
+    F: Future<Output = ()>,
+{
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        warn!("Accepting a connection failed: {}", err);
+                        continue;
+                    }
+                };
+
+                tokio::spawn(accept(
+                    handler.clone(),
+                    stream,
+                    tls.clone(),
+                    timeout,
+                    graceful.watcher(),
+                ));
+            }
+            _ = &mut shutdown => break,
+        }
+    }
+
+    drop(listener);
+
+    if tokio::time::timeout(timeout, graceful.shutdown()).await.is_err() {
+        warn!("Open requests were not finished within {:?}", timeout);
+    }
+}
+
+/// Serve a connection, after the TLS handshake if `tls` is given.
+async fn accept(
+    handler: Arc<HttpHandler>,
+    stream: TcpStream,
+    tls: Option<TlsAcceptor>,
+    timeout: Duration,
+    watcher: Watcher,
+) {
+    let tls = match tls {
+        Some(tls) => tls,
+        None => return serve_connection(handler, TokioIo::new(stream), timeout, watcher).await,
+    };
+
+    match tokio::time::timeout(timeout, tls.accept(stream)).await {
+        Ok(Ok(stream)) => serve_connection(handler, TokioIo::new(stream), timeout, watcher).await,
+        Ok(Err(err)) => info!("TLS handshake failed: {}", err),
+        Err(_) => info!("No TLS handshake within {:?}", timeout),
+    }
+}
+
+async fn serve_connection<I>(handler: Arc<HttpHandler>, io: I, timeout: Duration, watcher: Watcher)
+where
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+{
+    let connection = builder(timeout).serve_connection(
+        io,
+        service_fn(move |req| handle(handler.clone(), req, timeout)),
+    );
+
+    if let Err(err) = watcher.watch(connection).await {
+        info!("Connection failed: {}", err);
+    }
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate =
+        signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM.");
+
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+
+    info!("shutting down");
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() {
+    tokio::signal::ctrl_c().await.expect("Failed to listen for Ctrl-C.");
+    info!("shutting down");
 }
 
 #[cfg(test)]
 mod tests {
+    use std::convert::TryFrom;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
     use std::str;
-    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-    use hyper::server::Handler;
-    use hyper;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::oneshot;
+    use tokio_rustls::rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+    use tokio_rustls::rustls::crypto::ring;
+    use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+    use tokio_rustls::rustls::pki_types::pem::PemObject;
 
     use crate::filter;
     use crate::analyzer;
+    use crate::log_parser::log_events::{Request, Response};
+    use crate::multi_file;
+    use crate::request_response_matcher::RequestResponsePair;
     use super::*;
 
     mod mock;
@@ -57,71 +527,480 @@ mod tests {
         let args = args::RequestLogAnalyzerArgs {
             filenames: vec![String::from("src/test/simple-1.log")],
             conditions: filter::FilterConditions {
-                include_terms: None,
-                exclude_terms: None,
+                expression: None,
                 latest_time: None,
+                since: None,
+                until: None,
+                relative_to: filter::TimeAnchor::Now,
             },
             graphite_server: None,
             graphite_port: Some(2003),
             graphite_prefix: None,
             prometheus_listen: None,
+            prometheus_buckets: prometheus::DEFAULT_BUCKETS.to_vec(),
+            prometheus_legacy_gauges: true,
+            prometheus_labels: vec![],
+            prometheus_pushgateway: None,
+            prometheus_job: String::from("request_log_analyzer"),
+            prometheus_grouping: vec![],
+            prometheus_sources: vec![],
+            healthz_max_age: std::time::Duration::from_secs(300),
+            http_timeout: std::time::Duration::from_secs(30),
+            exporter: crate::config::ExporterConfig::default(),
+            grouping: analyzer::grouping::Grouping::default(),
             influxdb_write_url: None,
             influxdb_tags: None,
             quiet: false,
+            time_unit: analyzer::TimeUnit::Milliseconds,
+            jobs: 1,
+            follow: false,
+            follow_interval: std::time::Duration::from_secs(60),
+            state_file: None,
+            read_error_policy: multi_file::ErrorPolicy::Fail,
         };
 
-        fn run_fn(_: &args::RequestLogAnalyzerArgs) -> result::RequestLogAnalyzerResult {
-            result::RequestLogAnalyzerResult {
+        fn run_fn(
+            _: &args::RequestLogAnalyzerArgs,
+        ) -> Result<result::RequestLogAnalyzerResult, Error> {
+            Ok(result::RequestLogAnalyzerResult {
                 count: 3,
                 timing: Some(analyzer::TimingResult {
-                    max: 100,
-                    min: 1,
-                    avg: 37,
-                    median: 10,
-                    percentile90: 90,
-                    percentile99: 99,
+                    max: 100.0,
+                    min: 1.0,
+                    avg: 37.0,
+                    median: 10.0,
+                    percentile90: 90.0,
+                    percentile99: 99.0,
                     count: 3,
                 }),
                 error: None,
-            }
+                distribution: analyzer::distribution::Distribution::default(),
+                files: vec![],
+            })
+        };
+
+        let handler = Arc::new(HttpHandler::new(args, Metrics::Run(run_fn)));
+
+        let result = scrape(&handler);
+
+        assert!(result.contains("request_count 3"));
+        assert!(result.contains("request_time_max 100"));
+        assert!(result.contains("request_time_min 1"));
+        assert!(result.contains("request_time_avg 37"));
+        assert!(result.contains("request_time_median 10"));
+        assert!(result.contains("request_time_percentile90 90"));
+        assert!(result.contains("request_time_percentile99 99"));
+        assert!(result.contains("window_requests_by_duration{le=\"+Inf\"} 0"));
+
+    }
+
+    #[test]
+    fn test_handle_tailed() {
+        let args = args::parse_args(vec![String::from("request_log_analyzer")]).unwrap();
+        let tailed = Arc::new(Mutex::new(tailer::Tailed::new(&args)));
+
+        let handler = Arc::new(HttpHandler::new(args, Metrics::Tailed(tailed.clone())));
+
+        assert!(scrape(&handler).contains("request_duration_seconds_count 0"));
+
+        let pair = RequestResponsePair {
+            request: Request::new_from_log_line(
+                "08/Apr/2016:09:57:47 +0200 [001] -> GET /content/some/page.html HTTP/1.1",
+            ).unwrap(),
+            response: Response::new_from_log_line(
+                "08/Apr/2016:09:57:47 +0200 [001] <- 200 text/html 7ms",
+            ).unwrap(),
         };
+        tailed.lock().unwrap().renderer.observe(&pair);
+
+        let result = scrape(&handler);
+        assert!(result.contains("request_duration_seconds_count 1"));
+        assert!(result.contains("requests_total{status=\"200\"} 1"));
+        assert_eq!(result.matches("# TYPE requests_total").count(), 1);
+    }
 
-        let handler = HttpHandler {
-            args: args,
-            run: run_fn,
+    #[test]
+    fn test_handle_openmetrics() {
+        let mut args = args::parse_args(vec![String::from("request_log_analyzer")]).unwrap();
+        args.prometheus_buckets = vec![0.005, 0.01];
+        let tailed = Arc::new(Mutex::new(tailer::Tailed::new(&args)));
+
+        let handler = Arc::new(HttpHandler::new(args, Metrics::Tailed(tailed.clone())));
+
+        let pair = RequestResponsePair {
+            request: Request::new_from_log_line(
+                "08/Apr/2016:09:57:47 +0200 [042] -> GET /content/some/page.html HTTP/1.1",
+            ).unwrap(),
+            response: Response::new_from_log_line(
+                "08/Apr/2016:09:57:47 +0200 [042] <- 200 text/html 7ms",
+            ).unwrap(),
         };
+        tailed.lock().unwrap().renderer.observe(&pair);
 
-        // Create a minimal HTTP request
-        let mut request_mock_network_stream =
-            mock::MockStream::with_input(b"GET / HTTP/1.0\r\n\r\n");
+        let result = scrape_request(
+            &handler,
+            b"GET /metrics HTTP/1.0\r\nAccept: application/openmetrics-text;version=1.0.0,\
+              text/plain;version=0.0.4;q=0.5,*/*;q=0.1\r\n\r\n",
+        );
 
-        let mut reader = hyper::buffer::BufReader::new(
-            &mut request_mock_network_stream as &mut dyn hyper::net::NetworkStream,
+        assert!(result.contains(
+            "Content-Type: application/openmetrics-text; version=1.0.0; charset=utf-8",
+        ));
+        assert!(result.contains(
+            "request_duration_seconds_bucket{le=\"0.01\"} 1 \
+             # {request_id=\"42\",path=\"/content/some/page.html\"} 0.007 1460102267.000\n",
+        ));
+        assert!(result.contains("# TYPE requests counter\n"));
+        assert!(result.contains("requests_total{status=\"200\"} 1\n"));
+        assert!(result.ends_with("# EOF\n"));
+
+        let result = scrape(&handler);
+        assert!(result.contains("Content-Type: text/plain; version=0.0.4"));
+        assert!(!result.contains("# {request_id"));
+    }
+
+    #[test]
+    fn test_handle_query() {
+        let args = args::parse_args(vec![
+            String::from("request_log_analyzer"),
+            String::from("--prometheus-listen"),
+            String::from("localhost:9898"),
+            String::from("src/test/simple-1.log"),
+        ]).unwrap();
+
+        let handler = Arc::new(HttpHandler::new(args, Metrics::Run(run)));
+
+        let result = scrape_request(
+            &handler,
+            b"GET /metrics?include=other&group_by=method HTTP/1.0\r\n\r\n",
         );
+        assert!(result.contains("# TYPE window_requests_by_duration gauge\n"));
+        assert!(result.contains("window_requests{method=\"GET\",status=\"200\"} 1\n"));
+        assert!(result.contains("window_requests_by_duration{le=\"+Inf\",method=\"GET\"} 1\n"));
 
-        let socket = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 8080);
+        let result = scrape_request(&handler, b"GET /metrics?minutes=soon HTTP/1.0\r\n\r\n");
+        assert!(result.starts_with("HTTP/1.0 400 Bad Request"), "{}", result);
+        assert!(result.ends_with("minutes must be a number of minutes (soon)\n"));
+    }
 
-        let request = hyper::server::Request::new(&mut reader, socket).unwrap();
+    #[test]
+    fn test_handle_tailed_query() {
+        let args = args::parse_args(vec![String::from("request_log_analyzer")]).unwrap();
+        let tailed = Arc::new(Mutex::new(tailer::Tailed::new(&args)));
 
-        let mut headers = hyper::header::Headers::new();
-        let mut response_mock_network_stream = mock::MockStream::new();
+        let handler = Arc::new(HttpHandler::new(args, Metrics::Tailed(tailed)));
 
-        {
-            let response =
-                hyper::server::Response::new(&mut response_mock_network_stream, &mut headers);
+        let result = scrape_request(&handler, b"GET /metrics?minutes=5 HTTP/1.0\r\n\r\n");
+        assert!(result.starts_with("HTTP/1.0 400 Bad Request"), "{}", result);
+    }
+
+    #[test]
+    fn test_handle_routes() {
+        let mut args = args::parse_args(vec![
+            String::from("request_log_analyzer"),
+            String::from("src/test/simple-1.log"),
+        ]).unwrap();
+        args.healthz_max_age = std::time::Duration::from_secs(u32::MAX.into());
+
+        let handler = Arc::new(HttpHandler::new(args.clone(), Metrics::Run(run)));
+
+        let result =
+            scrape_request(&handler, b"GET /api/summary?include=other HTTP/1.0\r\n\r\n");
+        assert!(result.starts_with("HTTP/1.0 200 OK"));
+        assert!(result.contains("Content-Type: application/json"));
+        assert!(result.contains(
+            "\r\n\r\n{\"count\":1,\"time_unit\":\"ms\",\"time\":{\"avg\":10,",
+        ));
+
+        let result = scrape_request(&handler, b"GET /healthz HTTP/1.0\r\n\r\n");
+        assert!(result.starts_with("HTTP/1.0 200 OK"), "{}", result);
+        assert!(result.contains("src/test/simple-1.log: modified "));
+
+        let result = scrape_request(&handler, b"GET / HTTP/1.0\r\n\r\n");
+        assert!(result.starts_with("HTTP/1.0 404 Not Found"));
+
+        args.filenames.push(String::from("src/test/missing.log"));
+        let handler = Arc::new(HttpHandler::new(args, Metrics::Run(run)));
+
+        let result = scrape_request(&handler, b"GET /healthz HTTP/1.0\r\n\r\n");
+        assert!(result.starts_with("HTTP/1.0 503 Service Unavailable"));
+        assert!(result.contains("src/test/missing.log: can not be read"));
+
+        // The server keeps running, e.g. until a moved log file is back
+        let result = scrape(&handler);
+        assert!(result.starts_with("HTTP/1.0 500 Internal Server Error"));
+        assert!(result.contains("Analyzing the logs failed: Can not read src/test/missing.log: "));
+
+        let result = scrape_request(&handler, b"GET /api/summary HTTP/1.0\r\n\r\n");
+        assert!(result.starts_with("HTTP/1.0 500 Internal Server Error"));
+    }
+
+    #[test]
+    fn test_handle_tailed_summary() {
+        let args = args::parse_args(vec![String::from("request_log_analyzer")]).unwrap();
+        let tailed = Arc::new(Mutex::new(tailer::Tailed::new(&args)));
+
+        let handler = Arc::new(HttpHandler::new(args, Metrics::Tailed(tailed.clone())));
+
+        let result = scrape_request(&handler, b"GET /api/summary HTTP/1.0\r\n\r\n");
+        assert!(result.ends_with(
+            "{\"count\":0,\"time_unit\":\"ms\",\"time\":null,\"error\":null,\"input\":[]}\n",
+        ));
+
+        tailed.lock().unwrap().summaries[0].count = 7;
+
+        let result = scrape_request(&handler, b"GET /api/summary HTTP/1.0\r\n\r\n");
+        assert!(result.contains("{\"count\":7,"));
+    }
+
+    #[test]
+    fn test_handle_sources() {
+        let mut args = args::parse_args(vec![
+            String::from("request_log_analyzer"),
+            String::from("--prometheus-listen"),
+            String::from("localhost:9898"),
+            String::from("--prometheus-source"),
+            String::from("author=src/test/simple-1.log"),
+            String::from("--prometheus-source"),
+            String::from("publish=src/test/simple-2.log"),
+        ]).unwrap();
+        args.healthz_max_age = Duration::from_secs(u32::MAX.into());
+
+        let handler = Arc::new(HttpHandler::new(args.clone(), Metrics::Run(run)));
+
+        let result = scrape(&handler);
+        assert!(result.contains("window_requests_by_duration{le=\"+Inf\",source=\"author\"} 2\n"));
+        assert!(result.contains("window_requests{source=\"publish\",status=\"200\"} 2\n"));
+        assert_eq!(result.matches("# TYPE window_requests gauge").count(), 1);
+
+        let result = scrape_request(&handler, b"GET /api/summary HTTP/1.0\r\n\r\n");
+        assert!(result.contains("\r\n\r\n{\"author\":{\"count\":2,"));
+        assert!(result.contains("},\"publish\":{\"count\":2,"));
+
+        let result = scrape_request(&handler, b"GET /healthz HTTP/1.0\r\n\r\n");
+        assert!(result.starts_with("HTTP/1.0 200 OK"), "{}", result);
+        assert!(result.contains("\nauthor: src/test/simple-1.log: modified "));
+        assert!(result.contains("\npublish: src/test/simple-2.log: modified "));
+
+        args.follow = true;
+        let tailed = Arc::new(Mutex::new(tailer::Tailed::new(&args)));
+        let handler = Arc::new(HttpHandler::new(args, Metrics::Tailed(tailed.clone())));
+
+        tailed.lock().unwrap().summaries[1].count = 7;
+
+        let result = scrape_request(&handler, b"GET /api/summary HTTP/1.0\r\n\r\n");
+        assert!(result.contains("{\"author\":{\"count\":0,"));
+        assert!(result.contains("\"publish\":{\"count\":7,"));
+    }
+
+    #[test]
+    fn test_handle_timeout() {
+        let mut args = args::parse_args(vec![String::from("request_log_analyzer")]).unwrap();
+        args.http_timeout = Duration::from_millis(100);
+
+        let handler = Arc::new(HttpHandler::new(args, Metrics::Run(slow_run)));
+
+        let result = scrape(&handler);
+        assert!(result.starts_with("HTTP/1.0 503 Service Unavailable"), "{}", result);
+        assert!(result.ends_with("No answer within 100ms, try a larger --http-timeout\n"));
+    }
+
+    #[test]
+    fn test_answer_one_analysis_at_a_time() {
+        let args = args::parse_args(vec![String::from("request_log_analyzer")]).unwrap();
+        let handler = Arc::new(HttpHandler::new(args, Metrics::Run(counting_run)));
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async {
+            let scrapes: Vec<_> = (0..4)
+                .map(|_| {
+                    tokio::spawn(tokio::time::timeout(
+                        Duration::from_millis(50),
+                        answer(handler.clone(), String::from("/metrics"), String::new(), String::new()),
+                    ))
+                })
+                .collect();
+
+            for scrape in scrapes {
+                assert!(scrape.await.unwrap().is_err());
+            }
+
+            // The other scrapes timed out waiting for the first analysis
+            let health = answer(handler.clone(), String::from("/healthz"), String::new(), String::new());
+            assert!(tokio::time::timeout(Duration::from_millis(50), health).await.is_ok());
+        });
+        drop(runtime);
+
+        assert_eq!(ANALYSES.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_serve_graceful_shutdown() {
+        let args = args::parse_args(vec![String::from("request_log_analyzer")]).unwrap();
+        let handler = Arc::new(HttpHandler::new(args, Metrics::Run(slow_run)));
 
-            handler.handle(request, response);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let listener = runtime.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let address = listener.local_addr().unwrap();
+        let (shutdown, shutdown_received) = oneshot::channel::<()>();
+
+        let server = thread::spawn(move || {
+            runtime.block_on(serve(handler, listener, None, Duration::from_secs(5), async {
+                shutdown_received.await.ok();
+            }))
+        });
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(b"GET /api/summary HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+
+        // Shut down while the request is being answered
+        thread::sleep(Duration::from_millis(100));
+        shutdown.send(()).unwrap();
+
+        let mut result = String::new();
+        stream.read_to_string(&mut result).unwrap();
+        assert!(result.starts_with("HTTP/1.1 200 OK"), "{}", result);
+        assert!(result.ends_with(
+            "{\"count\":0,\"time_unit\":\"ms\",\"time\":null,\"error\":null,\"input\":[]}\n",
+        ));
+
+        server.join().unwrap();
+        assert!(TcpStream::connect(address).is_err());
+    }
+
+    #[test]
+    fn test_handle_auth() {
+        // With a log file, /api/summary does not read stdin
+        let mut args = args::parse_args(vec![
+            String::from("request_log_analyzer"),
+            String::from("src/test/simple-1.log"),
+        ]).unwrap();
+        args.healthz_max_age = Duration::from_secs(u32::MAX.into());
+        args.exporter.bearer_token = Some(String::from("abc.def"));
+
+        let handler = Arc::new(HttpHandler::new(args, Metrics::Run(run)));
+
+        let result = scrape_request(&handler, b"GET /api/summary HTTP/1.0\r\n\r\n");
+        assert!(result.starts_with("HTTP/1.0 401 Unauthorized"), "{}", result);
+        assert!(result.contains("Www-Authenticate: Bearer realm=\"request_log_analyzer\""));
+
+        let result = scrape_request(
+            &handler,
+            b"GET /api/summary HTTP/1.0\r\nAuthorization: Bearer abc.def\r\n\r\n",
+        );
+        assert!(result.starts_with("HTTP/1.0 200 OK"), "{}", result);
+
+        let result = scrape_request(&handler, b"GET /healthz HTTP/1.0\r\n\r\n");
+        assert!(result.starts_with("HTTP/1.0 200 OK"), "{}", result);
+    }
+
+    #[test]
+    fn test_serve_tls() {
+        let args = args::parse_args(vec![String::from("request_log_analyzer")]).unwrap();
+        let handler = Arc::new(HttpHandler::new(args, Metrics::Run(run)));
+        let tls = tls::acceptor("src/test/localhost-cert.pem", "src/test/localhost-key.pem")
+            .unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let listener = runtime.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let address = listener.local_addr().unwrap();
+        let (shutdown, shutdown_received) = oneshot::channel::<()>();
+
+        let server = thread::spawn(move || {
+            runtime.block_on(serve(handler, listener, Some(tls), Duration::from_secs(5), async {
+                shutdown_received.await.ok();
+            }))
+        });
+
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(CertificateDer::from_pem_file("src/test/localhost-cert.pem").unwrap())
+            .unwrap();
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connection = ClientConnection::new(
+            Arc::new(config),
+            ServerName::try_from("localhost").unwrap(),
+        ).unwrap();
+
+        let mut stream = StreamOwned::new(connection, TcpStream::connect(address).unwrap());
+        stream.write_all(b"GET /healthz HTTP/1.0\r\n\r\n").unwrap();
+
+        let mut result = String::new();
+        stream.read_to_string(&mut result).unwrap();
+        assert!(result.starts_with("HTTP/1.0 200 OK"), "{}", result);
+        assert!(result.ends_with("reading from stdin\n"));
+
+        shutdown.send(()).unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_negotiate() {
+        for &(accept, expected) in &[
+            ("", Format::Text),
+            ("*/*", Format::Text),
+            ("text/plain;version=0.0.4", Format::Text),
+            ("application/openmetrics-text", Format::OpenMetrics),
+            (
+                "application/openmetrics-text;version=1.0.0,application/openmetrics-text;\
+                 version=0.0.1;q=0.75,text/plain;version=0.0.4;q=0.5,*/*;q=0.1",
+                Format::OpenMetrics,
+            ),
+            ("application/openmetrics-text;q=0.2,text/plain;q=0.5", Format::Text),
+            ("application/openmetrics-text;q=0,*/*", Format::Text),
+        ]
+        {
+            assert_eq!(negotiate(accept), expected, "{}", accept);
         }
+    }
 
-        let result = str::from_utf8(&response_mock_network_stream.write).unwrap();
+    fn slow_run(
+        args: &args::RequestLogAnalyzerArgs,
+    ) -> Result<result::RequestLogAnalyzerResult, Error> {
+        thread::sleep(Duration::from_millis(500));
+        Ok(analyzer::PartialAnalysis::new().result(args.time_unit))
+    }
 
-        assert!(result.contains("request_count 3"));
-        assert!(result.contains("request_time_max 100"));
-        assert!(result.contains("request_time_min 1"));
-        assert!(result.contains("request_time_avg 37"));
-        assert!(result.contains("request_time_median 10"));
-        assert!(result.contains("request_time_percentile90 90"));
-        assert!(result.contains("request_time_percentile99 99"));
+    static ANALYSES: AtomicUsize = AtomicUsize::new(0);
+
+    fn counting_run(
+        args: &args::RequestLogAnalyzerArgs,
+    ) -> Result<result::RequestLogAnalyzerResult, Error> {
+        ANALYSES.fetch_add(1, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(300));
+        Ok(analyzer::PartialAnalysis::new().result(args.time_unit))
+    }
+
+    fn scrape(handler: &Arc<HttpHandler>) -> String {
+        // Create a minimal HTTP request
+        scrape_request(handler, b"GET /metrics HTTP/1.0\r\n\r\n")
+    }
+
+    fn scrape_request(handler: &Arc<HttpHandler>, request: &[u8]) -> String {
+        let mut stream = mock::MockStream::with_input(request);
+        let handler = handler.clone();
+        let timeout = handler.args.http_timeout;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        // The mock has sent its whole request when it is read to the end
+        let mut builder = builder(timeout);
+        builder.half_close(true);
+
+        runtime
+            .block_on(builder.serve_connection(
+                TokioIo::new(&mut stream),
+                service_fn(move |req| handle(handler.clone(), req, timeout)),
+            ))
+            .unwrap();
 
+        String::from(str::from_utf8(&stream.write).unwrap())
     }
 }
@@ -0,0 +1,165 @@
+use chrono::Duration;
+use failure::{Error, err_msg};
+use crate::analyzer::grouping::GroupBy;
+use crate::args;
+use crate::filter;
+use crate::filter::expression::Expression;
+
+/// The args of a scrape with the query parameters of its URL, like
+/// `?include=/content/dam&minutes=5&group_by=method`.
+///
+/// The filter options add to the filters of the startup args, `minutes`
+/// replaces their time window and `group_by` their grouping, which can not
+/// use the labels of `--prometheus-labels`. Names may be written with `_`
+/// instead of `-`, like `min_duration`.
+pub fn scrape_args(
+    args: &args::RequestLogAnalyzerArgs,
+    query: &str,
+) -> Result<args::RequestLogAnalyzerArgs, Error> {
+    let mut args = args.clone();
+    let mut filters: Vec<(String, Vec<String>)> = vec![];
+
+    for (key, value) in parse(query) {
+        let name = key.replace('_', "-");
+
+        match name.as_str() {
+            "minutes" => {
+                let minutes = value.parse::<u32>().map_err(|_| {
+                    err_msg(format!("minutes must be a number of minutes ({})", value))
+                })?;
+                args.conditions.latest_time = Some(Duration::minutes(i64::from(minutes)));
+            }
+            "group-by" if value.is_empty() => args.grouping.group_by = vec![],
+            "group-by" => {
+                args.grouping.group_by = GroupBy::parse_list(&value).map_err(|name| {
+                    err_msg(format!(
+                        "group_by must be some of status_class, method, path_group ({})",
+                        name
+                    ))
+                })?;
+
+                // The constant labels can not be variable labels as well
+                if let Some(name) = args.grouping.label_names().into_iter().find(|name| {
+                    args.prometheus_labels.iter().any(|(other, _)| other == name)
+                })
+                {
+                    return Err(err_msg(format!(
+                        "group_by can not use the label {} of --prometheus-labels",
+                        name
+                    )));
+                }
+            }
+            name if filter::options::NAMES.contains(&name) => {
+                match filters.iter_mut().find(|(other, _)| other == name) {
+                    Some((_, values)) => values.push(value),
+                    None => filters.push((String::from(name), vec![value])),
+                }
+            }
+            _ => {
+                return Err(err_msg(format!(
+                    "Unknown query parameter {}, expected minutes, group_by or one of {}",
+                    key,
+                    filter::options::NAMES.join(", ")
+                )))
+            }
+        }
+    }
+
+    let mut expressions: Vec<Expression> = args.conditions.expression.take().into_iter().collect();
+
+    for (name, values) in &filters {
+        let values: Vec<&str> = values.iter().map(String::as_str).collect();
+        expressions.extend(filter::options::expression(name, name, &values)?);
+    }
+
+    args.conditions.expression = Expression::all(expressions);
+
+    Ok(args)
+}
+
+/// The decoded names and values of a query string
+fn parse(query: &str) -> Vec<(String, String)> {
+    url::form_urlencoded::parse(query.as_bytes())
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn startup_args() -> args::RequestLogAnalyzerArgs {
+        args::parse_args(vec![
+            String::from("request_log_analyzer"),
+            String::from("--exclude"),
+            String::from("health"),
+            String::from("-t"),
+            String::from("10"),
+        ]).unwrap()
+    }
+
+    #[test]
+    fn test_scrape_args() {
+        let args = scrape_args(
+            &startup_args(),
+            "include=/content/dam&include=%2Fcontent%2Fsite&minutes=5&group_by=method,\
+             status_class&min_duration=250ms",
+        ).unwrap();
+
+        assert_eq!(
+            args.conditions.expression,
+            Expression::all(vec![
+                Expression::negate(Expression::line_contains("health")),
+                Expression::any(vec![
+                    Expression::line_contains("/content/dam"),
+                    Expression::line_contains("/content/site"),
+                ]).unwrap(),
+                Expression::parse("duration >= 250ms").unwrap(),
+            ])
+        );
+        assert_eq!(args.conditions.latest_time, Some(Duration::minutes(5)));
+        assert_eq!(args.grouping.group_by, vec![GroupBy::Method, GroupBy::StatusClass]);
+    }
+
+    #[test]
+    fn test_scrape_args_constant_labels() {
+        let startup_args = args::parse_args(vec![
+            String::from("request_log_analyzer"),
+            String::from("--prometheus-labels"),
+            String::from("method=any"),
+        ]).unwrap();
+
+        let error_message = match scrape_args(&startup_args, "group_by=status_class,method") {
+            Err(err) => format!("{}", err),
+            Ok(_) => unreachable!(),
+        };
+        assert!(error_message.contains("group_by can not use the label method"));
+
+        let args = scrape_args(&startup_args, "group_by=status_class").unwrap();
+        assert_eq!(args.grouping.group_by, vec![GroupBy::StatusClass]);
+    }
+
+    #[test]
+    fn test_scrape_args_empty() {
+        assert_eq!(scrape_args(&startup_args(), "").unwrap(), startup_args());
+    }
+
+    #[test]
+    fn test_scrape_args_invalid() {
+        for &(query, expected) in &[
+            ("minutes=five", "minutes must be a number of minutes (five)"),
+            ("group_by=host", "group_by must be some of"),
+            ("status=2xx&status=ok", "status must be status codes"),
+            ("include_regex=(", "include-regex ( is invalid"),
+            ("debug=true", "Unknown query parameter debug"),
+        ]
+        {
+            let error_message = match scrape_args(&startup_args(), query) {
+                Err(err) => format!("{}", err),
+                Ok(_) => unreachable!(),
+            };
+
+            assert!(error_message.contains(expected), "{}", error_message);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::io;
+use std::mem;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+use crate::analyzer;
+use crate::args;
+use crate::filter;
+use crate::follow;
+use crate::log_parser;
+use crate::render::prometheus::PrometheusRenderer;
+use crate::request_response_matcher::{RequestResponsePair, RequestResponsePairIterator};
+use crate::result;
+use crate::state;
+
+/// What the tailer keeps up to date for the requests to the exporter.
+pub struct Tailed {
+    pub renderer: PrometheusRenderer,
+    /// The result of the latest complete `--follow-interval` of each source
+    pub summaries: Vec<result::RequestLogAnalyzerResult>,
+}
+
+impl Tailed {
+    pub fn new(args: &args::RequestLogAnalyzerArgs) -> Tailed {
+        let sources = args.sources();
+
+        Tailed {
+            // With the `source` label, if there are sources
+            renderer: crate::prometheus_renderer(&sources[0], false),
+            summaries: sources
+                .iter()
+                .map(|_| analyzer::PartialAnalysis::new().result(args.time_unit))
+                .collect(),
+        }
+    }
+}
+
+/// Keep the metrics and summary of `tailed` up to date with the requests
+/// appended to the followed log file, so scrapes don't have to read the log.
+///
+/// The histogram and counters are cumulative, the legacy gauges and the
+/// summary describe the latest `--follow-interval`. `args` are those of the
+/// source with the index `source`.
+pub fn tail(
+    reader: follow::FollowReader,
+    args: &args::RequestLogAnalyzerArgs,
+    tailed: &Mutex<Tailed>,
+    source: usize,
+) {
+    let mut events_iterator = log_parser::LogEvents::new(
+        io::BufReader::new(reader),
+        args.needs_original_log_line(),
+    ).filter_map(|event| event.ok());
+
+    let mut pairs_iterator = RequestResponsePairIterator::new(&mut events_iterator)
+        .prune_pending(state::max_pending_request_age());
+
+    let mut interval_analysis = analyzer::PartialAnalysis::new();
+    let mut next_interval = Instant::now() + args.follow_interval;
+
+    loop {
+        if update(&mut pairs_iterator, args, tailed, &mut interval_analysis) == 0 {
+            thread::sleep(crate::FOLLOW_POLL_INTERVAL);
+        }
+
+        if Instant::now() >= next_interval {
+            let analysis = mem::replace(&mut interval_analysis, analyzer::PartialAnalysis::new());
+            let result = analysis.result(args.time_unit);
+
+            let mut tailed = tailed.lock().unwrap();
+            tailed.renderer.set_legacy_gauges(&result);
+            tailed.summaries[source] = result;
+            next_interval += args.follow_interval;
+        }
+    }
+}
+
+/// Count the requests that can be read now, returning how many pairs were
+/// read.
+fn update<I>(
+    pairs: &mut I,
+    args: &args::RequestLogAnalyzerArgs,
+    tailed: &Mutex<Tailed>,
+    interval_analysis: &mut analyzer::PartialAnalysis,
+) -> usize
+where
+    I: Iterator<Item = RequestResponsePair>,
+{
+    let mut count = 0;
+
+    for pair in pairs {
+        count += 1;
+
+        if filter::matches_filter(&pair, &args.conditions) {
+            let pair = args.grouping.label(pair);
+
+            tailed.lock().unwrap().renderer.observe(&pair);
+            interval_analysis.add(&pair);
+        }
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::str;
+    use crate::filter::expression::Expression;
+    use super::*;
+
+    #[test]
+    fn test_update() {
+        let mut args = args::parse_args(vec![String::from("request_log_analyzer")]).unwrap();
+        args.conditions.expression = Expression::parse("path ~ other").ok();
+
+        args.prometheus_buckets = vec![0.01, 1.0];
+        let tailed = Mutex::new(Tailed::new(&args));
+        let mut interval_analysis = analyzer::PartialAnalysis::new();
+
+        let file = File::open("src/test/simple-1.log").unwrap();
+        let mut events_iterator = log_parser::LogEvents::new(io::BufReader::new(file), true)
+            .filter_map(|event| event.ok());
+        let mut pairs_iterator = RequestResponsePairIterator::new(&mut events_iterator);
+
+        assert_eq!(update(&mut pairs_iterator, &args, &tailed, &mut interval_analysis), 2);
+        assert_eq!(update(&mut pairs_iterator, &args, &tailed, &mut interval_analysis), 0);
+        assert_eq!(interval_analysis.result(args.time_unit).count, 1);
+
+        let renderer = &mut tailed.lock().unwrap().renderer;
+        renderer.encode();
+
+        let buffer_text = str::from_utf8(&renderer.buffer).unwrap();
+        assert!(buffer_text.contains("request_duration_seconds_bucket{le=\"0.01\"} 1"));
+        assert!(buffer_text.contains("request_duration_seconds_count 1"));
+        assert!(buffer_text.contains("requests_total{status=\"200\"} 1"));
+    }
+
+    #[test]
+    fn test_update_source() {
+        let args = args::parse_args(vec![
+            String::from("request_log_analyzer"),
+            String::from("--prometheus-listen"),
+            String::from("localhost:9898"),
+            String::from("--prometheus-source"),
+            String::from("author=src/test/simple-1.log"),
+            String::from("--prometheus-source"),
+            String::from("publish=src/test/simple-2.log"),
+        ]).unwrap();
+        let tailed = Mutex::new(Tailed::new(&args));
+        let mut interval_analysis = analyzer::PartialAnalysis::new();
+
+        let source = &args.sources()[1];
+        let file = File::open(&source.filenames[0]).unwrap();
+        let mut events_iterator = log_parser::LogEvents::new(io::BufReader::new(file), true)
+            .filter_map(|event| event.ok());
+        let mut pairs_iterator = RequestResponsePairIterator::new(&mut events_iterator);
+
+        assert_eq!(update(&mut pairs_iterator, source, &tailed, &mut interval_analysis), 2);
+        assert_eq!(tailed.lock().unwrap().summaries.len(), 2);
+
+        let renderer = &mut tailed.lock().unwrap().renderer;
+        renderer.encode();
+
+        let buffer_text = str::from_utf8(&renderer.buffer).unwrap();
+        assert!(buffer_text.contains("request_duration_seconds_count{source=\"publish\"} 2"));
+        assert!(!buffer_text.contains("source=\"author\""));
+    }
+}
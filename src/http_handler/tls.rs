@@ -0,0 +1,65 @@
+use std::sync::Arc;
+use failure::{Error, err_msg};
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+
+/// Serve HTTPS with the certificate chain and private key of PEM files.
+pub fn acceptor(cert: &str, key: &str) -> Result<TlsAcceptor, Error> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| err_msg(format!("Can not read the certificate {} ({})", cert, err)))?;
+
+    if certs.is_empty() {
+        return Err(err_msg(format!("There is no certificate in {}", cert)));
+    }
+
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|err| err_msg(format!("Can not read the private key {} ({})", key, err)))?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|err| err_msg(format!("Invalid certificate or private key ({})", err)))?;
+
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CERT: &str = "src/test/localhost-cert.pem";
+    const KEY: &str = "src/test/localhost-key.pem";
+
+    fn error_message(result: Result<TlsAcceptor, Error>) -> String {
+        match result {
+            Err(err) => format!("{}", err),
+            Ok(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_acceptor() {
+        assert!(acceptor(CERT, KEY).is_ok());
+    }
+
+    #[test]
+    fn test_acceptor_invalid() {
+        assert!(error_message(acceptor("src/test/missing.pem", KEY)).starts_with(
+            "Can not read the certificate src/test/missing.pem (",
+        ));
+        assert_eq!(
+            error_message(acceptor("src/test/simple-1.log", KEY)),
+            "There is no certificate in src/test/simple-1.log"
+        );
+        assert!(error_message(acceptor(CERT, CERT)).starts_with(
+            "Can not read the private key src/test/localhost-cert.pem (",
+        ));
+    }
+}
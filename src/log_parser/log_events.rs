@@ -1,3 +1,4 @@
+use std::str;
 use chrono::*;
 
 #[derive(Eq, PartialEq, Clone, Debug)]
@@ -16,43 +17,42 @@ pub enum LogEvent {
 pub struct Request {
     pub id: i32,
     pub time: DateTime<FixedOffset>,
-    pub original_log_line: String,
+    pub original_log_line: Option<String>,
 }
 
 impl Request {
+    #[cfg(test)]
     pub fn new_from_log_line(log_line: &str) -> Result<Request, &'static str> {
-        let parts: Vec<&str> = log_line.split(' ').collect();
+        Request::parse(log_line.as_bytes(), true)
+    }
 
-        let id = match parts.get(2) {
-            Some(id) => id,
+    /// Parse a request from a raw log line.
+    ///
+    /// The line is only copied into `original_log_line` if
+    /// `keep_original_line` is set, everything else is read in place.
+    pub fn parse(log_line: &[u8], keep_original_line: bool) -> Result<Request, &'static str> {
+        let mut parts = log_line.split(|byte| *byte == b' ');
+
+        let date = parts.next().unwrap_or_default();
+        let zone = match parts.next() {
+            Some(zone) => zone,
             None => return Err("Uncomprehensible request logline"),
         };
 
-        // Shortest valid id format is "[1]"
-        if id.len() < 3 {
-            return Err("Uncomprehensible request logline");
-        }
-
-        let id_parsed: i32 = match id.chars()
-            .skip(1)
-            .take(id.len() - 2)
-            .collect::<String>()
-            .parse() {
-            Ok(id) => id,
-            Err(_) => return Err("Uncomprehensible request logline"),
+        let id_parsed = match parts.next().and_then(parse_id) {
+            Some(id) => id,
+            None => return Err("Uncomprehensible request logline"),
         };
 
-        let date = &format!("{} {}", parts[0], parts[1]);
-
-        let date_parsed = match DateTime::parse_from_str(date, "%d/%b/%Y:%H:%M:%S %z") {
-            Ok(date_time) => date_time,
-            Err(_) => return Err("Uncomprehensible request logline"),
+        let date_parsed = match parse_time(log_line, date, zone) {
+            Some(date_time) => date_time,
+            None => return Err("Uncomprehensible request logline"),
         };
 
         Ok(Request {
             id: id_parsed,
             time: date_parsed,
-            original_log_line: log_line.to_string(),
+            original_log_line: original_log_line(log_line, keep_original_line),
         })
     }
 }
@@ -61,67 +61,176 @@ impl Request {
 pub struct Response {
     pub id: i32,
     pub response_time: Duration,
-    pub original_log_line: String,
+    pub original_log_line: Option<String>,
+    /// The HTTP status code, unless the log has something else there
+    pub status: Option<u16>,
     pub http_error: Option<HttpError>,
 }
 
 impl Response {
+    #[cfg(test)]
     pub fn new_from_log_line(log_line: &str) -> Result<Response, &'static str> {
-        let parts: Vec<&str> = log_line.split(' ').collect();
-
-        let id = match parts.get(2) {
-            Some(id) => id,
-            None => return Err("Uncomprehensible request logline"),
-        };
-
-        // Shortest valid id format is "[1]"
-        if id.len() < 3 {
-            return Err("Uncomprehensible response logline");
-        }
-
-        let id_numeric: i32 = match id.chars()
-            .skip(1)
-            .take(id.len() - 2)
-            .collect::<String>()
-            .parse() {
-            Ok(number) => number,
-            Err(_) => return Err("Uncomprehensible response logline"),
-        };
+        Response::parse(log_line.as_bytes(), true)
+    }
 
-        let response_time = parts[parts.len() - 1];
-        if response_time.len() < 3 {
-            return Err("Uncomprehensible response logline");
-        }
+    /// Parse a response from a raw log line, see `Request::parse`.
+    pub fn parse(log_line: &[u8], keep_original_line: bool) -> Result<Response, &'static str> {
+        let mut parts = log_line.split(|byte| *byte == b' ');
 
-        let response_time_duration = match response_time
-            .chars()
-            .take(response_time.len() - 2)
-            .collect::<String>()
-            .parse() {
-            Ok(number) => Duration::milliseconds(number),
-            Err(_) => return Err("Uncomprehensible response logline"),
+        let id_numeric = match parts.nth(2).and_then(parse_id) {
+            Some(id) => id,
+            None => return Err("Uncomprehensible response logline"),
         };
 
-        let http_error = match parts.get(4) {
+        let (status, http_error) = match parts.nth(1) {
             Some(part) => {
-                match part.chars().nth(0) {
-                    Some('4') => Some(HttpError::ClientError4xx),
-                    Some('5') => Some(HttpError::ServerError5xx),
+                let status = str::from_utf8(part).ok().and_then(|part| part.parse().ok());
+                let http_error = match part.first() {
+                    Some(b'4') => Some(HttpError::ClientError4xx),
+                    Some(b'5') => Some(HttpError::ServerError5xx),
                     _ => None,
-                }
+                };
+
+                (status, http_error)
             }
             None => return Err("Uncomprehensible response logline"),
         };
 
+        let response_time = log_line.rsplit(|byte| *byte == b' ').next().unwrap_or_default();
+        let response_time_duration = match str::from_utf8(response_time) {
+            Ok(response_time) => parse_duration(response_time)?,
+            Err(_) => return Err("Uncomprehensible response logline"),
+        };
+
         Ok(Response {
             id: id_numeric,
             response_time: response_time_duration,
+            status,
             http_error,
-            original_log_line: log_line.to_string(),
+            original_log_line: original_log_line(log_line, keep_original_line),
         })
     }
 }
 
+fn original_log_line(log_line: &[u8], keep_original_line: bool) -> Option<String> {
+    if keep_original_line {
+        Some(String::from_utf8_lossy(log_line).into_owned())
+    } else {
+        None
+    }
+}
+
+/// Parse an id like "[02]" without allocating.
+fn parse_id(id: &[u8]) -> Option<i32> {
+    // Shortest valid id format is "[1]"
+    if id.len() < 3 {
+        return None;
+    }
+
+    str::from_utf8(&id[1..id.len() - 1]).ok()?.parse().ok()
+}
+
+/// Parse the timestamp at the start of a log line, e.g.
+/// "08/Apr/2016:09:58:47 +0200".
+///
+/// The fixed layout written by AEM is decoded by hand, anything unusual is
+/// handed to chrono's (much slower) generic parser.
+fn parse_time(log_line: &[u8], date: &[u8], zone: &[u8]) -> Option<DateTime<FixedOffset>> {
+    if let Some(time) = parse_time_fixed_layout(date, zone) {
+        return Some(time);
+    }
+
+    let text = str::from_utf8(&log_line[..date.len() + 1 + zone.len()]).ok()?;
+    DateTime::parse_from_str(text, "%d/%b/%Y:%H:%M:%S %z").ok()
+}
+
+fn parse_time_fixed_layout(date: &[u8], zone: &[u8]) -> Option<DateTime<FixedOffset>> {
+    fn number(digits: &[u8]) -> Option<u32> {
+        digits.iter().try_fold(0, |value, digit| match digit {
+            b'0'..=b'9' => Some(value * 10 + u32::from(digit - b'0')),
+            _ => None,
+        })
+    }
+
+    // dd/Mon/YYYY:HH:MM:SS
+    if date.len() != 20 || date[2] != b'/' || date[6] != b'/' || date[11] != b':' ||
+        date[14] != b':' || date[17] != b':'
+    {
+        return None;
+    }
+
+    // +hhmm
+    if zone.len() != 5 {
+        return None;
+    }
+
+    let month = match &date[3..6] {
+        b"Jan" => 1,
+        b"Feb" => 2,
+        b"Mar" => 3,
+        b"Apr" => 4,
+        b"May" => 5,
+        b"Jun" => 6,
+        b"Jul" => 7,
+        b"Aug" => 8,
+        b"Sep" => 9,
+        b"Oct" => 10,
+        b"Nov" => 11,
+        b"Dec" => 12,
+        _ => return None,
+    };
+
+    let zone_seconds = (number(&zone[1..3])? * 3600 + number(&zone[3..5])? * 60) as i32;
+    let offset = match zone[0] {
+        b'+' => FixedOffset::east_opt(zone_seconds)?,
+        b'-' => FixedOffset::west_opt(zone_seconds)?,
+        _ => return None,
+    };
+
+    offset
+        .with_ymd_and_hms(
+            number(&date[7..11])? as i32,
+            month,
+            number(&date[0..2])?,
+            number(&date[12..14])?,
+            number(&date[15..17])?,
+            number(&date[18..20])?,
+        )
+        .single()
+}
+
+/// Parse a response time such as `10ms`, `1.5s`, `250µs` or `250us`.
+///
+/// AEM writes milliseconds like `10ms`, and bare numbers without a unit
+/// suffix are taken as milliseconds too. The result keeps microsecond
+/// precision.
+pub fn parse_duration(text: &str) -> Result<Duration, &'static str> {
+    let (number, microseconds_per_unit) = if let Some(number) = text.strip_suffix("ms") {
+        (number, 1_000.0)
+    } else if let Some(number) = text.strip_suffix("µs") {
+        (number, 1.0)
+    } else if let Some(number) = text.strip_suffix("us") {
+        (number, 1.0)
+    } else if let Some(number) = text.strip_suffix('s') {
+        (number, 1_000_000.0)
+    } else {
+        (text, 1_000.0)
+    };
+
+    let value: f64 = match number.parse() {
+        Ok(value) => value,
+        Err(_) => return Err("Uncomprehensible response logline"),
+    };
+
+    if !value.is_finite() || value < 0.0 {
+        return Err("Uncomprehensible response logline");
+    }
+
+    Ok(Duration::microseconds(
+        (value * microseconds_per_unit).round() as i64,
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::*;
@@ -136,7 +245,7 @@ mod tests {
             id: 2,
             time: DateTime::parse_from_str("08/Apr/2016:09:58:47 +0200", "%d/%b/%Y:%H:%M:%S %z")
                 .unwrap(),
-            original_log_line: line.clone(),
+            original_log_line: Some(line.clone()),
         };
 
         let result = Request::new_from_log_line(&line);
@@ -144,6 +253,45 @@ mod tests {
         assert_eq!(result.unwrap(), expected)
     }
 
+    #[test]
+    fn test_parse_request_line_without_original_line() {
+        let line = "08/Apr/2016:09:58:47 +0200 [02] -> GET /content/some/other.html HTTP/1.1";
+
+        let result = Request::parse(line.as_bytes(), false).unwrap();
+
+        assert_eq!(result.id, 2);
+        assert_eq!(result.original_log_line, None);
+    }
+
+    #[test]
+    fn test_parse_request_line_time_matches_chrono() {
+        let lines = vec![
+            "08/Apr/2016:09:58:47 +0200 [02] -> GET /content/some/other.html HTTP/1.1",
+            "31/Dec/1999:23:59:59 -0130 [02] -> GET /content/some/other.html HTTP/1.1",
+            "01/Jan/2024:00:00:00 +0000 [02] -> GET /content/some/other.html HTTP/1.1",
+        ];
+
+        for line in lines {
+            let expected = DateTime::parse_from_str(&line[0..26], "%d/%b/%Y:%H:%M:%S %z").unwrap();
+            let result = Request::new_from_log_line(line).unwrap().time;
+
+            assert_eq!(result, expected);
+            assert_eq!(result.offset(), expected.offset());
+        }
+    }
+
+    #[test]
+    fn test_parse_request_line_time_unusual_layout() {
+        // Not the fixed layout, handled by the generic parser
+        let line = "8/Apr/2016:09:58:47 +0200 [02] -> GET /content/some/other.html HTTP/1.1";
+
+        let result = Request::new_from_log_line(line).unwrap().time;
+        let expected = DateTime::parse_from_str("08/Apr/2016:09:58:47 +0200", "%d/%b/%Y:%H:%M:%S %z")
+            .unwrap();
+
+        assert_eq!(result, expected);
+    }
+
     #[test]
     fn test_parse_request_line_bad_format() {
         let line = "08/A16:09:58:47 justsomegarbage".to_string();
@@ -197,7 +345,8 @@ mod tests {
         let expected = Response {
             id: 2,
             response_time: Duration::milliseconds(10),
-            original_log_line: line.clone(),
+            original_log_line: Some(line.clone()),
+            status: Some(200),
             http_error: None,
         };
 
@@ -206,6 +355,19 @@ mod tests {
         assert_eq!(result.unwrap(), expected)
     }
 
+    #[test]
+    fn test_parse_response_line_without_original_line() {
+        let line = "08/Apr/2016:09:58:48 +0200 [02] <- 500 text/html 10ms";
+
+        let result = Response::parse(line.as_bytes(), false).unwrap();
+
+        assert_eq!(result.id, 2);
+        assert_eq!(result.response_time, Duration::milliseconds(10));
+        assert_eq!(result.status, Some(500));
+        assert_eq!(result.http_error, Some(HttpError::ServerError5xx));
+        assert_eq!(result.original_log_line, None);
+    }
+
     #[test]
     fn test_parse_response_line_inconsistent_space() {
         let line = "06/Apr/2016:14:54:16 +0200 [200] <- 200 text/html; charset=utf-8 250ms"
@@ -214,7 +376,8 @@ mod tests {
         let expected = Response {
             id: 200,
             response_time: Duration::milliseconds(250),
-            original_log_line: line.clone(),
+            original_log_line: Some(line.clone()),
+            status: Some(200),
             http_error: None,
         };
 
@@ -267,6 +430,46 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_parse_response_line_seconds() {
+        let line = "08/Apr/2016:09:58:48 +0200 [02] <- 200 text/html 1.5s".to_string();
+
+        let result = Response::new_from_log_line(&line).unwrap().response_time;
+
+        assert_eq!(result, Duration::milliseconds(1500));
+    }
+
+    #[test]
+    fn test_parse_response_line_bare_number() {
+        let line = "08/Apr/2016:09:58:48 +0200 [02] <- 200 text/html 42".to_string();
+
+        let result = Response::new_from_log_line(&line).unwrap().response_time;
+
+        assert_eq!(result, Duration::milliseconds(42));
+    }
+
+    #[test]
+    fn test_parse_duration_units() {
+        assert_eq!(parse_duration("10ms"), Ok(Duration::milliseconds(10)));
+        assert_eq!(parse_duration("0.25ms"), Ok(Duration::microseconds(250)));
+        assert_eq!(parse_duration("2s"), Ok(Duration::seconds(2)));
+        assert_eq!(parse_duration("0.001s"), Ok(Duration::milliseconds(1)));
+        assert_eq!(parse_duration("250us"), Ok(Duration::microseconds(250)));
+        assert_eq!(parse_duration("250µs"), Ok(Duration::microseconds(250)));
+        assert_eq!(parse_duration("7"), Ok(Duration::milliseconds(7)));
+        assert_eq!(parse_duration("1.5"), Ok(Duration::microseconds(1500)));
+    }
+
+    #[test]
+    fn test_parse_duration_invalid() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("ms").is_err());
+        assert!(parse_duration("5ns").is_err());
+        assert!(parse_duration("-5ms").is_err());
+        assert!(parse_duration("NaNms").is_err());
+        assert!(parse_duration("infs").is_err());
+    }
+
     #[test]
     fn test_parse_response_line_client_error() {
         let line = "08/Apr/2016:09:58:48 +0200 [02] <- 400 text/html 10ms".to_string();
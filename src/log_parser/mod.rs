@@ -2,29 +2,70 @@ use std::io;
 pub mod log_events;
 use self::log_events::*;
 
-pub fn parse_line(line: Result<String, io::Error>) -> Result<LogEvent, &'static str> {
-    match line {
-        Err(_) => Err("Failed to read line."),
-        Ok(ref line) if line.contains("->") => {
-            Ok(LogEvent::Request(Request::new_from_log_line(line)?))
+pub fn parse_line(line: &[u8], keep_original_line: bool) -> Result<LogEvent, &'static str> {
+    if contains(line, b"->") {
+        Ok(LogEvent::Request(Request::parse(line, keep_original_line)?))
+    } else if contains(line, b"<-") {
+        Ok(LogEvent::Response(Response::parse(line, keep_original_line)?))
+    } else {
+        Err("Line is neither a Request nor a Response")
+    }
+}
+
+fn contains(line: &[u8], needle: &[u8]) -> bool {
+    line.windows(needle.len()).any(|window| window == needle)
+}
+
+/// Iterator over the log events of a reader.
+///
+/// All lines are read into the same buffer, so parsing a line does not
+/// allocate unless the original line has to be kept.
+pub struct LogEvents<R> {
+    reader: R,
+    line: Vec<u8>,
+    keep_original_line: bool,
+}
+
+impl<R: io::BufRead> LogEvents<R> {
+    pub fn new(reader: R, keep_original_line: bool) -> LogEvents<R> {
+        LogEvents {
+            reader,
+            line: Vec::new(),
+            keep_original_line,
         }
-        Ok(ref line) if line.contains("<-") => {
-            Ok(LogEvent::Response(Response::new_from_log_line(line)?))
+    }
+}
+
+impl<R: io::BufRead> Iterator for LogEvents<R> {
+    type Item = Result<LogEvent, &'static str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.line.clear();
+
+        match self.reader.read_until(b'\n', &mut self.line) {
+            Ok(0) => None,
+            Ok(_) => Some(parse_line(trim_line_end(&self.line), self.keep_original_line)),
+            Err(_) => Some(Err("Failed to read line.")),
         }
-        Ok(_) => Err("Line is neither a Request nor a Response"),
     }
 }
 
+fn trim_line_end(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
 #[cfg(test)]
 mod tests {
+    use std::io::Cursor;
+    use std::time::Instant;
     use super::*;
 
     #[test]
     fn test_parse_line_request() {
-        let line = "08/Apr/2016:09:58:47 +0200 [02] -> GET /content/some/other.html HTTP/1.1"
-            .to_string();
+        let line = "08/Apr/2016:09:58:47 +0200 [02] -> GET /content/some/other.html HTTP/1.1";
 
-        let event = match parse_line(Ok(line)).unwrap() {
+        let event = match parse_line(line.as_bytes(), false).unwrap() {
             LogEvent::Request(request) => request,
             LogEvent::Response(_) => unreachable!(),
         };
@@ -34,9 +75,9 @@ mod tests {
 
     #[test]
     fn test_parse_line_response() {
-        let line = "08/Apr/2016:09:58:48 +0200 [05] <- 200 text/html 10ms".to_string();
+        let line = "08/Apr/2016:09:58:48 +0200 [05] <- 200 text/html 10ms";
 
-        let event = match parse_line(Ok(line)).unwrap() {
+        let event = match parse_line(line.as_bytes(), false).unwrap() {
             LogEvent::Request(_) => unreachable!(),
             LogEvent::Response(response) => response,
         };
@@ -46,20 +87,98 @@ mod tests {
 
     #[test]
     fn test_parse_line_unrecognized() {
-        let line = "08/Apr/2016:09:58:48 +0200 [05] XY 200 text/html 10ms".to_string();
+        let line = "08/Apr/2016:09:58:48 +0200 [05] XY 200 text/html 10ms";
 
-        let event = parse_line(Ok(line));
+        let event = parse_line(line.as_bytes(), false);
 
         assert_eq!(event, Err("Line is neither a Request nor a Response"));
     }
 
     #[test]
     fn test_parse_line_with_response_arrow_in_url() {
-        let line = "08/Apr/2016:09:58:47 +0200 [02] -> GET /content/<-.html HTTP/1.1".to_string();
+        let line = "08/Apr/2016:09:58:47 +0200 [02] -> GET /content/<-.html HTTP/1.1";
 
-        let _ = match parse_line(Ok(line)).unwrap() {
+        let _ = match parse_line(line.as_bytes(), false).unwrap() {
             LogEvent::Request(request) => request,
             LogEvent::Response(_) => unreachable!(),
         };
     }
+
+    #[test]
+    fn test_log_events() {
+        let input = "08/Apr/2016:09:58:47 +0200 [02] -> GET /content/some/other.html HTTP/1.1\r\n\
+                     garbage\n\
+                     08/Apr/2016:09:58:48 +0200 [02] <- 200 text/html 10ms";
+
+        let events: Vec<_> = LogEvents::new(Cursor::new(input), true).collect();
+
+        assert_eq!(events.len(), 3);
+        assert!(events[1].is_err());
+
+        match events[2] {
+            Ok(LogEvent::Response(ref response)) => {
+                assert_eq!(
+                    response.original_log_line,
+                    Some(String::from("08/Apr/2016:09:58:48 +0200 [02] <- 200 text/html 10ms"))
+                )
+            }
+            _ => unreachable!(),
+        }
+
+        match events[0] {
+            Ok(LogEvent::Request(ref request)) => {
+                assert!(!request.original_log_line.as_ref().unwrap().ends_with('\r'))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_log_events_read_error() {
+        struct FailingReader;
+
+        impl io::Read for FailingReader {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("mock error"))
+            }
+        }
+
+        let mut events = LogEvents::new(io::BufReader::new(FailingReader), false);
+
+        assert_eq!(events.next(), Some(Err("Failed to read line.")));
+    }
+
+    /// Measures parser throughput, run with `make bench`.
+    #[test]
+    #[ignore]
+    fn bench_parse_throughput() {
+        let mut input = String::new();
+        for id in 0..200_000 {
+            input.push_str(&format!(
+                "08/Apr/2016:09:58:47 +0200 [{}] -> GET /content/some/page-{}.html HTTP/1.1\n",
+                id,
+                id
+            ));
+            input.push_str(&format!(
+                "08/Apr/2016:09:58:47 +0200 [{}] <- 200 text/html; charset=utf-8 {}ms\n",
+                id,
+                id % 1200
+            ));
+        }
+
+        for &keep_original_line in &[false, true] {
+            let start = Instant::now();
+            let count = LogEvents::new(Cursor::new(input.as_bytes()), keep_original_line)
+                .filter(|event| event.is_ok())
+                .count();
+            let seconds = start.elapsed().as_secs_f64();
+
+            assert_eq!(count, 400_000);
+            println!(
+                "keep_original_line={}: {:.1} MB/s",
+                keep_original_line,
+                input.len() as f64 / 1_000_000.0 / seconds
+            );
+        }
+    }
 }
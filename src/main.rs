@@ -1,20 +1,32 @@
 use std::io;
-use std::io::prelude::*;
 use std::net::TcpStream;
 use std::env;
 use std::process;
+use std::mem;
+use std::thread;
+use std::time::Instant;
 use chrono::*;
-use failure::Error;
+use failure::{Error, err_msg};
 
 mod analyzer;
 mod args;
+mod config;
 mod multi_file;
+mod parallel;
 mod filter;
+mod follow;
 mod log_parser;
 mod render;
 mod request_response_matcher;
+mod http_client;
 mod http_handler;
 mod result;
+mod state;
+#[cfg(test)]
+mod temp_files;
+
+/// How often to check for new lines in `--follow` mode
+const FOLLOW_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
 
 fn main() {
     env_logger::init();
@@ -30,231 +42,701 @@ fn main() {
     if args.prometheus_listen.is_some() {
         let binding_address = args.prometheus_listen.clone().unwrap();
         http_handler::listen_http(args, &binding_address);
+    } else if args.follow {
+        follow(&args);
     } else {
-        let result = run(&args);
+        match run(&args) {
+            Ok(result) => render(&args, result, &mut http_renderers(&args)),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+    }
+}
 
-        let mut stream;
-        let mut stdout;
+/// Render `result` to the terminal and Graphite, and with the
+/// `http_renderers`.
+fn render(
+    args: &args::RequestLogAnalyzerArgs,
+    result: result::RequestLogAnalyzerResult,
+    http_renderers: &mut [Box<dyn render::Renderer>],
+) {
+    let mut stream;
+    let mut stdout;
+
+    let mut renderers: Vec<Box<dyn render::Renderer>>;
+    renderers = vec![];
+
+    if !args.quiet {
+        stdout = io::stdout();
+        renderers.push(Box::new(
+            render::terminal::TerminalRenderer::new(&mut stdout),
+        ));
+    }
 
-        let mut renderers: Vec<Box<dyn render::Renderer>>;
-        renderers = vec![];
+    if args.graphite_server.is_some() {
+        stream = match TcpStream::connect((
+            args.graphite_server.as_ref().unwrap().as_str(),
+            args.graphite_port.unwrap(),
+        )) {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("Could not connect to the Graphite server: {}", err);
+                process::exit(1);
+            }
+        };
 
-        if !args.quiet {
-            stdout = io::stdout();
-            renderers.push(Box::new(
-                render::terminal::TerminalRenderer::new(&mut stdout),
-            ));
-        }
+        renderers.push(Box::new(render::graphite::GraphiteRenderer::new(
+            Utc::now(),
+            args.graphite_prefix.clone(),
+            &mut stream,
+        )));
+    }
 
-        if args.graphite_server.is_some() {
-            stream = match TcpStream::connect((
-                args.graphite_server.as_ref().unwrap().as_str(),
-                args.graphite_port.unwrap(),
-            )) {
-                Ok(stream) => stream,
-                Err(err) => {
-                    eprintln!("Could not connect to the Graphite server: {}", err);
-                    process::exit(1);
-                }
-            };
+    for mut renderer in renderers {
+        renderer.render(result.clone());
+    }
+
+    for renderer in http_renderers {
+        renderer.render(result.clone());
+    }
+}
+
+/// The renderers that send the results to InfluxDB and the Pushgateway. They
+/// keep their connections open, so `--follow` reuses them for every interval.
+fn http_renderers(args: &args::RequestLogAnalyzerArgs) -> Vec<Box<dyn render::Renderer>> {
+    let mut renderers: Vec<Box<dyn render::Renderer>> = vec![];
+
+    if args.influxdb_write_url.is_some() {
+        renderers.push(Box::new(render::influxdb::InfluxDbRenderer::new(
+            &args.influxdb_write_url.clone().unwrap(),
+            args.influxdb_tags.clone(),
+            args.http_timeout,
+        )));
+    }
 
-            renderers.push(Box::new(render::graphite::GraphiteRenderer::new(
-                Utc::now(),
-                args.graphite_prefix.clone(),
-                &mut stream,
-            )));
+    if let Some(ref gateway_url) = args.prometheus_pushgateway {
+        renderers.push(Box::new(render::pushgateway::PushgatewayRenderer::new(
+            gateway_url,
+            &args.prometheus_job,
+            &args.prometheus_grouping,
+            prometheus_renderer(args, false),
+            args.http_timeout,
+        )));
+    }
+
+    renderers
+}
+
+/// With `window`, the renderer describes a single analysis with gauges,
+/// otherwise its histogram and counters add up the results it renders.
+fn prometheus_renderer(
+    args: &args::RequestLogAnalyzerArgs,
+    window: bool,
+) -> render::prometheus::PrometheusRenderer {
+    let new = if window {
+        render::prometheus::PrometheusRenderer::new_window
+    } else {
+        render::prometheus::PrometheusRenderer::new
+    };
+
+    new(
+        &args.prometheus_buckets,
+        args.prometheus_legacy_gauges,
+        &args.prometheus_labels,
+        &args.grouping.label_names(),
+    )
+}
+
+/// Keep reading lines appended to the log file and render the requests that
+/// were completed during each interval.
+fn follow(args: &args::RequestLogAnalyzerArgs) {
+    let reader = match follow::FollowReader::open(&args.filenames[0]) {
+        Ok(reader) => reader,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
         }
+    };
+
+    let mut events_iterator = log_parser::LogEvents::new(
+        io::BufReader::new(reader),
+        args.needs_original_log_line(),
+    ).filter_map(|event| event.ok());
+
+    let mut pairs_iterator = request_response_matcher::RequestResponsePairIterator::new(
+        &mut events_iterator,
+    ).prune_pending(state::max_pending_request_age());
 
-        if args.influxdb_write_url.is_some() {
-            renderers.push(Box::new(render::influxdb::InfluxDbRenderer::new(
-                &args.influxdb_write_url.clone().unwrap(),
-                args.influxdb_tags.clone(),
-            )));
+    let mut analysis = partial_analysis(args);
+    let mut http_renderers = http_renderers(args);
+    let mut next_render = Instant::now() + args.follow_interval;
+
+    loop {
+        match pairs_iterator.next() {
+            Some(pair) => {
+                if filter::matches_filter(&pair, &args.conditions) {
+                    analysis.add(&args.grouping.label(pair));
+                }
+            }
+            None => thread::sleep(FOLLOW_POLL_INTERVAL),
         }
 
-        for mut renderer in renderers {
-            renderer.render(result.clone());
+        if Instant::now() >= next_render {
+            let interval_analysis = mem::replace(&mut analysis, partial_analysis(args));
+            render(args, interval_analysis.result(args.time_unit), &mut http_renderers);
+            next_render += args.follow_interval;
         }
     }
 }
 
-fn get_input(args: &args::RequestLogAnalyzerArgs) -> Result<Box<dyn io::Read>, Error> {
-    let input: Box<dyn io::Read> = match args.filenames[0].as_ref() {
-        "-" => Box::new(io::stdin()),
+/// The reader for the input files, and the summaries of the files that
+/// MultiFile has read (empty for stdin).
+fn get_input(
+    filenames: &[String],
+    error_policy: multi_file::ErrorPolicy,
+) -> Result<(Box<dyn io::Read>, multi_file::Summaries), Error> {
+    let input: (Box<dyn io::Read>, multi_file::Summaries) = match filenames[0].as_ref() {
+        "-" => (Box::new(io::stdin()), multi_file::Summaries::default()),
         _ => {
-            let filenames = args.filenames.clone();
-            Box::new(multi_file::MultiFile::new(filenames))
+            let input = multi_file::MultiFile::new(filenames.to_vec()).error_policy(error_policy);
+            let summaries = input.summaries();
+            (Box::new(input), summaries)
         }
     };
     Ok(input)
 }
 
-fn run(args: &args::RequestLogAnalyzerArgs) -> result::RequestLogAnalyzerResult {
-    let input = match get_input(&args) {
-        Ok(input) => input,
-        Err(err) => {
-            eprintln!("{}", err);
-            process::exit(1);
+/// Analyze the input files. Fails if they can not be read, or with
+/// `--on-read-error fail` if reading one of them fails.
+fn run(args: &args::RequestLogAnalyzerArgs) -> Result<result::RequestLogAnalyzerResult, Error> {
+    let (filenames, skipped_files) =
+        multi_file::check_readable(&args.filenames, args.read_error_policy)?;
+
+    let mut result = analyze(&filenames, args)?;
+
+    if args.read_error_policy == multi_file::ErrorPolicy::Fail {
+        if let Some(error) = result.files.iter().find_map(|file| file.error.as_ref()) {
+            return Err(err_msg(error.clone()));
+        }
+    }
+
+    result.files.extend(skipped_files);
+    Ok(result)
+}
+
+fn analyze(
+    filenames: &[String],
+    args: &args::RequestLogAnalyzerArgs,
+) -> Result<result::RequestLogAnalyzerResult, Error> {
+    let resolved_args;
+    let args = match (args.conditions.relative_to, args.conditions.latest_time) {
+        (filter::TimeAnchor::LogEnd, Some(_)) if filenames[0] != "-" => {
+            resolved_args = anchor_on_log_end(filenames, args);
+            &resolved_args
+        }
+        _ => args,
+    };
+
+    if let Some(ref state_file) = args.state_file {
+        return Ok(analyze_incremental(state_file, filenames, args)?.result(args.time_unit));
+    }
+
+    // Rotated files that end before the time range or start after it can not
+    // contain matches
+    let now = match args.conditions.relative_to {
+        filter::TimeAnchor::Time(time) => time.with_timezone(&Utc),
+        filter::TimeAnchor::Now | filter::TimeAnchor::LogEnd => Utc::now(),
+    };
+
+    let since = args.conditions
+        .latest_time
+        .map(|latest_time| now - latest_time)
+        .into_iter()
+        .chain(args.conditions.since.map(|since| since.with_timezone(&Utc)))
+        .max();
+
+    let filenames = match since {
+        Some(since) => multi_file::discover::skip_files_before(filenames, since),
+        None => filenames.to_vec(),
+    };
+
+    let filenames = match args.conditions.until {
+        Some(until) => {
+            multi_file::discover::skip_files_from(&filenames, until.with_timezone(&Utc))
         }
+        None => filenames,
     };
 
+    if filenames.is_empty() {
+        return Ok(analyzer::PartialAnalysis::new().result(args.time_unit));
+    }
+
+    if args.jobs > 1 && filenames.len() == 1 && filenames[0] != "-" &&
+        !multi_file::compression::is_compressed(&filenames[0]).unwrap_or(true)
+    {
+        return Ok(analyze_file_chunked(&filenames[0], args)?.result(args.time_unit));
+    }
+
+    if args.jobs > 1 && filenames[0] != "-" {
+        return Ok(parallel::analyze_all(&filenames, args.jobs, |filename| {
+            analyze_file(filename, args)
+        }).result(args.time_unit));
+    }
+
+    let (input, summaries) = get_input(&filenames, args.read_error_policy)?;
+
     let reader = io::BufReader::new(input);
 
-    let mut events_iterator = reader
-        .lines()
-        .map(log_parser::parse_line)
-        .filter(|event| event.is_ok())
-        .map(|event| event.unwrap());
+    let mut events_iterator =
+        log_parser::LogEvents::new(reader, args.needs_original_log_line())
+            .filter_map(|event| event.ok());
+
+    // Only a single file is known to be in chronological order
+    let until = match filenames.len() {
+        1 if filenames[0] != "-" => args.conditions.until,
+        _ => None,
+    };
 
     let pairs_iterator = request_response_matcher::RequestResponsePairIterator::new(
         &mut events_iterator,
-    ).filter(|pair| filter::matches_filter(pair, &args.conditions));
+    ).until(until);
+
+    let mut result = match (args.conditions.relative_to, args.conditions.latest_time) {
+        // Stdin can only be read once, so keep the pairs that can be within
+        // -t of its end until the end is known
+        (filter::TimeAnchor::LogEnd, Some(latest_time)) => {
+            let (pairs, end) = filter::latest_pairs(pairs_iterator, latest_time);
+            let conditions = filter::FilterConditions {
+                relative_to: end.map_or(filter::TimeAnchor::LogEnd, filter::TimeAnchor::Time),
+                ..args.conditions.clone()
+            };
+
+            analyzer::analyze_iterator(
+                pairs
+                    .into_iter()
+                    .filter(|pair| filter::matches_filter(pair, &conditions))
+                    .map(|pair| args.grouping.label(pair)),
+                args.time_unit,
+                args.distribution_buckets(),
+            )
+        }
+        _ => {
+            analyzer::analyze_iterator(
+                pairs_iterator
+                    .filter(|pair| filter::matches_filter(pair, &args.conditions))
+                    .map(|pair| args.grouping.label(pair)),
+                args.time_unit,
+                args.distribution_buckets(),
+            )
+        }
+    };
+    result.files = summaries.borrow_mut().drain(..).collect();
+    Ok(result)
+}
+
+/// Resolve `-t` relative to the log end into the time of the latest request
+/// in the files.
+fn anchor_on_log_end(
+    filenames: &[String],
+    args: &args::RequestLogAnalyzerArgs,
+) -> args::RequestLogAnalyzerArgs {
+    let end = filenames
+        .iter()
+        .filter_map(|filename| {
+            multi_file::discover::latest_request_time(filename).unwrap_or(None)
+        })
+        .max();
+
+    let mut resolved_args = args.clone();
+
+    if let Some(end) = end {
+        resolved_args.conditions.relative_to = filter::TimeAnchor::Time(end);
+    }
+
+    resolved_args
+}
+
+fn analyze_file(filename: &str, args: &args::RequestLogAnalyzerArgs) -> analyzer::PartialAnalysis {
+    let input = multi_file::MultiFile::new(vec![String::from(filename)]);
+
+    analyze_multi_file(input, args, args.conditions.until).0
+}
+
+/// Analyze a single uncompressed file by splitting it into one chunk per job.
+///
+/// Requests and responses that end up in different chunks are matched after
+/// all chunks have been analyzed.
+fn analyze_file_chunked(
+    filename: &str,
+    args: &args::RequestLogAnalyzerArgs,
+) -> Result<analyzer::PartialAnalysis, Error> {
+    let chunks = parallel::chunks::split(filename, args.jobs)?;
+
+    let chunk_results = parallel::map_parallel(&chunks, args.jobs, |chunk| {
+        let input = multi_file::MultiFile::new_with_ranges(
+            vec![(String::from(filename), Some(chunk.clone()))],
+        );
+
+        analyze_multi_file(input, args, None)
+    });
+
+    let mut analysis = analyzer::PartialAnalysis::new();
+    let mut unmatched_events = vec![];
+
+    for (chunk_analysis, chunk_unmatched_events) in chunk_results {
+        analysis.merge(chunk_analysis);
+        unmatched_events.extend(chunk_unmatched_events);
+    }
+
+    let (straddling_analysis, _) = analyze_events(&mut unmatched_events.into_iter(), args, None);
+    analysis.merge(straddling_analysis);
+
+    Ok(analysis)
+}
+
+/// Analyze only the lines appended since the previous run with the same state
+/// file, and update the state file.
+///
+/// Requests that were still waiting for their response at the end of the
+/// previous run are matched against the new responses. If a file can not be
+/// read completely, the state file is left as it is.
+fn analyze_incremental(
+    state_file: &str,
+    filenames: &[String],
+    args: &args::RequestLogAnalyzerArgs,
+) -> Result<analyzer::PartialAnalysis, Error> {
+    let previous_state = state::State::load(state_file)?;
+    let (ranges, files) = previous_state.plan(filenames)?;
+
+    let input = multi_file::MultiFile::new_with_ranges(ranges);
+    let summaries = input.summaries();
+    let reader = io::BufReader::new(input);
+
+    let mut events_iterator = previous_state
+        .pending_requests
+        .into_iter()
+        .map(log_parser::log_events::LogEvent::Request)
+        .chain(
+            log_parser::LogEvents::new(reader, args.needs_original_log_line())
+                .filter_map(|event| event.ok()),
+        );
 
-    analyzer::analyze_iterator(pairs_iterator)
+    let (mut analysis, unmatched_events) = analyze_events(&mut events_iterator, args, None);
+
+    for summary in summaries.borrow_mut().drain(..) {
+        if let Some(error) = summary.error {
+            return Err(err_msg(error));
+        }
+
+        analysis.add_file(summary);
+    }
+
+    let pending_requests = unmatched_events
+        .into_iter()
+        .filter_map(|event| match event {
+            log_parser::log_events::LogEvent::Request(request) => Some(request),
+            log_parser::log_events::LogEvent::Response(_) => None,
+        })
+        .collect();
+
+    let state = state::State {
+        files,
+        pending_requests: state::prune_pending_requests(pending_requests),
+    };
+    state.save(state_file)?;
+
+    Ok(analysis)
+}
+
+/// Analyze the files of `input`, adding their summaries to the analysis.
+fn analyze_multi_file(
+    input: multi_file::MultiFile,
+    args: &args::RequestLogAnalyzerArgs,
+    until: Option<DateTime<FixedOffset>>,
+) -> (analyzer::PartialAnalysis, Vec<log_parser::log_events::LogEvent>) {
+    let input = input.error_policy(args.read_error_policy);
+    let summaries = input.summaries();
+
+    let (mut analysis, unmatched_events) = analyze_reader(io::BufReader::new(input), args, until);
+
+    for summary in summaries.borrow_mut().drain(..) {
+        analysis.add_file(summary);
+    }
+
+    (analysis, unmatched_events)
+}
+
+fn analyze_reader<R: io::BufRead>(
+    reader: R,
+    args: &args::RequestLogAnalyzerArgs,
+    until: Option<DateTime<FixedOffset>>,
+) -> (analyzer::PartialAnalysis, Vec<log_parser::log_events::LogEvent>) {
+    let mut events_iterator =
+        log_parser::LogEvents::new(reader, args.needs_original_log_line())
+            .filter_map(|event| event.ok());
+
+    analyze_events(&mut events_iterator, args, until)
+}
+
+/// A partial analysis, which counts the requests into a distribution if a
+/// Prometheus renderer needs one.
+fn partial_analysis(args: &args::RequestLogAnalyzerArgs) -> analyzer::PartialAnalysis {
+    match args.distribution_buckets() {
+        Some(buckets) => analyzer::PartialAnalysis::with_distribution(buckets),
+        None => analyzer::PartialAnalysis::new(),
+    }
+}
+
+/// Analyze the matching pairs of some events, also returning the events that
+/// could not be matched.
+///
+/// With `until`, the events are assumed to be chronological and reading stops
+/// once they have passed it.
+fn analyze_events(
+    events: &mut dyn Iterator<Item = log_parser::log_events::LogEvent>,
+    args: &args::RequestLogAnalyzerArgs,
+    until: Option<DateTime<FixedOffset>>,
+) -> (analyzer::PartialAnalysis, Vec<log_parser::log_events::LogEvent>) {
+    let mut pairs_iterator =
+        request_response_matcher::RequestResponsePairIterator::new(events).until(until);
+    let mut analysis = partial_analysis(args);
+
+    for pair in pairs_iterator.by_ref().filter(|pair| {
+        filter::matches_filter(pair, &args.conditions)
+    })
+    {
+        analysis.add(&args.grouping.label(pair));
+    }
+
+    (analysis, pairs_iterator.into_unmatched_events())
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::temp_files::temp_path;
     use super::*;
 
+    fn parse_args(filenames: &[&str]) -> args::RequestLogAnalyzerArgs {
+        let mut command_line = vec![String::from("request_log_analyzer")];
+        command_line.extend(filenames.iter().map(|filename| String::from(*filename)));
+        args::parse_args(command_line).unwrap()
+    }
+
     #[test]
     fn test_run() {
-        let args = args::RequestLogAnalyzerArgs {
-            filenames: vec![String::from("src/test/simple-1.log")],
-            conditions: filter::FilterConditions {
-                include_terms: None,
-                exclude_terms: None,
-                latest_time: None,
-            },
-            graphite_server: None,
-            graphite_port: Some(2003),
-            graphite_prefix: None,
-            prometheus_listen: None,
-            influxdb_write_url: None,
-            influxdb_tags: None,
-            quiet: false,
-        };
+        let args = parse_args(&["src/test/simple-1.log"]);
 
-        let result = run(&args);
+        let result = run(&args).unwrap();
         assert_eq!(result.count, 2);
 
         let timing = result.timing.unwrap();
-        assert_eq!(timing.min, 7);
-        assert_eq!(timing.max, 10);
+        assert_eq!(timing.min, 7.0);
+        assert_eq!(timing.max, 10.0);
 
         assert!(result.error.is_some());
     }
 
     #[test]
     fn test_get_input_file() {
-        let args = args::RequestLogAnalyzerArgs {
-            filenames: vec![String::from("src/test/simple-1.log")],
-            conditions: filter::FilterConditions {
-                include_terms: None,
-                exclude_terms: None,
-                latest_time: None,
-            },
-            graphite_server: None,
-            graphite_port: Some(2003),
-            graphite_prefix: None,
-            prometheus_listen: None,
-            influxdb_write_url: None,
-            influxdb_tags: None,
-            quiet: false,
-        };
-
-        let result = get_input(&args);
+        let result = get_input(
+            &[String::from("src/test/simple-1.log")],
+            multi_file::ErrorPolicy::Fail,
+        );
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_get_input_stdin() {
-        let args = args::RequestLogAnalyzerArgs {
-            filenames: vec![String::from("-")],
-            conditions: filter::FilterConditions {
-                include_terms: None,
-                exclude_terms: None,
-                latest_time: None,
-            },
-            graphite_server: None,
-            graphite_port: Some(2003),
-            graphite_prefix: None,
-            prometheus_listen: None,
-            influxdb_write_url: None,
-            influxdb_tags: None,
-            quiet: false,
-        };
-
-        let result = get_input(&args);
+        let result = get_input(&[String::from("-")], multi_file::ErrorPolicy::Fail);
         assert!(result.is_ok());
     }
 
     #[test]
     fn test_run_ignore_broken_lines() {
-        let args = args::RequestLogAnalyzerArgs {
-            filenames: vec![String::from("src/test/broken.log")],
-            conditions: filter::FilterConditions {
-                include_terms: None,
-                exclude_terms: None,
-                latest_time: None,
-            },
-            graphite_server: None,
-            graphite_port: Some(2003),
-            graphite_prefix: None,
-            prometheus_listen: None,
-            influxdb_write_url: None,
-            influxdb_tags: None,
-            quiet: false,
-        };
+        let args = parse_args(&["src/test/broken.log"]);
 
-        let result = run(&args);
+        let result = run(&args).unwrap();
         assert_eq!(result.count, 1);
     }
 
     #[test]
     fn test_run_multiple_files() {
-        let args = args::RequestLogAnalyzerArgs {
-            filenames: vec![
-                String::from("src/test/simple-1.log"),
-                String::from("src/test/simple-2.log"),
-            ],
-            conditions: filter::FilterConditions {
-                include_terms: None,
-                exclude_terms: None,
-                latest_time: None,
-            },
-            graphite_server: None,
-            graphite_port: Some(2003),
-            graphite_prefix: None,
-            prometheus_listen: None,
-            influxdb_write_url: None,
-            influxdb_tags: None,
-            quiet: false,
-        };
+        let args = parse_args(&["src/test/simple-1.log", "src/test/simple-2.log"]);
 
-        let result = run(&args);
+        let result = run(&args).unwrap();
         assert_eq!(result.count, 4);
     }
 
     #[test]
     fn test_run_gzipped_file() {
+        let args = parse_args(&["src/test/simple-1.log.gz"]);
+
+        let result = run(&args).unwrap();
+        assert_eq!(result.count, 2);
+    }
+
+    #[test]
+    fn test_run_time_range() {
+        let time = |value| DateTime::parse_from_rfc3339(value).unwrap();
+
+        let args = parse_args(&[
+            "src/test/simple-1.log",
+            "src/test/percentile.log",
+            "--since",
+            "2016-04-08T09:58:00+02:00",
+            "--until",
+            "2016-04-08T08:10:00Z",
+        ]);
+
+        let result = run(&args).unwrap();
+        assert_eq!(result.count, 7);
+
+        // Only simple-1.log starts before --until
         let args = args::RequestLogAnalyzerArgs {
-            filenames: vec![String::from("src/test/simple-1.log.gz")],
             conditions: filter::FilterConditions {
-                include_terms: None,
-                exclude_terms: None,
-                latest_time: None,
+                since: None,
+                until: Some(time("2016-04-08T08:00:00Z")),
+                ..args.conditions
             },
-            graphite_server: None,
-            graphite_port: Some(2003),
-            graphite_prefix: None,
-            prometheus_listen: None,
-            influxdb_write_url: None,
-            influxdb_tags: None,
-            quiet: false,
+            ..args
         };
 
-        let result = run(&args);
+        let result = run(&args).unwrap();
         assert_eq!(result.count, 2);
+        assert_eq!(result.files.len(), 1);
+    }
+
+    #[test]
+    fn test_run_relative_to_log_end() {
+        let args = parse_args(&[
+            "src/test/simple-1.log",
+            "src/test/percentile.log",
+            "-t",
+            "10",
+            "--relative-to",
+            "log-end",
+        ]);
+
+        // percentile.log ends at 10:14:59, the requests at 10:04:59 and later match
+        let result = run(&args).unwrap();
+        assert_eq!(result.count, 7);
+
+        let args = args::RequestLogAnalyzerArgs { jobs: 2, ..args };
+
+        let result = run(&args).unwrap();
+        assert_eq!(result.count, 7);
+    }
+
+    #[test]
+    fn test_run_time_units() {
+        let args = parse_args(&["src/test/units.log"]);
+
+        let result = run(&args).unwrap();
+        assert_eq!(result.count, 3);
+
+        let timing = result.timing.unwrap();
+        assert_eq!(timing.min, 0.0);
+        assert_eq!(timing.max, 1500.0);
+        assert_eq!(timing.avg, 504.0);
+
+        let args = args::RequestLogAnalyzerArgs {
+            time_unit: analyzer::TimeUnit::Microseconds,
+            ..args
+        };
+
+        let timing = run(&args).unwrap().timing.unwrap();
+        assert_eq!(timing.min, 250.0);
+        assert_eq!(timing.avg, 504083.0);
+    }
+
+    #[test]
+    fn test_run_multiple_files_parallel() {
+        let args = parse_args(&[
+            "src/test/simple-1.log",
+            "src/test/simple-2.log",
+            "src/test/simple-1.log.gz",
+            "--jobs",
+            "2",
+        ]);
+
+        let result = run(&args).unwrap();
+        assert_eq!(result.count, 6);
+
+        let timing = result.timing.clone().unwrap();
+        assert_eq!(timing.min, 7.0);
+        assert_eq!(timing.max, 10.0);
+
+        let sequential_args = args::RequestLogAnalyzerArgs { jobs: 1, ..args };
+        assert_eq!(run(&sequential_args).unwrap(), result);
+    }
+
+    #[test]
+    fn test_run_single_file_chunked() {
+        for filename in &[
+            "src/test/simple-1.log",
+            "src/test/percentile.log",
+            "src/test/response-time-calculations.log",
+            "src/test/broken.log",
+        ]
+        {
+            let base = parse_args(&[filename]);
+            let expected = run(&base).unwrap();
+
+            for jobs in 2..6 {
+                let args = args::RequestLogAnalyzerArgs { jobs, ..base.clone() };
+                assert_eq!(run(&args).unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_skip_unreadable_files() {
+        let args = parse_args(&[
+            "src/test/simple-1.log",
+            "src/test/non-existent.log",
+            "--on-read-error",
+            "skip",
+        ]);
+
+        let result = run(&args).unwrap();
+        assert_eq!(result.count, 2);
+
+        assert_eq!(result.files.len(), 2);
+        assert_eq!(result.files[0].lines, 4);
+        assert_eq!(result.files[0].error, None);
+        assert_eq!(result.files[1].filename, "src/test/non-existent.log");
+        assert!(result.files[1].error.is_some());
+    }
+
+    #[test]
+    fn test_run_incremental() {
+        let log_file = temp_path("main-incremental.log");
+        let state_file = temp_path("main-incremental.state");
+
+        let lines: Vec<String> = std::fs::read_to_string("src/test/simple-1.log")
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+
+        let args = parse_args(&[
+            &log_file,
+            "--state-file",
+            &state_file,
+        ]);
+
+        // The response to the second request is only written after the first run
+        std::fs::write(&log_file, format!("{}\n{}\n{}\n", lines[0], lines[1], lines[2])).unwrap();
+        assert_eq!(run(&args).unwrap().count, 1);
+
+        std::fs::write(&log_file, format!("{}\n", lines.join("\n"))).unwrap();
+        assert_eq!(run(&args).unwrap().count, 1);
+
+        assert_eq!(run(&args).unwrap().count, 0);
+
+        std::fs::remove_file(&log_file).unwrap();
+        std::fs::remove_file(&state_file).unwrap();
     }
 }
@@ -0,0 +1,376 @@
+use std::cmp;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use flate2::read::{DeflateDecoder, MultiGzDecoder};
+use log::warn;
+use crate::multi_file::discover;
+
+/// Enough bytes to recognize every format, including the tar header
+const HEADER_SIZE: usize = 512;
+const TAR_MAGIC_OFFSET: usize = 257;
+
+/// Format of a file or stream, recognized by its first bytes.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Format {
+    Plain,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+    Zip,
+    Tar,
+}
+
+pub fn detect(header: &[u8]) -> Format {
+    if header.starts_with(&[0x1f, 0x8b]) {
+        Format::Gzip
+    } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Format::Zstd
+    } else if header.starts_with(b"BZh") {
+        Format::Bzip2
+    } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Format::Xz
+    } else if header.starts_with(b"PK\x03\x04") {
+        Format::Zip
+    } else if header.len() >= TAR_MAGIC_OFFSET + 5 &&
+               &header[TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 5] == b"ustar"
+    {
+        Format::Tar
+    } else {
+        Format::Plain
+    }
+}
+
+/// Whether a file is anything other than plain text.
+pub fn is_compressed(filename: &str) -> io::Result<bool> {
+    let mut header = vec![];
+    File::open(filename)?
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut header)?;
+
+    Ok(detect(&header) != Format::Plain)
+}
+
+/// Open a file for reading its decompressed contents.
+///
+/// The members of zip and tar archives are read one after another. Zip
+/// members are ordered like rotated log files, tar members are read in the
+/// order of the archive.
+pub fn open(filename: &str) -> io::Result<Box<dyn Read>> {
+    let mut file = File::open(filename)?;
+    let mut header = vec![];
+    (&file).take(HEADER_SIZE as u64).read_to_end(&mut header)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    if detect(&header) == Format::Zip {
+        return Ok(Box::new(ZipMembers::open(filename)?));
+    }
+
+    match decompress(Box::new(file))? {
+        (Format::Tar, archive) => Ok(Box::new(TarMembers::new(archive))),
+        (Format::Zip, _) => Err(invalid_data(
+            format!("{}: compressed zip archives are not supported", filename),
+        )),
+        (_, reader) => Ok(reader),
+    }
+}
+
+/// Remove all compression layers from a stream, returning the format of the
+/// data underneath (plain text or an archive) and a reader for it.
+fn decompress<'a>(reader: Box<dyn Read + 'a>) -> io::Result<(Format, Box<dyn Read + 'a>)> {
+    let mut reader = reader;
+    let mut header = vec![];
+    reader.by_ref().take(HEADER_SIZE as u64).read_to_end(
+        &mut header,
+    )?;
+
+    let format = detect(&header);
+    let reader = io::Cursor::new(header).chain(reader);
+
+    match format {
+        Format::Gzip => decompress(Box::new(MultiGzDecoder::new(reader))),
+        Format::Zstd => decompress(Box::new(zstd::Decoder::new(reader)?)),
+        Format::Bzip2 => decompress(Box::new(bzip2::read::MultiBzDecoder::new(reader))),
+        Format::Xz => decompress(Box::new(xz2::read::XzDecoder::new_multi_decoder(reader))),
+        Format::Plain | Format::Zip | Format::Tar => Ok((format, Box::new(reader))),
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the contents of all files in a zip archive.
+///
+/// Every member is opened on its own at its offset in the archive, so no
+/// member has to be held in memory.
+struct ZipMembers {
+    filename: String,
+    members: Vec<ZipMember>,
+    current: Option<Box<dyn Read>>,
+}
+
+struct ZipMember {
+    name: String,
+    data_start: u64,
+    compressed_size: u64,
+    compression: zip::CompressionMethod,
+}
+
+impl ZipMembers {
+    fn open(filename: &str) -> io::Result<ZipMembers> {
+        let mut archive = zip::ZipArchive::new(File::open(filename)?).map_err(
+            |err| {
+                invalid_data(format!("{}: {}", filename, err))
+            },
+        )?;
+
+        let mut members = vec![];
+
+        for index in 0..archive.len() {
+            let member = archive.by_index_raw(index).map_err(|err| {
+                invalid_data(format!("{}: {}", filename, err))
+            })?;
+
+            if member.is_file() {
+                members.push(ZipMember {
+                    name: String::from(member.name()),
+                    data_start: member.data_start(),
+                    compressed_size: member.compressed_size(),
+                    compression: member.compression(),
+                });
+            }
+        }
+
+        let mut names: Vec<String> = members.iter().map(|member| member.name.clone()).collect();
+        discover::sort_by_rotation(&mut names);
+
+        // Reversed, so the next member can be popped off the end
+        members.sort_by_key(|member| {
+            cmp::Reverse(names.iter().position(|name| *name == member.name))
+        });
+
+        Ok(ZipMembers {
+            filename: String::from(filename),
+            members,
+            current: None,
+        })
+    }
+
+    fn open_member(&self, member: &ZipMember) -> io::Result<Box<dyn Read>> {
+        let mut file = File::open(&self.filename)?;
+        file.seek(SeekFrom::Start(member.data_start))?;
+        let data = file.take(member.compressed_size);
+
+        let reader: Box<dyn Read> = match member.compression {
+            zip::CompressionMethod::Stored => Box::new(data),
+            zip::CompressionMethod::Deflated => Box::new(DeflateDecoder::new(data)),
+            method => {
+                return Err(invalid_data(format!(
+                    "{}: {} uses the unsupported compression method {}",
+                    self.filename,
+                    member.name,
+                    method
+                )))
+            }
+        };
+
+        match decompress(reader)? {
+            (Format::Plain, reader) => Ok(reader),
+            _ => {
+                warn!("{}: skipping the nested archive {}", self.filename, member.name);
+                Ok(Box::new(io::empty()))
+            }
+        }
+    }
+}
+
+impl Read for ZipMembers {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(ref mut current) = self.current {
+                match current.read(buf)? {
+                    0 => self.current = None,
+                    read_size => return Ok(read_size),
+                }
+            }
+
+            match self.members.pop() {
+                Some(member) => self.current = Some(self.open_member(&member)?),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+/// Reads the contents of all regular files in a tar stream.
+struct TarMembers<R> {
+    archive: R,
+    remaining: u64,
+    padding: u64,
+    finished: bool,
+}
+
+impl<R: Read> TarMembers<R> {
+    fn new(archive: R) -> TarMembers<R> {
+        TarMembers {
+            archive,
+            remaining: 0,
+            padding: 0,
+            finished: false,
+        }
+    }
+
+    /// Skip to the next regular file, returning false at the end of the archive.
+    fn next_member(&mut self) -> io::Result<bool> {
+        loop {
+            io::copy(
+                &mut self.archive.by_ref().take(self.remaining + self.padding),
+                &mut io::sink(),
+            )?;
+
+            let mut header = vec![];
+            self.archive.by_ref().take(HEADER_SIZE as u64).read_to_end(
+                &mut header,
+            )?;
+
+            if header.iter().all(|byte| *byte == 0) {
+                return Ok(false);
+            }
+
+            if header.len() < HEADER_SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Tar archive ends within a header",
+                ));
+            }
+
+            let size = tar_size(&header[124..136])
+                .ok_or_else(|| invalid_data(String::from("Invalid size in tar header")))?;
+
+            self.remaining = size;
+            self.padding = (HEADER_SIZE as u64 - size % HEADER_SIZE as u64) % HEADER_SIZE as u64;
+
+            // Regular files, everything else (directories, links, long names) is skipped
+            if header[156] == b'0' || header[156] == 0 {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for TarMembers<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.remaining == 0 {
+            if self.finished || !self.next_member()? {
+                self.finished = true;
+                return Ok(0);
+            }
+        }
+
+        let max_size = cmp::min(self.remaining, buf.len() as u64) as usize;
+        let read_size = self.archive.read(&mut buf[..max_size])?;
+
+        if read_size == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Tar archive ends within a file",
+            ));
+        }
+
+        self.remaining -= read_size as u64;
+        Ok(read_size)
+    }
+}
+
+/// Parse the size field of a tar header, octal or GNU base-256.
+fn tar_size(field: &[u8]) -> Option<u64> {
+    if field[0] & 0x80 != 0 {
+        return Some(field[1..].iter().fold(0, |size, byte| size << 8 | *byte as u64));
+    }
+
+    let digits = std::str::from_utf8(field).ok()?;
+    let digits = digits.trim_matches(|c| c == '\0' || c == ' ');
+
+    if digits.is_empty() {
+        return Some(0);
+    }
+
+    u64::from_str_radix(digits, 8).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufRead;
+    use super::*;
+
+    fn read_lines(filename: &str) -> Vec<String> {
+        let reader = io::BufReader::new(open(filename).unwrap());
+
+        reader.lines().map(|line| line.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(b"08/Apr/2016:09:57:47 +0200 [001]"), Format::Plain);
+        assert_eq!(detect(b""), Format::Plain);
+        assert_eq!(detect(&[0x1f, 0x8b, 0x08]), Format::Gzip);
+        assert_eq!(detect(b"BZh91AY&SY"), Format::Bzip2);
+        assert_eq!(detect(b"PK\x03\x04\x14\x00"), Format::Zip);
+
+        let mut tar_header = vec![0; HEADER_SIZE];
+        tar_header[TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 6].copy_from_slice(b"ustar\0");
+        assert_eq!(detect(&tar_header), Format::Tar);
+    }
+
+    #[test]
+    fn test_is_compressed() {
+        assert!(!is_compressed("src/test/simple-1.log").unwrap());
+        assert!(is_compressed("src/test/simple-1.log.gz").unwrap());
+        assert!(is_compressed("src/test/non-existent.log").is_err());
+    }
+
+    #[test]
+    fn test_open_compressed() {
+        let expected = read_lines("src/test/simple-1.log");
+
+        for filename in &[
+            "src/test/simple-1.log.gz",
+            "src/test/simple-1.log.zst",
+            "src/test/simple-1.log.bz2",
+            "src/test/simple-1.log.xz",
+        ]
+        {
+            assert_eq!(read_lines(filename), expected, "{}", filename);
+        }
+    }
+
+    #[test]
+    fn test_open_plain_file_with_misleading_name() {
+        assert_eq!(read_lines("src/test/plain.log.gz").len(), 4);
+    }
+
+    #[test]
+    fn test_open_archives() {
+        let expected: Vec<String> = read_lines("src/test/simple-1.log")
+            .into_iter()
+            .chain(read_lines("src/test/simple-2.log"))
+            .collect();
+
+        // The zip contains simple-2.log as request.log and simple-1.log as
+        // request.log.1.gz, the tar.gz contains them in archive order
+        assert_eq!(read_lines("src/test/support-bundle.zip"), expected);
+        assert_eq!(read_lines("src/test/support-bundle.tar.gz"), expected);
+    }
+
+    #[test]
+    fn test_tar_size() {
+        assert_eq!(tar_size(b"00000000144\0"), Some(100));
+        assert_eq!(tar_size(b"        144 "), Some(100));
+        assert_eq!(tar_size(b"\0\0\0\0\0\0\0\0\0\0\0\0"), Some(0));
+        assert_eq!(tar_size(&[0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x00]), Some(256));
+        assert_eq!(tar_size(b"0000000009x\0"), None);
+    }
+}
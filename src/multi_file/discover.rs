@@ -0,0 +1,391 @@
+use std::cmp;
+use std::cmp::Reverse;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::time::SystemTime;
+use chrono::*;
+use failure::{Error, err_msg};
+use glob::glob;
+use crate::log_parser;
+use crate::log_parser::log_events::LogEvent;
+use crate::multi_file::compression;
+
+/// How much of the end of a file is searched for its latest request, and of
+/// the start for its earliest
+const TAIL_SIZE: u64 = 64 * 1024;
+
+/// The name of the request log of AEM, the only files read of a directory
+const REQUEST_LOG: &str = "request.log";
+
+/// Extensions of compressed files, ignored for ordering
+const COMPRESSION_SUFFIXES: [&str; 5] = [".gz", ".zst", ".bz2", ".xz", ".zip"];
+
+/// Position of a file in the rotation of its log, oldest first.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Rotation {
+    /// `request.log.2024-01-01` or `request.log-20240101`
+    Dated(NaiveDate),
+    /// `request.log.1`, a higher number is older
+    Numbered(Reverse<u32>),
+    /// `request.log`, the file that is currently written
+    Current,
+}
+
+/// Expand directories and glob patterns into the files they contain, each
+/// log ordered from its oldest rotated file to the current one. Of a
+/// directory, only `request.log` and its rotated files are taken.
+///
+/// Plain file names and `-` for stdin are kept as they are.
+pub fn expand_inputs(inputs: &[String]) -> Result<Vec<String>, Error> {
+    let mut filenames = vec![];
+
+    for input in inputs {
+        let mut expanded = if Path::new(input).is_dir() {
+            directory_files(input)?
+        } else if input.contains(['*', '?', '[']) {
+            glob_files(input)?
+        } else {
+            filenames.push(input.clone());
+            continue;
+        };
+
+        if expanded.is_empty() {
+            return Err(err_msg(format!("No log files found for {}", input)));
+        }
+
+        sort_by_rotation(&mut expanded);
+        filenames.extend(expanded);
+    }
+
+    Ok(filenames)
+}
+
+fn directory_files(directory: &str) -> Result<Vec<String>, Error> {
+    let mut filenames = vec![];
+
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+
+        if path.is_file() && is_request_log(&path) {
+            if let Some(filename) = path.to_str() {
+                filenames.push(String::from(filename));
+            }
+        }
+    }
+
+    Ok(filenames)
+}
+
+/// Whether the file is `request.log` or one of its rotations, like
+/// `request.log.1`, `request.log.2024-01-01.gz` or `request.log-20240101`.
+fn is_request_log(path: &Path) -> bool {
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => match name.strip_prefix(REQUEST_LOG) {
+            Some(rotation) => rotation.is_empty() || rotation.starts_with(['.', '-']),
+            None => false,
+        },
+        None => false,
+    }
+}
+
+fn glob_files(pattern: &str) -> Result<Vec<String>, Error> {
+    let mut filenames = vec![];
+
+    for path in glob(pattern)? {
+        let path = path?;
+
+        if path.is_file() {
+            if let Some(filename) = path.to_str() {
+                filenames.push(String::from(filename));
+            }
+        }
+    }
+
+    Ok(filenames)
+}
+
+/// Group the files of each log and order them chronologically.
+pub fn sort_by_rotation(filenames: &mut [String]) {
+    filenames.sort_by_key(|filename| rotation_order(filename));
+}
+
+/// Sort key that groups the files of a log and orders them chronologically.
+fn rotation_order(filename: &str) -> (String, Rotation, String) {
+    let name = COMPRESSION_SUFFIXES
+        .iter()
+        .find_map(|suffix| filename.strip_suffix(suffix))
+        .unwrap_or(filename);
+
+    let (base, rotation) = ['.', '-']
+        .iter()
+        .find_map(|separator| {
+            let position = name.rfind(*separator)?;
+            let rotation = rotation_suffix(&name[position + 1..])?;
+
+            Some((&name[..position], rotation))
+        })
+        .unwrap_or((name, Rotation::Current));
+
+    (String::from(base), rotation, String::from(filename))
+}
+
+fn rotation_suffix(suffix: &str) -> Option<Rotation> {
+    if let Ok(date) = NaiveDate::parse_from_str(suffix, "%Y-%m-%d") {
+        return Some(Rotation::Dated(date));
+    }
+
+    if suffix.len() == 8 {
+        if let Ok(date) = NaiveDate::parse_from_str(suffix, "%Y%m%d") {
+            return Some(Rotation::Dated(date));
+        }
+    }
+
+    if suffix.len() <= 4 {
+        if let Ok(number) = suffix.parse() {
+            return Some(Rotation::Numbered(Reverse(number)));
+        }
+    }
+
+    None
+}
+
+/// Leave out the files that were completely written before `since`.
+///
+/// The end of a file is the time of its last request, or the modification
+/// time for compressed files. Files whose end is unknown are kept.
+pub fn skip_files_before(filenames: &[String], since: DateTime<Utc>) -> Vec<String> {
+    filenames
+        .iter()
+        .filter(|filename| match last_time(filename) {
+            Some(last_time) => last_time >= since,
+            None => true,
+        })
+        .cloned()
+        .collect()
+}
+
+/// Leave out the files that were started at or after `until`, judged by the
+/// time of their first request. Files whose start is unknown are kept.
+pub fn skip_files_from(filenames: &[String], until: DateTime<Utc>) -> Vec<String> {
+    filenames
+        .iter()
+        .filter(|filename| match first_request_time(filename) {
+            Ok(Some(first_time)) => first_time < until,
+            _ => true,
+        })
+        .cloned()
+        .collect()
+}
+
+/// The time of the latest request in a file. Only the end of plain files is
+/// read, compressed files are read completely.
+pub fn latest_request_time(filename: &str) -> io::Result<Option<DateTime<FixedOffset>>> {
+    let reader: Box<dyn Read> = if compression::is_compressed(filename)? {
+        compression::open(filename)?
+    } else {
+        let mut file = File::open(filename)?;
+        let size = file.metadata()?.len();
+        file.seek(SeekFrom::Start(size.saturating_sub(TAIL_SIZE)))?;
+        Box::new(file)
+    };
+
+    let mut latest = None;
+
+    for line in io::BufReader::new(reader).split(b'\n') {
+        if let Ok(LogEvent::Request(request)) = log_parser::parse_line(&line?, false) {
+            latest = cmp::max(latest, Some(request.time));
+        }
+    }
+
+    Ok(latest)
+}
+
+fn last_time(filename: &str) -> Option<DateTime<Utc>> {
+    if filename == "-" {
+        return None;
+    }
+
+    if let Ok(false) = compression::is_compressed(filename) {
+        if let Ok(Some(time)) = latest_request_time(filename) {
+            return Some(time.with_timezone(&Utc));
+        }
+    }
+
+    let modified = fs::metadata(filename).and_then(|metadata| metadata.modified()).ok()?;
+    let seconds = modified.duration_since(SystemTime::UNIX_EPOCH).ok()?.as_secs();
+
+    Utc.timestamp_opt(seconds as i64, 0).single()
+}
+
+fn first_request_time(filename: &str) -> io::Result<Option<DateTime<Utc>>> {
+    if filename == "-" {
+        return Ok(None);
+    }
+
+    let mut head = vec![];
+    compression::open(filename)?.take(TAIL_SIZE).read_to_end(&mut head)?;
+
+    Ok(head.split(|byte| *byte == b'\n').find_map(|line| {
+        match log_parser::parse_line(line, false) {
+            Ok(LogEvent::Request(request)) => Some(request.time.with_timezone(&Utc)),
+            _ => None,
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::temp_files::temp_directory;
+    use super::*;
+
+    #[test]
+    fn test_rotation_order() {
+        let mut filenames = vec![
+            "logs/request.log",
+            "logs/access.log",
+            "logs/request.log.1",
+            "logs/request.log.2.gz",
+            "logs/request.log.3.zst",
+            "logs/request.log.10",
+            "logs/access.log-20240102",
+            "logs/access.log-20231231.gz",
+        ];
+
+        filenames.sort_by_key(|filename| rotation_order(filename));
+
+        assert_eq!(
+            filenames,
+            vec![
+                "logs/access.log-20231231.gz",
+                "logs/access.log-20240102",
+                "logs/access.log",
+                "logs/request.log.10",
+                "logs/request.log.3.zst",
+                "logs/request.log.2.gz",
+                "logs/request.log.1",
+                "logs/request.log",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rotation_order_dated() {
+        let mut filenames = vec![
+            "request.log",
+            "request.log.2024-01-02",
+            "request.log.2023-12-31.gz",
+        ];
+
+        filenames.sort_by_key(|filename| rotation_order(filename));
+
+        assert_eq!(
+            filenames,
+            vec![
+                "request.log.2023-12-31.gz",
+                "request.log.2024-01-02",
+                "request.log",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_inputs_directory_and_glob() {
+        let directory = temp_directory("discover-expand");
+        for name in &[
+            "request.log",
+            "request.log.1",
+            "request.log.2.gz",
+            "request.log-20240101",
+            "request.logger",
+            "error.log",
+        ]
+        {
+            File::create(format!("{}/{}", directory, name)).unwrap();
+        }
+        fs::create_dir(format!("{}/archive", directory)).unwrap();
+
+        let result = expand_inputs(&[
+            String::from("first.log"),
+            format!("{}/request.log.*", directory),
+            directory.clone(),
+        ]).unwrap();
+
+        // Of the directory, error.log and request.logger are left out
+        let expected: Vec<String> = vec![
+            String::from("first.log"),
+            format!("{}/request.log.2.gz", directory),
+            format!("{}/request.log.1", directory),
+            format!("{}/request.log-20240101", directory),
+            format!("{}/request.log.2.gz", directory),
+            format!("{}/request.log.1", directory),
+            format!("{}/request.log", directory),
+        ];
+        assert_eq!(result, expected);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_expand_inputs_no_match() {
+        let result = expand_inputs(&[String::from("src/test/*.nothing")]);
+
+        let error_message = match result {
+            Err(err) => format!("{}", err),
+            Ok(_) => unreachable!(),
+        };
+
+        assert!(error_message.contains("No log files found for src/test/*.nothing"));
+    }
+
+    #[test]
+    fn test_skip_files_before() {
+        let filenames = vec![
+            String::from("-"),
+            String::from("src/test/simple-1.log"),
+            String::from("src/test/percentile.log"),
+        ];
+
+        // simple-1.log ends at 07:58:47 UTC, percentile.log at 08:14:59 UTC
+        let since = Utc.with_ymd_and_hms(2016, 4, 8, 7, 58, 47).unwrap();
+        assert_eq!(skip_files_before(&filenames, since), filenames);
+
+        let since = Utc.with_ymd_and_hms(2016, 4, 8, 8, 0, 0).unwrap();
+        assert_eq!(
+            skip_files_before(&filenames, since),
+            vec![String::from("-"), String::from("src/test/percentile.log")]
+        );
+    }
+
+    #[test]
+    fn test_latest_request_time() {
+        let latest = DateTime::parse_from_rfc3339("2016-04-08T09:58:47+02:00").unwrap();
+
+        assert_eq!(latest_request_time("src/test/simple-1.log").unwrap(), Some(latest));
+        assert_eq!(latest_request_time("src/test/simple-1.log.xz").unwrap(), Some(latest));
+        assert_eq!(latest_request_time("src/test/empty.log").unwrap(), None);
+        assert!(latest_request_time("src/test/non-existent.log").is_err());
+    }
+
+    #[test]
+    fn test_skip_files_from() {
+        let filenames = vec![
+            String::from("-"),
+            String::from("src/test/simple-1.log.gz"),
+            String::from("src/test/percentile.log"),
+        ];
+
+        // simple-1.log starts at 07:57:47 UTC, percentile.log at 08:00:59 UTC
+        let until = Utc.with_ymd_and_hms(2016, 4, 8, 8, 1, 0).unwrap();
+        assert_eq!(skip_files_from(&filenames, until), filenames);
+
+        let until = Utc.with_ymd_and_hms(2016, 4, 8, 8, 0, 59).unwrap();
+        assert_eq!(
+            skip_files_from(&filenames, until),
+            vec![String::from("-"), String::from("src/test/simple-1.log.gz")]
+        );
+    }
+}
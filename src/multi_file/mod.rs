@@ -1,47 +1,207 @@
+use std::cell::RefCell;
 use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
 use std::fs::File;
-use flate2::read::GzDecoder;
+use std::ops::Range;
+use std::rc::Rc;
+use failure::{Error, err_msg};
+use log::warn;
+use crate::result::FileSummary;
+
+pub mod compression;
+pub mod discover;
+
+/// A file name and the byte range to read from it, `None` for the whole file
+pub type FileRange = (String, Option<Range<u64>>);
+
+/// Summaries of the files read by a `MultiFile`, shared with its owner
+pub type Summaries = Rc<RefCell<Vec<FileSummary>>>;
+
+/// What to do when an input file can not be read.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ErrorPolicy {
+    /// Stop reading, the run fails
+    Fail,
+    /// Warn and continue with the next file
+    Skip,
+}
 
 pub struct MultiFile {
-    files_iterator: Box<dyn Iterator<Item = String>>,
+    files_iterator: Box<dyn Iterator<Item = FileRange>>,
     current_file: Option<Box<dyn io::Read>>,
+    current_summary: Option<FileSummary>,
+    ends_with_line_break: bool,
+    error_policy: ErrorPolicy,
+    summaries: Summaries,
 }
 
 impl MultiFile {
     pub fn new(filenames: Vec<String>) -> MultiFile {
+        MultiFile::new_with_ranges(filenames.into_iter().map(|filename| (filename, None)).collect())
+    }
+
+    /// Read only a byte range of each file, or the whole file for `None`.
+    pub fn new_with_ranges(files: Vec<FileRange>) -> MultiFile {
         MultiFile {
-            files_iterator: Box::new(filenames.into_iter()),
+            files_iterator: Box::new(files.into_iter()),
             current_file: None,
+            current_summary: None,
+            ends_with_line_break: true,
+            error_policy: ErrorPolicy::Fail,
+            summaries: Rc::new(RefCell::new(vec![])),
+        }
+    }
+
+    pub fn error_policy(mut self, error_policy: ErrorPolicy) -> MultiFile {
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// The summaries of the files that have been read completely or failed,
+    /// updated while reading.
+    pub fn summaries(&self) -> Summaries {
+        self.summaries.clone()
+    }
+
+    fn open_next(&mut self) -> Option<io::Result<Box<dyn io::Read>>> {
+        let (filename, range) = self.files_iterator.next()?;
+
+        self.current_summary = Some(FileSummary {
+            filename: filename.clone(),
+            lines: 0,
+            error: None,
+        });
+        self.ends_with_line_break = true;
+
+        Some(match range {
+            Some(range) => {
+                open_range(&filename, &range).map(|file| Box::new(file) as Box<dyn io::Read>)
+            }
+            None => compression::open(&filename),
+        })
+    }
+
+    /// Record the summary of the current file, returning the error that
+    /// should stop reading.
+    fn finish_file(&mut self, error: Option<io::Error>) -> Option<io::Error> {
+        self.current_file = None;
+
+        let mut summary = self.current_summary.take()?;
+
+        if !self.ends_with_line_break {
+            summary.lines += 1;
+        }
+
+        let error = error.map(|err| {
+            io::Error::new(err.kind(), format!("{}: {}", summary.filename, err))
+        });
+
+        summary.error = error.as_ref().map(|err| err.to_string());
+        self.summaries.borrow_mut().push(summary);
+
+        match (error, self.error_policy) {
+            (Some(err), ErrorPolicy::Fail) => {
+                self.files_iterator = Box::new(std::iter::empty());
+                Some(err)
+            }
+            (Some(err), ErrorPolicy::Skip) => {
+                warn!("Skipping the rest of {}", err);
+                None
+            }
+            (None, _) => None,
+        }
+    }
+}
+
+/// Open a byte range of an uncompressed file for reading.
+pub fn open_range(filename: &str, range: &Range<u64>) -> io::Result<io::Take<File>> {
+    let mut file = File::open(filename)?;
+    file.seek(SeekFrom::Start(range.start))?;
+
+    Ok(file.take(range.end.saturating_sub(range.start)))
+}
+
+/// Check that all files can be opened before starting the analysis.
+///
+/// Returns the files to read, and the summaries of the skipped files.
+pub fn check_readable(
+    filenames: &[String],
+    error_policy: ErrorPolicy,
+) -> Result<(Vec<String>, Vec<FileSummary>), Error> {
+    let mut readable = vec![];
+    let mut skipped = vec![];
+
+    for filename in filenames {
+        if filename == "-" {
+            readable.push(filename.clone());
+            continue;
+        }
+
+        match File::open(filename) {
+            Ok(_) => readable.push(filename.clone()),
+            Err(err) => {
+                let message = format!("Can not read {}: {}", filename, err);
+
+                if error_policy == ErrorPolicy::Fail {
+                    return Err(err_msg(message));
+                }
+
+                warn!("{}", message);
+                skipped.push(FileSummary {
+                    filename: filename.clone(),
+                    lines: 0,
+                    error: Some(message),
+                });
+            }
         }
     }
+
+    Ok((readable, skipped))
 }
 
 impl io::Read for MultiFile {
-    fn read(&mut self, mut buf: &mut [u8]) -> io::Result<usize> {
-        let read_size = match self.current_file {
-            Some(ref mut file) => file.read(buf),
-            None => {
-                self.current_file = match &self.files_iterator.next() {
-                    Some(file) if file.ends_with(".gz") => Some(Box::new(
-                        GzDecoder::new(File::open(file)?),
-                    )),
-                    Some(file) => Some(Box::new(File::open(file)?)),
-                    None => {
-                        return Ok(0);
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read_size = match self.current_file {
+                Some(ref mut file) => file.read(buf),
+                None => {
+                    match self.open_next() {
+                        Some(Ok(file)) => self.current_file = Some(file),
+                        Some(Err(err)) => {
+                            if let Some(err) = self.finish_file(Some(err)) {
+                                return Err(err);
+                            }
+                        }
+                        None => return Ok(0),
                     }
-                };
-                return self.read(&mut buf);
-            }
-        };
+                    continue;
+                }
+            };
 
-        match read_size {
-            Ok(0) => {
-                // EOF, proceed with next file
-                self.current_file = None;
-                self.read(&mut buf)
+            match read_size {
+                Ok(0) => {
+                    // EOF, proceed with next file
+                    self.finish_file(None);
+                }
+                Ok(read_size) => {
+                    if let Some(ref mut summary) = self.current_summary {
+                        summary.lines += buf[..read_size]
+                            .iter()
+                            .filter(|byte| **byte == b'\n')
+                            .count();
+                    }
+                    self.ends_with_line_break = buf[read_size - 1] == b'\n';
+
+                    return Ok(read_size);
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    if let Some(err) = self.finish_file(Some(err)) {
+                        return Err(err);
+                    }
+                }
             }
-            Ok(read_size) => Ok(read_size),
-            Err(err) => Err(err),
         }
     }
 }
@@ -126,4 +286,122 @@ mod tests {
         let result = reader.lines().count();
         assert_eq!(result, 8);
     }
+
+    #[test]
+    fn test_read_ranges() {
+        let files = vec![
+            (String::from("src/test/simple-1.log"), Some(127..200)),
+            (String::from("src/test/simple-2.log"), None),
+            (String::from("src/test/simple-1.log"), Some(200..200)),
+        ];
+        let input = MultiFile::new_with_ranges(files);
+
+        let reader = io::BufReader::new(input);
+        let lines: Vec<String> = reader.lines().map(|line| line.unwrap()).collect();
+
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[0],
+            "08/Apr/2016:09:58:47 +0200 [02] -> GET /content/some/other.html HTTP/1.1"
+        );
+    }
+
+    #[test]
+    fn test_read_summaries() {
+        let filenames = vec![
+            String::from("src/test/simple-1.log"),
+            String::from("src/test/empty.log"),
+            String::from("src/test/simple-1.log.gz"),
+        ];
+        let input = MultiFile::new(filenames);
+        let summaries = input.summaries();
+
+        let reader = io::BufReader::new(input);
+        assert_eq!(reader.lines().count(), 8);
+
+        let summary = |filename: &str, lines| {
+            FileSummary {
+                filename: String::from(filename),
+                lines,
+                error: None,
+            }
+        };
+        assert_eq!(
+            *summaries.borrow(),
+            vec![
+                summary("src/test/simple-1.log", 4),
+                summary("src/test/empty.log", 0),
+                summary("src/test/simple-1.log.gz", 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_summary_without_final_line_break() {
+        let files = vec![(String::from("src/test/simple-1.log"), Some(0..80))];
+        let input = MultiFile::new_with_ranges(files);
+        let summaries = input.summaries();
+
+        let reader = io::BufReader::new(input);
+        assert_eq!(reader.lines().count(), 2);
+
+        assert_eq!(summaries.borrow()[0].lines, 2);
+    }
+
+    #[test]
+    fn test_read_error_fail() {
+        let filenames = vec![
+            String::from("src/test/non-existent.log"),
+            String::from("src/test/simple-1.log"),
+        ];
+        let mut input = MultiFile::new(filenames);
+        let summaries = input.summaries();
+        let mut buffer = [0; 10];
+
+        let error_message = format!("{}", input.read(&mut buffer).unwrap_err());
+        assert!(error_message.starts_with("src/test/non-existent.log: "));
+
+        assert_eq!(input.read(&mut buffer).unwrap(), 0);
+        assert_eq!(summaries.borrow().len(), 1);
+        assert_eq!(summaries.borrow()[0].error, Some(error_message));
+    }
+
+    #[test]
+    fn test_read_error_skip() {
+        let filenames = vec![
+            String::from("src/test/non-existent.log"),
+            String::from("src/test/simple-1.log"),
+        ];
+        let input = MultiFile::new(filenames).error_policy(ErrorPolicy::Skip);
+        let summaries = input.summaries();
+
+        let reader = io::BufReader::new(input);
+        assert_eq!(reader.lines().count(), 4);
+
+        assert!(summaries.borrow()[0].error.is_some());
+        assert_eq!(summaries.borrow()[1].lines, 4);
+    }
+
+    #[test]
+    fn test_check_readable() {
+        let filenames = vec![
+            String::from("-"),
+            String::from("src/test/non-existent.log"),
+            String::from("src/test/simple-1.log"),
+        ];
+
+        let error_message = match check_readable(&filenames, ErrorPolicy::Fail) {
+            Err(err) => format!("{}", err),
+            Ok(_) => unreachable!(),
+        };
+        assert!(error_message.contains("Can not read src/test/non-existent.log"));
+
+        let (readable, skipped) = check_readable(&filenames, ErrorPolicy::Skip).unwrap();
+        assert_eq!(
+            readable,
+            vec![String::from("-"), String::from("src/test/simple-1.log")]
+        );
+        assert_eq!(skipped[0].filename, "src/test/non-existent.log");
+        assert!(skipped[0].error.is_some());
+    }
 }
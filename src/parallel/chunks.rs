@@ -0,0 +1,121 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::ops::Range;
+
+/// Split a file into up to `count` byte ranges of similar size.
+///
+/// Every range starts at the beginning of a line and ends after a line
+/// break (or at the end of the file), so the ranges can be parsed
+/// independently.
+pub fn split(filename: &str, count: usize) -> io::Result<Vec<Range<u64>>> {
+    let mut file = File::open(filename)?;
+    let size = file.metadata()?.len();
+
+    let mut boundaries = vec![0];
+
+    for index in 1..count as u64 {
+        let previous = *boundaries.last().unwrap();
+        let boundary = next_line_start(&mut file, (size * index / count as u64).max(previous))?;
+
+        if boundary >= size {
+            break;
+        }
+
+        if boundary > previous {
+            boundaries.push(boundary);
+        }
+    }
+
+    boundaries.push(size);
+
+    Ok(
+        boundaries
+            .windows(2)
+            .map(|boundary| boundary[0]..boundary[1])
+            .collect(),
+    )
+}
+
+/// Find the first line that starts at or after `position`.
+fn next_line_start(file: &mut File, position: u64) -> io::Result<u64> {
+    if position == 0 {
+        return Ok(0);
+    }
+
+    // Start one byte early, in case `position` is already a line start
+    file.seek(SeekFrom::Start(position - 1))?;
+
+    let mut skipped = vec![];
+    let skipped_size = io::BufReader::new(file).read_until(b'\n', &mut skipped)?;
+
+    Ok(position - 1 + skipped_size as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::multi_file;
+    use super::*;
+
+    fn read_chunks(filename: &str, count: usize) -> Vec<String> {
+        split(filename, count)
+            .unwrap()
+            .iter()
+            .map(|range| {
+                let mut text = String::new();
+                multi_file::open_range(filename, range).unwrap().read_to_string(&mut text).unwrap();
+                text
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_split_on_line_boundaries() {
+        let chunks = read_chunks("src/test/simple-1.log", 3);
+
+        assert_eq!(chunks.len(), 3);
+
+        for chunk in &chunks {
+            assert!(chunk.ends_with('\n'));
+            assert!(chunk.starts_with("08/Apr/2016"));
+        }
+
+        assert_eq!(
+            chunks.concat(),
+            fs::read_to_string("src/test/simple-1.log").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_split_more_chunks_than_lines() {
+        let chunks = read_chunks("src/test/simple-1.log", 20);
+
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(
+            chunks.concat(),
+            fs::read_to_string("src/test/simple-1.log").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_split_single_chunk() {
+        let ranges = split("src/test/simple-1.log", 1).unwrap();
+
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].start, 0);
+    }
+
+    #[test]
+    fn test_split_empty_file() {
+        let ranges = split("src/test/empty.log", 4).unwrap();
+
+        assert_eq!(ranges, vec![0..0]);
+    }
+
+    #[test]
+    fn test_split_non_existent() {
+        assert!(split("src/test/non-existent.log", 4).is_err());
+    }
+}
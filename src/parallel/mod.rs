@@ -0,0 +1,115 @@
+use std::sync::Mutex;
+use std::thread;
+use crate::analyzer::PartialAnalysis;
+
+pub mod chunks;
+
+/// Apply `function` to all inputs on up to `jobs` threads.
+///
+/// The results are returned in the order of the inputs.
+pub fn map_parallel<T, R, F>(inputs: &[T], jobs: usize, function: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let pending = Mutex::new(inputs.iter().enumerate());
+    let function = &function;
+    let pending = &pending;
+
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.max(1).min(inputs.len()))
+            .map(|_| {
+                scope.spawn(move || {
+                    let next_input = || pending.lock().unwrap().next();
+                    let mut results = vec![];
+
+                    while let Some((index, input)) = next_input() {
+                        results.push((index, function(input)));
+                    }
+
+                    results
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("Analysis thread panicked."))
+            .collect()
+    });
+
+    results.sort_by_key(|&(index, _)| index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Analyze several inputs on up to `jobs` threads and merge the results.
+///
+/// Every input is analyzed on its own by `analyze`, so requests and
+/// responses are only matched within the same input.
+pub fn analyze_all<F>(inputs: &[String], jobs: usize, analyze: F) -> PartialAnalysis
+where
+    F: Fn(&str) -> PartialAnalysis + Sync,
+{
+    let mut analysis = PartialAnalysis::new();
+
+    for partial_analysis in map_parallel(inputs, jobs, |input| analyze(input)) {
+        analysis.merge(partial_analysis);
+    }
+
+    analysis
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::analyzer::TimeUnit;
+    use super::*;
+
+    fn analyze_fixture(input: &str) -> PartialAnalysis {
+        let mut analysis = PartialAnalysis::new();
+
+        for _ in 0..input.len() {
+            analysis.add(&(input.len() as i64));
+        }
+
+        analysis
+    }
+
+    #[test]
+    fn test_map_parallel_keeps_order() {
+        let inputs: Vec<usize> = (0..100).collect();
+
+        let result = map_parallel(&inputs, 3, |input| input * 2);
+
+        assert_eq!(result, (0..100).map(|input| input * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_analyze_all() {
+        let inputs = vec![String::from("a"), String::from("bb"), String::from("ccc")];
+
+        let result = analyze_all(&inputs, 2, analyze_fixture).result(TimeUnit::Milliseconds);
+
+        assert_eq!(result.count, 6);
+
+        let timing = result.timing.unwrap();
+        assert_eq!(timing.min, 1.0);
+        assert_eq!(timing.max, 3.0);
+    }
+
+    #[test]
+    fn test_analyze_all_more_jobs_than_inputs() {
+        let inputs = vec![String::from("a")];
+
+        let result = analyze_all(&inputs, 8, analyze_fixture).result(TimeUnit::Milliseconds);
+
+        assert_eq!(result.count, 1);
+    }
+
+    #[test]
+    fn test_analyze_all_no_inputs() {
+        let result = analyze_all(&[], 4, analyze_fixture).result(TimeUnit::Milliseconds);
+
+        assert_eq!(result.count, 0);
+    }
+}
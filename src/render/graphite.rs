@@ -112,18 +112,20 @@ mod tests {
         result::RequestLogAnalyzerResult {
             count: 3,
             timing: Some(analyzer::TimingResult {
-                max: 100,
-                min: 1,
-                avg: 37,
-                median: 10,
-                percentile90: 90,
-                percentile99: 99,
+                max: 100.0,
+                min: 1.0,
+                avg: 37.0,
+                median: 10.0,
+                percentile90: 90.0,
+                percentile99: 99.0,
                 count: 3,
             }),
             error: Some(analyzer::aggregated_error_rates::ErrorRatesResult {
                 client_error_4xx: 0.1,
                 server_error_5xx: 0.2,
             }),
+            distribution: analyzer::distribution::Distribution::default(),
+            files: vec![],
         }
     }
 
@@ -243,6 +245,8 @@ mod tests {
             count: 0,
             timing: None,
             error: None,
+            distribution: analyzer::distribution::Distribution::default(),
+            files: vec![],
         };
 
         {
@@ -0,0 +1,340 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::{self, LabelPair, Metric, MetricFamily, MetricType};
+use crate::analyzer::distribution;
+
+const BUCKET_LABEL: &str = "le";
+
+/// A histogram of response times by the values of its variable labels, to
+/// which many requests with the same response time can be added at once.
+///
+/// A window describes only the requests of one analysis, so its counts can
+/// go down. It is collected as gauges instead, the cumulative counts of the
+/// buckets by their `le` label under the name of the histogram, and the sums
+/// of the response times under a name of their own. Neither uses the
+/// suffixes reserved for the series of histograms.
+#[derive(Clone)]
+pub struct Histogram {
+    name: String,
+    help: String,
+    buckets: Vec<f64>,
+    label_names: Vec<String>,
+    window: Option<Window>,
+    descs: Vec<Desc>,
+    series: Arc<Mutex<BTreeMap<Vec<String>, Series>>>,
+}
+
+/// The name and help of the sums of a window.
+#[derive(Clone)]
+struct Window {
+    sum_name: String,
+    sum_help: String,
+}
+
+/// The number of requests in each bucket, the `+Inf` bucket comes after the
+/// last upper bound, and the sum of their response times.
+struct Series {
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+impl Histogram {
+    /// `buckets` are the increasing upper bounds in seconds.
+    pub fn new(name: &str, help: &str, buckets: &[f64], label_names: &[&str]) -> Histogram {
+        Histogram::with_window(name, help, buckets, label_names, None)
+    }
+
+    /// A window, with the cumulative counts of the buckets as the gauge
+    /// `name` and the sums of the response times as the gauge `sum_name`.
+    pub fn new_window(
+        name: &str,
+        help: &str,
+        sum_name: &str,
+        sum_help: &str,
+        buckets: &[f64],
+        label_names: &[&str],
+    ) -> Histogram {
+        let window = Window {
+            sum_name: String::from(sum_name),
+            sum_help: String::from(sum_help),
+        };
+
+        Histogram::with_window(name, help, buckets, label_names, Some(window))
+    }
+
+    fn with_window(
+        name: &str,
+        help: &str,
+        buckets: &[f64],
+        label_names: &[&str],
+        window: Option<Window>,
+    ) -> Histogram {
+        let label_names: Vec<String> = label_names.iter().map(|name| String::from(*name)).collect();
+
+        let descs = match window {
+            Some(ref window) => {
+                let mut bucket_label_names = label_names.clone();
+                bucket_label_names.push(String::from(BUCKET_LABEL));
+
+                vec![
+                    desc(name, help, bucket_label_names),
+                    desc(&window.sum_name, &window.sum_help, label_names.clone()),
+                ]
+            }
+            None => vec![desc(name, help, label_names.clone())],
+        };
+
+        let histogram = Histogram {
+            name: String::from(name),
+            help: String::from(help),
+            buckets: buckets.to_vec(),
+            label_names,
+            window,
+            descs,
+            series: Arc::new(Mutex::new(BTreeMap::new())),
+        };
+
+        // Without labels, the histogram is there before the first request
+        if histogram.label_names.is_empty() {
+            histogram.add(&[], 0.0, 0);
+        }
+
+        histogram
+    }
+
+    /// Add `count` requests with the response time `seconds`.
+    pub fn add(&self, label_values: &[String], seconds: f64, count: u64) {
+        let bucket = self.bucket(seconds);
+        let mut series = self.series.lock().unwrap();
+        let series = self.series_entry(&mut series, label_values);
+
+        series.counts[bucket] += count;
+        series.sum += seconds * count as f64;
+    }
+
+    /// Add the number of requests in each bucket, counted with the same
+    /// upper bounds, and the sum of their response times.
+    pub fn add_counts(&self, label_values: &[String], counts: &[usize], seconds: f64) {
+        let mut series = self.series.lock().unwrap();
+        let series = self.series_entry(&mut series, label_values);
+
+        for (count, added) in series.counts.iter_mut().zip(counts) {
+            *count += *added as u64;
+        }
+        series.sum += seconds;
+    }
+
+    /// The index of the bucket of `seconds`, the `+Inf` bucket after the
+    /// last upper bound.
+    pub fn bucket(&self, seconds: f64) -> usize {
+        distribution::bucket(&self.buckets, seconds)
+    }
+
+    fn series_entry<'a>(
+        &self,
+        series: &'a mut BTreeMap<Vec<String>, Series>,
+        label_values: &[String],
+    ) -> &'a mut Series {
+        series.entry(label_values.to_vec()).or_insert_with(|| {
+            Series {
+                counts: vec![0; self.buckets.len() + 1],
+                sum: 0.0,
+            }
+        })
+    }
+
+    fn collect_histogram(&self, series: &BTreeMap<Vec<String>, Series>) -> MetricFamily {
+        let metrics = series
+            .iter()
+            .map(|(label_values, series)| {
+                let mut histogram = proto::Histogram::default();
+                let mut cumulative_count = 0;
+
+                for (upper_bound, count) in self.buckets.iter().zip(&series.counts) {
+                    cumulative_count += count;
+
+                    let mut bucket = proto::Bucket::default();
+                    bucket.set_upper_bound(*upper_bound);
+                    bucket.set_cumulative_count(cumulative_count);
+                    histogram.mut_bucket().push(bucket);
+                }
+
+                histogram.set_sample_count(series.counts.iter().sum());
+                histogram.set_sample_sum(series.sum);
+
+                let mut metric = self.metric(label_values, None);
+                metric.set_histogram(histogram);
+                metric
+            })
+            .collect();
+
+        family(&self.name, &self.help, MetricType::HISTOGRAM, metrics)
+    }
+
+    fn collect_gauges(
+        &self,
+        window: &Window,
+        series: &BTreeMap<Vec<String>, Series>,
+    ) -> Vec<MetricFamily> {
+        let mut buckets = Vec::new();
+        let mut sums = Vec::new();
+
+        for (label_values, series) in series {
+            let upper_bounds = self.buckets.iter().chain(Some(&f64::INFINITY));
+            let mut cumulative_count = 0;
+
+            for (upper_bound, count) in upper_bounds.zip(&series.counts) {
+                cumulative_count += count;
+                buckets.push(gauge(
+                    self.metric(label_values, Some(*upper_bound)),
+                    cumulative_count as f64,
+                ));
+            }
+
+            sums.push(gauge(self.metric(label_values, None), series.sum));
+        }
+
+        vec![
+            family(&self.name, &self.help, MetricType::GAUGE, buckets),
+            family(&window.sum_name, &window.sum_help, MetricType::GAUGE, sums),
+        ]
+    }
+
+    /// A metric with the variable labels sorted by name, like the metrics of
+    /// `prometheus::HistogramVec` are.
+    fn metric(&self, label_values: &[String], upper_bound: Option<f64>) -> Metric {
+        let mut labels: Vec<LabelPair> = self.label_names
+            .iter()
+            .zip(label_values)
+            .map(|(name, value)| label(name, value))
+            .collect();
+
+        if let Some(upper_bound) = upper_bound {
+            labels.push(label(BUCKET_LABEL, &float(upper_bound)));
+        }
+
+        labels.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+
+        let mut metric = Metric::default();
+        for label in labels {
+            metric.mut_label().push(label);
+        }
+        metric
+    }
+}
+
+impl Collector for Histogram {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let series = self.series.lock().unwrap();
+
+        match self.window {
+            Some(ref window) => self.collect_gauges(window, &series),
+            None => vec![self.collect_histogram(&series)],
+        }
+    }
+}
+
+fn desc(name: &str, help: &str, label_names: Vec<String>) -> Desc {
+    Desc::new(String::from(name), String::from(help), label_names, HashMap::new())
+        .expect("Failed to create Prometheus histogram.")
+}
+
+fn family(name: &str, help: &str, field_type: MetricType, metrics: Vec<Metric>) -> MetricFamily {
+    let mut family = MetricFamily::default();
+    family.set_name(String::from(name));
+    family.set_help(String::from(help));
+    family.set_field_type(field_type);
+
+    for metric in metrics {
+        family.mut_metric().push(metric);
+    }
+    family
+}
+
+fn gauge(mut metric: Metric, value: f64) -> Metric {
+    let mut gauge = proto::Gauge::default();
+    gauge.set_value(value);
+    metric.set_gauge(gauge);
+    metric
+}
+
+fn label(name: &str, value: &str) -> LabelPair {
+    let mut label = LabelPair::default();
+    label.set_name(String::from(name));
+    label.set_value(String::from(value));
+    label
+}
+
+/// `le` is formatted like the text format formats the upper bounds of a
+/// histogram, so the buckets are the same time series either way.
+fn float(upper_bound: f64) -> String {
+    if upper_bound == f64::INFINITY {
+        String::from("+Inf")
+    } else {
+        upper_bound.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str;
+    use prometheus::{Encoder, Registry, TextEncoder};
+    use super::*;
+
+    fn encode_to_string(histogram: &Histogram) -> String {
+        let registry = Registry::new();
+        registry.register(Box::new(histogram.clone())).unwrap();
+
+        let mut out = Vec::new();
+        TextEncoder::new().encode(&registry.gather(), &mut out).unwrap();
+        String::from(str::from_utf8(&out).unwrap())
+    }
+
+    #[test]
+    fn test_histogram() {
+        let histogram = Histogram::new("duration_seconds", "Durations.", &[0.1, 1.0], &["m"]);
+        histogram.add(&[String::from("GET")], 0.05, 3);
+        histogram.add(&[String::from("GET")], 1.0, 1);
+        histogram.add(&[String::from("GET")], 2.0, 1);
+
+        assert_eq!(
+            encode_to_string(&histogram),
+            "# HELP duration_seconds Durations.\n\
+             # TYPE duration_seconds histogram\n\
+             duration_seconds_bucket{m=\"GET\",le=\"0.1\"} 3\n\
+             duration_seconds_bucket{m=\"GET\",le=\"1\"} 4\n\
+             duration_seconds_bucket{m=\"GET\",le=\"+Inf\"} 5\n\
+             duration_seconds_sum{m=\"GET\"} 3.15\n\
+             duration_seconds_count{m=\"GET\"} 5\n"
+        );
+    }
+
+    #[test]
+    fn test_histogram_window() {
+        let histogram = Histogram::new_window(
+            "window_requests_by_duration",
+            "Requests.",
+            "window_request_duration_seconds",
+            "Durations.",
+            &[0.1],
+            &["m"],
+        );
+        histogram.add(&[String::from("GET")], 0.05, 2);
+        histogram.add(&[String::from("GET")], 0.5, 1);
+
+        let text = encode_to_string(&histogram);
+
+        assert!(text.contains("# TYPE window_requests_by_duration gauge\n"));
+        assert!(text.contains("window_requests_by_duration{le=\"0.1\",m=\"GET\"} 2\n"));
+        assert!(text.contains("window_requests_by_duration{le=\"+Inf\",m=\"GET\"} 3\n"));
+        assert!(text.contains("# TYPE window_request_duration_seconds gauge\n"));
+        assert!(text.contains("window_request_duration_seconds{m=\"GET\"} 0.6\n"));
+        assert!(!text.contains("_bucket"));
+        assert!(!text.contains("_count"));
+    }
+}
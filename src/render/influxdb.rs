@@ -1,33 +1,46 @@
+use std::time::Duration;
 use log::warn;
+use crate::http_client::HttpClient;
 use crate::render::Renderer;
 use crate::result;
-use hyper;
-use hyper::client;
+use hyper::Method;
 
 pub struct InfluxDbRenderer {
     write_url: String,
     tags: Option<String>,
+    client: HttpClient,
 }
 
 impl InfluxDbRenderer {
-    pub fn new(write_url: &str, tags: Option<String>) -> InfluxDbRenderer {
+    pub fn new(write_url: &str, tags: Option<String>, timeout: Duration) -> InfluxDbRenderer {
         InfluxDbRenderer {
             write_url: String::from(write_url),
             tags,
+            client: HttpClient::new(timeout),
         }
     }
 }
 
 impl Renderer for InfluxDbRenderer {
+    /// A failed write is only reported, so `--follow` writes again after the
+    /// next interval.
     fn render(&mut self, result: result::RequestLogAnalyzerResult) -> () {
-        let client = client::Client::new();
         let data = self.post_body(result);
-        let response = client.post(&self.write_url).body(&data).send().expect(
-            "Could not connect to InfluxDB host.",
-        );
+        let status = match self.client.send(
+            Method::POST,
+            &self.write_url,
+            "text/plain; charset=utf-8",
+            data.into_bytes(),
+        ) {
+            Ok(status) => status,
+            Err(err) => {
+                eprintln!("Could not connect to InfluxDB host: {}", err);
+                return;
+            }
+        };
 
-        if response.status.class() != hyper::status::StatusClass::Success {
-            panic!("POSTing data to InfluxDB failed: {:?}", response.status);
+        if !status.is_success() {
+            eprintln!("POSTing data to InfluxDB failed: {:?}", status);
         }
     }
 }
@@ -83,36 +96,56 @@ impl InfluxDbRenderer {
 
 #[cfg(test)]
 mod tests {
+    use std::net::TcpListener;
     use crate::analyzer;
     use super::*;
 
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
     fn get_result_fixture() -> result::RequestLogAnalyzerResult {
         result::RequestLogAnalyzerResult {
             count: 3,
             timing: Some(analyzer::TimingResult {
-                max: 100,
-                min: 1,
-                avg: 37,
-                median: 10,
-                percentile90: 90,
-                percentile99: 99,
+                max: 100.0,
+                min: 1.0,
+                avg: 37.0,
+                median: 10.0,
+                percentile90: 90.0,
+                percentile99: 99.0,
                 count: 3,
             }),
             error: Some(analyzer::aggregated_error_rates::ErrorRatesResult {
                 client_error_4xx: 0.1,
                 server_error_5xx: 0.2,
             }),
+            distribution: analyzer::distribution::Distribution::default(),
+            files: vec![],
+        }
+    }
+
+    #[test]
+    fn test_render_unreachable() {
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/write?db=testdb", listener.local_addr().unwrap())
+        };
+
+        let mut renderer = InfluxDbRenderer::new(&url, None, TIMEOUT);
+
+        // Like --follow, which renders again after each interval
+        for _ in 0..2 {
+            renderer.render(get_result_fixture());
         }
     }
 
     #[test]
     fn test_instantiate() {
-        InfluxDbRenderer::new("http://example.com/write?db=testdb", None);
+        InfluxDbRenderer::new("http://example.com/write?db=testdb", None, TIMEOUT);
     }
 
     #[test]
     fn test_post_body() {
-        let renderer = InfluxDbRenderer::new("http://example.com/write?db=testdb", None);
+        let renderer = InfluxDbRenderer::new("http://example.com/write?db=testdb", None, TIMEOUT);
         let result = renderer.post_body(get_result_fixture());
 
         assert!(result.starts_with("request_log "));
@@ -132,7 +165,8 @@ mod tests {
     #[test]
     fn test_post_body_with_tag() {
         let tags = String::from("host=testhost");
-        let renderer = InfluxDbRenderer::new("http://example.com/write?db=testdb", Some(tags));
+        let renderer =
+            InfluxDbRenderer::new("http://example.com/write?db=testdb", Some(tags), TIMEOUT);
         let result = renderer.post_body(get_result_fixture());
 
         assert!(result.starts_with("request_log,host=testhost "));
@@ -143,12 +177,14 @@ mod tests {
 
     #[test]
     fn test_post_body_empty() {
-        let renderer = InfluxDbRenderer::new("http://example.com/write?db=testdb", None);
+        let renderer = InfluxDbRenderer::new("http://example.com/write?db=testdb", None, TIMEOUT);
 
         let result = renderer.post_body(result::RequestLogAnalyzerResult {
             count: 0,
             timing: None,
             error: None,
+            distribution: analyzer::distribution::Distribution::default(),
+            files: vec![],
         });
 
         assert!(result.starts_with("request_log "));
@@ -0,0 +1,218 @@
+use crate::analyzer;
+use crate::render::Renderer;
+use crate::result;
+
+/// Renders the summary of a result as a JSON object into `buffer`, with the
+/// same values as the terminal output.
+pub struct JsonRenderer {
+    pub buffer: Vec<u8>,
+    time_unit: analyzer::TimeUnit,
+}
+
+impl JsonRenderer {
+    pub fn new(time_unit: analyzer::TimeUnit) -> JsonRenderer {
+        JsonRenderer {
+            buffer: Vec::new(),
+            time_unit,
+        }
+    }
+}
+
+impl JsonRenderer {
+    /// An object with the summary of each `--prometheus-source` by its name.
+    pub fn render_sources(&mut self, results: Vec<(String, result::RequestLogAnalyzerResult)>) {
+        let summaries: Vec<String> = results
+            .into_iter()
+            .map(|(name, result)| format!("{}:{}", string(&name), self.summary(result)))
+            .collect();
+
+        self.buffer = format!("{{{}}}\n", summaries.join(",")).into_bytes();
+    }
+
+    fn summary(&self, result: result::RequestLogAnalyzerResult) -> String {
+        let timing = match result.timing {
+            Some(timing) => {
+                format!(
+                    "{{\"avg\":{},\"min\":{},\"median\":{},\"percentile90\":{},\
+                     \"percentile99\":{},\"max\":{}}}",
+                    number(timing.avg),
+                    number(timing.min),
+                    number(timing.median),
+                    number(timing.percentile90),
+                    number(timing.percentile99),
+                    number(timing.max)
+                )
+            }
+            None => String::from("null"),
+        };
+
+        let error = match result.error {
+            Some(error) => {
+                format!(
+                    "{{\"client_error_4xx_rate\":{},\"server_error_5xx_rate\":{}}}",
+                    number(f64::from(error.client_error_4xx)),
+                    number(f64::from(error.server_error_5xx))
+                )
+            }
+            None => String::from("null"),
+        };
+
+        let files: Vec<String> = result
+            .files
+            .iter()
+            .map(|file| {
+                format!(
+                    "{{\"filename\":{},\"lines\":{},\"error\":{}}}",
+                    string(&file.filename),
+                    file.lines,
+                    file.error.as_ref().map_or(
+                        String::from("null"),
+                        |error| string(error),
+                    )
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"count\":{},\"time_unit\":{},\"time\":{},\"error\":{},\"input\":[{}]}}",
+            result.count,
+            string(self.time_unit.name()),
+            timing,
+            error,
+            files.join(",")
+        )
+    }
+}
+
+impl Renderer for JsonRenderer {
+    fn render(&mut self, result: result::RequestLogAnalyzerResult) {
+        self.buffer = format!("{}\n", self.summary(result)).into_bytes();
+    }
+}
+
+/// JSON has no infinite numbers.
+fn number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        String::from("null")
+    }
+}
+
+fn string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str;
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut renderer = JsonRenderer::new(analyzer::TimeUnit::Milliseconds);
+        renderer.render(result::RequestLogAnalyzerResult {
+            count: 3,
+            timing: Some(analyzer::TimingResult {
+                max: 100.0,
+                min: 1.0,
+                avg: 37.5,
+                median: 10.0,
+                percentile90: 90.0,
+                percentile99: 99.0,
+                count: 3,
+            }),
+            error: Some(analyzer::aggregated_error_rates::ErrorRatesResult {
+                client_error_4xx: 0.25,
+                server_error_5xx: 0.0,
+            }),
+            distribution: analyzer::distribution::Distribution::default(),
+            files: vec![
+                result::FileSummary {
+                    filename: String::from("request.log"),
+                    lines: 6,
+                    error: None,
+                },
+                result::FileSummary {
+                    filename: String::from("C:\\logs\\request.log"),
+                    lines: 0,
+                    error: Some(String::from("Can not read \"C:\\logs\\request.log\"")),
+                },
+            ],
+        });
+
+        assert_eq!(
+            str::from_utf8(&renderer.buffer).unwrap(),
+            "{\"count\":3,\"time_unit\":\"ms\",\
+             \"time\":{\"avg\":37.5,\"min\":1,\"median\":10,\"percentile90\":90,\
+             \"percentile99\":99,\"max\":100},\
+             \"error\":{\"client_error_4xx_rate\":0.25,\"server_error_5xx_rate\":0},\
+             \"input\":[{\"filename\":\"request.log\",\"lines\":6,\"error\":null},\
+             {\"filename\":\"C:\\\\logs\\\\request.log\",\"lines\":0,\
+             \"error\":\"Can not read \\\"C:\\\\logs\\\\request.log\\\"\"}]}\n"
+        );
+    }
+
+    #[test]
+    fn test_render_empty() {
+        let mut renderer = JsonRenderer::new(analyzer::TimeUnit::Seconds);
+        renderer.render(result::RequestLogAnalyzerResult {
+            count: 0,
+            timing: None,
+            error: None,
+            distribution: analyzer::distribution::Distribution::default(),
+            files: vec![],
+        });
+
+        assert_eq!(
+            str::from_utf8(&renderer.buffer).unwrap(),
+            "{\"count\":0,\"time_unit\":\"s\",\"time\":null,\"error\":null,\"input\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn test_render_sources() {
+        let result = |count| {
+            result::RequestLogAnalyzerResult {
+                count,
+                timing: None,
+                error: None,
+                distribution: analyzer::distribution::Distribution::default(),
+                files: vec![],
+            }
+        };
+
+        let mut renderer = JsonRenderer::new(analyzer::TimeUnit::Milliseconds);
+        renderer.render_sources(vec![
+            (String::from("author"), result(0)),
+            (String::from("publish"), result(2)),
+        ]);
+
+        assert_eq!(
+            str::from_utf8(&renderer.buffer).unwrap(),
+            "{\"author\":{\"count\":0,\"time_unit\":\"ms\",\"time\":null,\"error\":null,\
+             \"input\":[]},\"publish\":{\"count\":2,\"time_unit\":\"ms\",\"time\":null,\
+             \"error\":null,\"input\":[]}}\n"
+        );
+    }
+
+    #[test]
+    fn test_string() {
+        assert_eq!(string("a\tb\u{1}\u{e9}"), "\"a\\tb\\u0001\u{e9}\"");
+    }
+}
@@ -1,7 +1,11 @@
 pub mod terminal;
 pub mod graphite;
 pub mod prometheus;
+pub mod histogram;
+pub mod openmetrics;
+pub mod pushgateway;
 pub mod influxdb;
+pub mod json;
 
 use crate::result;
 
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use prometheus::proto::{LabelPair, MetricFamily, MetricType};
+use crate::analyzer::distribution::Exemplar;
+
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Exemplars can have labels of at most 128 characters.
+const MAX_EXEMPLAR_LABELS_LENGTH: usize = 128;
+
+/// The latest request in each bucket of a histogram, by the values of its
+/// variable labels and the index of the bucket, with its response time in
+/// seconds. The `+Inf` bucket comes after the last upper bound.
+pub type BucketExemplars = HashMap<(Vec<String>, usize), (f64, Exemplar)>;
+
+/// Write the metric families in the OpenMetrics text format, with the
+/// exemplars on the buckets of histograms. `label_names` are the variable
+/// labels the exemplars are found by.
+pub fn encode(
+    families: &[MetricFamily],
+    label_names: &[String],
+    exemplars: &BucketExemplars,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    for family in families {
+        let name = family.get_name();
+
+        let (family_name, type_name) = match family.get_field_type() {
+            MetricType::COUNTER => (name.strip_suffix("_total").unwrap_or(name), "counter"),
+            MetricType::GAUGE => (name, "gauge"),
+            MetricType::HISTOGRAM => (name, "histogram"),
+            _ => (name, "unknown"),
+        };
+
+        writeln!(out, "# TYPE {} {}", family_name, type_name)?;
+        writeln!(out, "# HELP {} {}", family_name, escape(family.get_help()))?;
+
+        for metric in family.get_metric() {
+            let labels = metric.get_label();
+
+            match family.get_field_type() {
+                MetricType::COUNTER => {
+                    sample(out, name, labels, None, metric.get_counter().get_value())?;
+                    writeln!(out)?;
+                }
+                MetricType::GAUGE => {
+                    sample(out, name, labels, None, metric.get_gauge().get_value())?;
+                    writeln!(out)?;
+                }
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    let bucket_name = format!("{}_bucket", name);
+                    let values = variable_label_values(labels, label_names);
+
+                    let mut buckets: Vec<(f64, u64)> = histogram
+                        .get_bucket()
+                        .iter()
+                        .map(|bucket| (bucket.get_upper_bound(), bucket.get_cumulative_count()))
+                        .collect();
+
+                    if !buckets.last().is_some_and(|&(bound, _)| bound.is_infinite()) {
+                        buckets.push((f64::INFINITY, histogram.get_sample_count()));
+                    }
+
+                    for (index, (bound, count)) in buckets.into_iter().enumerate() {
+                        sample(out, &bucket_name, labels, Some(bound), count as f64)?;
+
+                        if let Some((seconds, exemplar)) =
+                            exemplars.get(&(values.clone(), index))
+                        {
+                            write_exemplar(out, *seconds, exemplar)?;
+                        }
+
+                        writeln!(out)?;
+                    }
+
+                    let sum_name = format!("{}_sum", name);
+                    sample(out, &sum_name, labels, None, histogram.get_sample_sum())?;
+                    writeln!(out)?;
+
+                    let count_name = format!("{}_count", name);
+                    let count = histogram.get_sample_count() as f64;
+                    sample(out, &count_name, labels, None, count)?;
+                    writeln!(out)?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    writeln!(out, "# EOF")
+}
+
+/// A sample without the line break, which may be followed by an exemplar.
+fn sample(
+    out: &mut dyn Write,
+    name: &str,
+    labels: &[LabelPair],
+    le: Option<f64>,
+    value: f64,
+) -> io::Result<()> {
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|label| format!("{}=\"{}\"", label.get_name(), escape(label.get_value())))
+        .collect();
+
+    // `le` is formatted like the text format does, so the buckets are the
+    // same time series in both formats
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", float(le)));
+    }
+
+    if pairs.is_empty() {
+        write!(out, "{} {}", name, float(value))
+    } else {
+        write!(out, "{}{{{}}} {}", name, pairs.join(","), float(value))
+    }
+}
+
+fn write_exemplar(out: &mut dyn Write, seconds: f64, exemplar: &Exemplar) -> io::Result<()> {
+    let request_id = exemplar.request_id.to_string();
+    let mut labels = format!("request_id=\"{}\"", request_id);
+
+    if let Some(ref path) = exemplar.path {
+        let length = MAX_EXEMPLAR_LABELS_LENGTH - "request_id".len() - request_id.len() -
+            "path".len();
+        let path: String = path.chars().take(length).collect();
+
+        labels.push_str(&format!(",path=\"{}\"", escape(&path)));
+    }
+
+    write!(
+        out,
+        " # {{{}}} {} {}.{:03}",
+        labels,
+        float(seconds),
+        exemplar.time.timestamp(),
+        exemplar.time.timestamp_subsec_millis()
+    )
+}
+
+/// The values of `label_names` in `labels`, in the order of `label_names`
+fn variable_label_values(labels: &[LabelPair], label_names: &[String]) -> Vec<String> {
+    label_names
+        .iter()
+        .filter_map(|name| {
+            labels.iter().find(|label| label.get_name() == name).map(
+                |label| String::from(label.get_value()),
+            )
+        })
+        .collect()
+}
+
+fn float(value: f64) -> String {
+    if value == f64::INFINITY {
+        String::from("+Inf")
+    } else if value == f64::NEG_INFINITY {
+        String::from("-Inf")
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str;
+    use chrono::{FixedOffset, TimeZone};
+    use ::prometheus::{HistogramOpts, HistogramVec, IntCounter, Registry};
+    use super::*;
+
+    fn exemplar(request_id: i32, path: Option<&str>) -> Exemplar {
+        Exemplar {
+            request_id,
+            path: path.map(String::from),
+            time: FixedOffset::east_opt(7200)
+                .unwrap()
+                .with_ymd_and_hms(2016, 4, 8, 9, 57, 47)
+                .unwrap(),
+        }
+    }
+
+    fn encode_to_string(registry: &Registry, exemplars: &BucketExemplars) -> String {
+        let mut out = Vec::new();
+        encode(&registry.gather(), &[String::from("method")], exemplars, &mut out).unwrap();
+        String::from(str::from_utf8(&out).unwrap())
+    }
+
+    #[test]
+    fn test_encode() {
+        let registry = Registry::new();
+        let histogram = HistogramVec::new(
+            HistogramOpts::new("request_duration_seconds", "The response times of requests.")
+                .buckets(vec![0.01, 1.0]),
+            &["method"],
+        ).unwrap();
+        let counter = IntCounter::new("requests_total", "The number of requests.").unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+
+        histogram.with_label_values(&["GET"]).observe(0.007);
+        histogram.with_label_values(&["GET"]).observe(2.5);
+        counter.inc_by(2);
+
+        let mut exemplars = BucketExemplars::new();
+        exemplars.insert(
+            (vec![String::from("GET")], 0),
+            (0.007, exemplar(1, Some("/content/a \"b\".html"))),
+        );
+        exemplars.insert((vec![String::from("GET")], 2), (2.5, exemplar(2, None)));
+
+        assert_eq!(
+            encode_to_string(&registry, &exemplars),
+            "# TYPE request_duration_seconds histogram\n\
+             # HELP request_duration_seconds The response times of requests.\n\
+             request_duration_seconds_bucket{method=\"GET\",le=\"0.01\"} 1 \
+             # {request_id=\"1\",path=\"/content/a \\\"b\\\".html\"} 0.007 1460102267.000\n\
+             request_duration_seconds_bucket{method=\"GET\",le=\"1\"} 1\n\
+             request_duration_seconds_bucket{method=\"GET\",le=\"+Inf\"} 2 \
+             # {request_id=\"2\"} 2.5 1460102267.000\n\
+             request_duration_seconds_sum{method=\"GET\"} 2.507\n\
+             request_duration_seconds_count{method=\"GET\"} 2\n\
+             # TYPE requests counter\n\
+             # HELP requests The number of requests.\n\
+             requests_total 2\n\
+             # EOF\n"
+        );
+    }
+
+    #[test]
+    fn test_exemplar_labels_length() {
+        let registry = Registry::new();
+        let histogram = HistogramVec::new(
+            HistogramOpts::new("request_duration_seconds", "The response times of requests.")
+                .buckets(vec![1.0]),
+            &["method"],
+        ).unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+        histogram.with_label_values(&["GET"]).observe(0.5);
+
+        let path = format!("/content/{}.html", "a".repeat(200));
+        let mut exemplars = BucketExemplars::new();
+        exemplars.insert((vec![String::from("GET")], 0), (0.5, exemplar(42, Some(&path))));
+
+        let text = encode_to_string(&registry, &exemplars);
+        let exemplar_path = text.split("path=\"").nth(1).unwrap().split('"').next().unwrap();
+
+        assert_eq!("request_id".len() + 2 + "path".len() + exemplar_path.len(), 128);
+        assert!(path.starts_with(exemplar_path));
+    }
+}
@@ -1,12 +1,47 @@
 use log::warn;
-use ::prometheus::{Registry, Gauge, Encoder, TextEncoder};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use crate::analyzer::Timing;
+use crate::analyzer::distribution::{Category, Exemplar};
+use crate::render::histogram::Histogram;
+use crate::render::openmetrics;
+use ::prometheus::{Registry, Gauge, IntCounterVec, IntGaugeVec, Opts, Encoder, TextEncoder};
 
 use super::*;
 
+/// The exposition formats `encode` can write.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Format {
+    /// The Prometheus text format 0.0.4
+    Text,
+    /// The OpenMetrics text format, with exemplars
+    OpenMetrics,
+}
+
 pub struct PrometheusRenderer {
     pub buffer: Vec<u8>,
+    /// The format of `buffer`, the text format unless set otherwise
+    pub format: Format,
     registry: prometheus::Registry,
-    pub encoder: prometheus::TextEncoder,
+    encoder: prometheus::TextEncoder,
+    duration: Histogram,
+    label_names: Vec<String>,
+    exemplars: openmetrics::BucketExemplars,
+    requests: Requests,
+    legacy_gauges: Option<LegacyGauges>,
+    window: bool,
+}
+
+/// The number of requests by status, a counter unless the renderer is a
+/// window.
+enum Requests {
+    Counter(prometheus::IntCounterVec),
+    Gauge(prometheus::IntGaugeVec),
+}
+
+/// The gauges of earlier versions. Unlike the histogram, they can not be
+/// aggregated across instances.
+struct LegacyGauges {
     count: prometheus::Gauge,
     max: prometheus::Gauge,
     min: prometheus::Gauge,
@@ -19,7 +54,126 @@ pub struct PrometheusRenderer {
 }
 
 impl PrometheusRenderer {
-    pub fn new() -> PrometheusRenderer {
+    /// `buckets` are the increasing upper bounds of the histogram in seconds.
+    /// `const_labels` are added to all metrics, the histogram and counters
+    /// are labeled with the `label_names` of the result's distribution.
+    pub fn new(
+        buckets: &[f64],
+        legacy_gauges: bool,
+        const_labels: &[(String, String)],
+        label_names: &[&str],
+    ) -> PrometheusRenderer {
+        PrometheusRenderer::with_window(buckets, legacy_gauges, const_labels, label_names, false)
+    }
+
+    /// A renderer of the results of a single analysis, like those of a
+    /// scrape without `--follow`. As they do not add up across scrapes, the
+    /// histogram and the number of requests are the `window_` gauges, and
+    /// there are no exemplars, which Prometheus only takes from histograms
+    /// and counters.
+    pub fn new_window(
+        buckets: &[f64],
+        legacy_gauges: bool,
+        const_labels: &[(String, String)],
+        label_names: &[&str],
+    ) -> PrometheusRenderer {
+        PrometheusRenderer::with_window(buckets, legacy_gauges, const_labels, label_names, true)
+    }
+
+    fn with_window(
+        buckets: &[f64],
+        legacy_gauges: bool,
+        const_labels: &[(String, String)],
+        label_names: &[&str],
+        window: bool,
+    ) -> PrometheusRenderer {
+        let const_labels: HashMap<String, String> = const_labels.iter().cloned().collect();
+        let registry = prometheus::Registry::new_custom(None, Some(const_labels))
+            .expect("Failed to create Prometheus registry.");
+
+        let duration = if window {
+            Histogram::new_window(
+                "window_requests_by_duration",
+                "The number of requests in the window with a response time of at most le seconds.",
+                "window_request_duration_seconds",
+                "The sum of the response times of the requests in the window.",
+                buckets,
+                label_names,
+            )
+        } else {
+            Histogram::new(
+                "request_duration_seconds",
+                "The response times of requests.",
+                buckets,
+                label_names,
+            )
+        };
+
+        let mut status_label_names = vec!["status"];
+        status_label_names.extend(label_names);
+
+        let requests = if window {
+            Requests::Gauge(
+                prometheus::IntGaugeVec::new(
+                    prometheus::Opts::new(
+                        "window_requests",
+                        "The number of requests in the window by response status.",
+                    ),
+                    &status_label_names,
+                ).expect("Failed to create Prometheus gauge."),
+            )
+        } else {
+            Requests::Counter(
+                prometheus::IntCounterVec::new(
+                    prometheus::Opts::new(
+                        "requests_total",
+                        "The number of requests by response status.",
+                    ),
+                    &status_label_names,
+                ).expect("Failed to create Prometheus counter."),
+            )
+        };
+
+        registry.register(Box::new(duration.clone())).expect(
+            "Failed to register Prometheus histogram.",
+        );
+        match requests {
+            Requests::Counter(ref counter) => registry.register(Box::new(counter.clone())),
+            Requests::Gauge(ref gauge) => registry.register(Box::new(gauge.clone())),
+        }.expect("Failed to register Prometheus counter.");
+
+        PrometheusRenderer {
+            buffer: Vec::new(),
+            format: Format::Text,
+            encoder: prometheus::TextEncoder::new(),
+            duration,
+            label_names: label_names.iter().map(|name| String::from(*name)).collect(),
+            exemplars: openmetrics::BucketExemplars::new(),
+            requests,
+            legacy_gauges: if legacy_gauges {
+                Some(LegacyGauges::new(&registry))
+            } else {
+                None
+            },
+            registry,
+            window,
+        }
+    }
+}
+
+impl Requests {
+    fn add(&self, status_labels: &[String], count: u64) {
+        let values = label_values(status_labels);
+
+        match *self {
+            Requests::Counter(ref counter) => counter.with_label_values(&values).inc_by(count),
+            Requests::Gauge(ref gauge) => gauge.with_label_values(&values).add(count as i64),
+        }
+    }
+}
+
+impl LegacyGauges {
+    fn new(registry: &prometheus::Registry) -> LegacyGauges {
         fn make_and_register_gauge(
             gauge_name: &str,
             registry: &prometheus::Registry,
@@ -35,43 +189,36 @@ impl PrometheusRenderer {
             gauge
         }
 
-        let registry = prometheus::Registry::new();
-
-        PrometheusRenderer {
-            buffer: Vec::new(),
-            encoder: prometheus::TextEncoder::new(),
-            count: make_and_register_gauge("request_count", &registry),
-            max: make_and_register_gauge("request_time_max", &registry),
-            min: make_and_register_gauge("request_time_min", &registry),
-            avg: make_and_register_gauge("request_time_avg", &registry),
-            median: make_and_register_gauge("request_time_median", &registry),
-            percentile90: make_and_register_gauge("request_time_percentile90", &registry),
-            percentile99: make_and_register_gauge("request_time_percentile99", &registry),
+        LegacyGauges {
+            count: make_and_register_gauge("request_count", registry),
+            max: make_and_register_gauge("request_time_max", registry),
+            min: make_and_register_gauge("request_time_min", registry),
+            avg: make_and_register_gauge("request_time_avg", registry),
+            median: make_and_register_gauge("request_time_median", registry),
+            percentile90: make_and_register_gauge("request_time_percentile90", registry),
+            percentile99: make_and_register_gauge("request_time_percentile99", registry),
             client_error_4xx_rate: make_and_register_gauge(
                 "request_error_client_error_4xx_rate",
-                &registry,
+                registry,
             ),
             server_error_5xx_rate: make_and_register_gauge(
                 "request_error_server_error_5xx_rate",
-                &registry,
+                registry,
             ),
-            registry,
         }
     }
-}
 
-impl Renderer for PrometheusRenderer {
-    fn render(&mut self, result: result::RequestLogAnalyzerResult) {
+    fn set(&self, result: &result::RequestLogAnalyzerResult) {
         self.count.set(result.count as f64);
 
         match result.timing {
-            Some(timing) => {
-                self.max.set(timing.max as f64);
-                self.min.set(timing.min as f64);
-                self.avg.set(timing.avg as f64);
-                self.median.set(timing.median as f64);
-                self.percentile90.set(timing.percentile90 as f64);
-                self.percentile99.set(timing.percentile99 as f64);
+            Some(ref timing) => {
+                self.max.set(timing.max);
+                self.min.set(timing.min);
+                self.avg.set(timing.avg);
+                self.median.set(timing.median);
+                self.percentile90.set(timing.percentile90);
+                self.percentile99.set(timing.percentile99);
             }
             None => {
                 warn!("No matching log lines in file.");
@@ -79,7 +226,7 @@ impl Renderer for PrometheusRenderer {
         }
 
         match result.error {
-            Some(error) => {
+            Some(ref error) => {
                 self.client_error_4xx_rate.set(
                     f64::from(error.client_error_4xx),
                 );
@@ -91,40 +238,163 @@ impl Renderer for PrometheusRenderer {
                 warn!("No matching log lines in file.");
             }
         }
+    }
+}
+
+impl PrometheusRenderer {
+    /// Count a single request in the histogram and counters.
+    pub fn observe<T>(&mut self, value: &T)
+    where
+        T: Timing + Category,
+    {
+        let labels = value.labels();
+        let seconds = value.num_microseconds() as f64 / 1_000_000.0;
+
+        self.duration.add(&labels, seconds, 1);
+        self.requests.add(&status_labels(value.status(), &labels), 1);
+
+        if let Some(exemplar) = value.exemplar() {
+            self.add_exemplar(labels, seconds, exemplar);
+        }
+    }
+
+    /// Keep the latest request of each bucket as its exemplar, unless the
+    /// buckets are gauges.
+    fn add_exemplar(&mut self, labels: Vec<String>, seconds: f64, exemplar: Exemplar) {
+        if self.window {
+            return;
+        }
+
+        let bucket = self.duration.bucket(seconds);
+
+        match self.exemplars.entry((labels, bucket)) {
+            Entry::Occupied(mut entry) => {
+                if entry.get().1.time <= exemplar.time {
+                    entry.insert((seconds, exemplar));
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((seconds, exemplar));
+            }
+        }
+    }
+
+    pub fn content_type(&self) -> String {
+        match self.format {
+            Format::Text => self.encoder.format_type().to_string(),
+            Format::OpenMetrics => openmetrics::CONTENT_TYPE.to_string(),
+        }
+    }
+
+    /// Set the gauges of earlier versions, if they are enabled.
+    pub fn set_legacy_gauges(&self, result: &result::RequestLogAnalyzerResult) {
+        if let Some(ref legacy_gauges) = self.legacy_gauges {
+            legacy_gauges.set(result);
+        }
+    }
+
+    /// Encode the current values of all metrics into `buffer`, in `format`.
+    pub fn encode(&mut self) {
         let metric_familys = self.registry.gather();
 
-        self.encoder
-            .encode(&metric_familys, &mut self.buffer)
-            .expect("Failed to encode Prometheus metrics.");
+        self.buffer.clear();
+        match self.format {
+            Format::Text => self.encoder.encode(&metric_familys, &mut self.buffer),
+            Format::OpenMetrics => {
+                openmetrics::encode(
+                    &metric_familys,
+                    &self.label_names,
+                    &self.exemplars,
+                    &mut self.buffer,
+                ).map_err(::prometheus::Error::from)
+            }
+        }.expect("Failed to encode Prometheus metrics.");
+    }
+}
+
+impl Renderer for PrometheusRenderer {
+    fn render(&mut self, result: result::RequestLogAnalyzerResult) {
+        for (labels, series) in &result.distribution.series {
+            let seconds = series.sum as f64 / 1_000_000.0;
+            self.duration.add_counts(labels, &series.counts, seconds);
+
+            for (status, count) in &series.statuses {
+                self.requests.add(&status_labels(*status, labels), *count as u64);
+            }
+
+            for (microseconds, exemplar) in series.exemplars.values() {
+                let seconds = *microseconds as f64 / 1_000_000.0;
+                self.add_exemplar(labels.clone(), seconds, exemplar.clone());
+            }
+        }
+
+        self.set_legacy_gauges(&result);
+        self.encode();
     }
 }
 
+fn label_values(labels: &[String]) -> Vec<&str> {
+    labels.iter().map(String::as_str).collect()
+}
+
+/// The values of the labels of `requests_total`, the status comes first.
+fn status_labels(status: Option<u16>, labels: &[String]) -> Vec<String> {
+    let status = match status {
+        Some(status) => status.to_string(),
+        None => String::from("unknown"),
+    };
+
+    let mut values = vec![status];
+    values.extend(labels.iter().cloned());
+    values
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
     use std::str;
     use super::*;
     use crate::analyzer;
+    use crate::analyzer::distribution::{Distribution, Series};
+
+    fn distribution(buckets: &[f64], series: Vec<(Vec<String>, Series)>) -> Distribution {
+        Distribution {
+            buckets: buckets.to_vec(),
+            series: series.into_iter().collect(),
+        }
+    }
+
+    fn series(counts: Vec<usize>, sum: i64, statuses: Vec<(Option<u16>, usize)>) -> Series {
+        Series {
+            counts,
+            sum,
+            statuses: statuses.into_iter().collect(),
+            exemplars: BTreeMap::new(),
+        }
+    }
 
     #[test]
     fn test_render_1() {
         let result = result::RequestLogAnalyzerResult {
             count: 3,
             timing: Some(analyzer::TimingResult {
-                max: 100,
-                min: 1,
-                avg: 37,
-                median: 10,
-                percentile90: 90,
-                percentile99: 99,
+                max: 100.0,
+                min: 1.0,
+                avg: 37.0,
+                median: 10.0,
+                percentile90: 90.0,
+                percentile99: 99.0,
                 count: 3,
             }),
             error: Some(analyzer::aggregated_error_rates::ErrorRatesResult {
                 client_error_4xx: 0.1,
                 server_error_5xx: 0.2,
             }),
+            distribution: analyzer::distribution::Distribution::default(),
+            files: vec![],
         };
 
-        let mut renderer = PrometheusRenderer::new();
+        let mut renderer = PrometheusRenderer::new(::prometheus::DEFAULT_BUCKETS, true, &[], &[]);
         renderer.render(result);
 
         let buffer_text = str::from_utf8(&renderer.buffer).unwrap();
@@ -148,18 +418,20 @@ mod tests {
         let result = result::RequestLogAnalyzerResult {
             count: 300,
             timing: Some(analyzer::TimingResult {
-                max: 1000,
-                min: 10,
-                avg: 42,
-                median: 75,
-                percentile90: 900,
-                percentile99: 990,
+                max: 1000.0,
+                min: 10.0,
+                avg: 42.0,
+                median: 75.0,
+                percentile90: 900.0,
+                percentile99: 990.0,
                 count: 300,
             }),
             error: None,
+            distribution: analyzer::distribution::Distribution::default(),
+            files: vec![],
         };
 
-        let mut renderer = PrometheusRenderer::new();
+        let mut renderer = PrometheusRenderer::new(::prometheus::DEFAULT_BUCKETS, true, &[], &[]);
         renderer.render(result);
 
         let buffer_text = str::from_utf8(&renderer.buffer).unwrap();
@@ -178,9 +450,11 @@ mod tests {
             count: 0,
             timing: None,
             error: None,
+            distribution: analyzer::distribution::Distribution::default(),
+            files: vec![],
         };
 
-        let mut renderer = PrometheusRenderer::new();
+        let mut renderer = PrometheusRenderer::new(::prometheus::DEFAULT_BUCKETS, true, &[], &[]);
         renderer.render(result);
 
         let buffer_text = str::from_utf8(&renderer.buffer).unwrap();
@@ -192,5 +466,129 @@ mod tests {
         assert!(buffer_text.contains("request_time_median 0"));
         assert!(buffer_text.contains("request_time_percentile90 0"));
         assert!(buffer_text.contains("request_time_percentile99 0"));
+        assert!(buffer_text.contains("request_duration_seconds_count 0"));
+    }
+
+    #[test]
+    fn test_render_histogram() {
+        let result = result::RequestLogAnalyzerResult {
+            count: 4,
+            timing: None,
+            error: None,
+            distribution: distribution(
+                &[0.01, 0.5, 10.0],
+                vec![
+                    (
+                        vec![],
+                        series(
+                            vec![2, 1, 0, 1],
+                            12264000,
+                            vec![(None, 1), (Some(200), 2), (Some(503), 1)],
+                        ),
+                    ),
+                ],
+            ),
+            files: vec![],
+        };
+
+        let mut renderer = PrometheusRenderer::new(&[0.01, 0.5, 10.0], false, &[], &[]);
+        renderer.render(result);
+
+        let buffer_text = str::from_utf8(&renderer.buffer).unwrap();
+
+        assert!(buffer_text.contains("# TYPE request_duration_seconds histogram"));
+        assert!(buffer_text.contains("request_duration_seconds_bucket{le=\"0.01\"} 2"));
+        assert!(buffer_text.contains("request_duration_seconds_bucket{le=\"0.5\"} 3"));
+        assert!(buffer_text.contains("request_duration_seconds_bucket{le=\"10\"} 3"));
+        assert!(buffer_text.contains("request_duration_seconds_bucket{le=\"+Inf\"} 4"));
+        assert!(buffer_text.contains("request_duration_seconds_sum 12.264"));
+        assert!(buffer_text.contains("request_duration_seconds_count 4"));
+        assert!(buffer_text.contains("# TYPE requests_total counter"));
+        assert!(buffer_text.contains("requests_total{status=\"200\"} 2"));
+        assert!(buffer_text.contains("requests_total{status=\"503\"} 1"));
+        assert!(buffer_text.contains("requests_total{status=\"unknown\"} 1"));
+        assert!(!buffer_text.contains("request_time_max"));
+    }
+
+    #[test]
+    fn test_render_window() {
+        let result = result::RequestLogAnalyzerResult {
+            count: 3,
+            timing: None,
+            error: None,
+            distribution: {
+                let mut series = series(vec![2, 1, 0], 264000, vec![(Some(200), 3)]);
+                series.exemplars.insert(0, (7000, analyzer::distribution::Exemplar {
+                    request_id: 1,
+                    path: None,
+                    time: chrono::DateTime::parse_from_rfc3339("2016-04-08T09:57:47+02:00")
+                        .unwrap(),
+                }));
+                distribution(&[0.01, 0.5], vec![(vec![], series)])
+            },
+            files: vec![],
+        };
+
+        let mut renderer = PrometheusRenderer::new_window(&[0.01, 0.5], false, &[], &[]);
+        renderer.format = Format::OpenMetrics;
+        renderer.render(result);
+
+        let buffer_text = str::from_utf8(&renderer.buffer).unwrap();
+
+        assert!(buffer_text.contains("# TYPE window_requests_by_duration gauge"));
+        assert!(buffer_text.contains("window_requests_by_duration{le=\"0.01\"} 2\n"));
+        assert!(buffer_text.contains("window_requests_by_duration{le=\"+Inf\"} 3\n"));
+        assert!(buffer_text.contains("window_request_duration_seconds 0.264\n"));
+        assert!(buffer_text.contains("# TYPE window_requests gauge"));
+        assert!(buffer_text.contains("window_requests{status=\"200\"} 3\n"));
+        assert!(!buffer_text.contains("request_duration_seconds_"));
+        assert!(!buffer_text.contains("requests_total"));
+        assert!(!buffer_text.contains("# {request_id"));
+    }
+
+    #[test]
+    fn test_render_labels() {
+        let labels = |method: &str, path_group: &str| {
+            vec![String::from(method), String::from(path_group)]
+        };
+
+        let result = result::RequestLogAnalyzerResult {
+            count: 3,
+            timing: None,
+            error: None,
+            distribution: distribution(
+                &[0.1, 1.0],
+                vec![
+                    (labels("GET", "pages"), series(vec![2, 0, 0], 14000, vec![(Some(200), 2)])),
+                    (labels("POST", "/bin"), series(vec![0, 1, 0], 250000, vec![(Some(500), 1)])),
+                ],
+            ),
+            files: vec![],
+        };
+
+        let const_labels = vec![(String::from("instance"), String::from("publish1"))];
+        let mut renderer = PrometheusRenderer::new(
+            &[0.1, 1.0],
+            true,
+            &const_labels,
+            &["method", "path_group"],
+        );
+        renderer.render(result);
+
+        let buffer_text = str::from_utf8(&renderer.buffer).unwrap();
+
+        assert!(buffer_text.contains(
+            "request_duration_seconds_bucket{method=\"GET\",path_group=\"pages\",\
+             instance=\"publish1\",le=\"0.1\"} 2",
+        ));
+        assert!(buffer_text.contains(
+            "request_duration_seconds_count{method=\"POST\",path_group=\"/bin\",\
+             instance=\"publish1\"} 1",
+        ));
+        assert!(buffer_text.contains(
+            "requests_total{method=\"GET\",path_group=\"pages\",status=\"200\",\
+             instance=\"publish1\"} 2",
+        ));
+        assert!(buffer_text.contains("request_count{instance=\"publish1\"} 3"));
     }
 }
@@ -0,0 +1,237 @@
+use std::time::Duration;
+use crate::http_client::HttpClient;
+use crate::render::Renderer;
+use crate::render::prometheus::PrometheusRenderer;
+use crate::result;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE;
+use hyper::Method;
+
+/// Pushes the metrics of a `PrometheusRenderer` to a Pushgateway, for runs
+/// that end before they could be scraped.
+pub struct PushgatewayRenderer {
+    url: String,
+    renderer: PrometheusRenderer,
+    client: HttpClient,
+}
+
+impl PushgatewayRenderer {
+    /// The metrics replace those pushed earlier with the same `job` and
+    /// `grouping` labels.
+    pub fn new(
+        gateway_url: &str,
+        job: &str,
+        grouping: &[(String, String)],
+        renderer: PrometheusRenderer,
+        timeout: Duration,
+    ) -> PushgatewayRenderer {
+        PushgatewayRenderer {
+            url: push_url(gateway_url, job, grouping),
+            renderer,
+            client: HttpClient::new(timeout),
+        }
+    }
+}
+
+impl Renderer for PushgatewayRenderer {
+    /// A failed push is only reported, so `--follow` pushes again after the
+    /// next interval.
+    fn render(&mut self, result: result::RequestLogAnalyzerResult) {
+        self.renderer.render(result);
+
+        let status = match self.client.send(
+            Method::PUT,
+            &self.url,
+            &self.renderer.content_type(),
+            self.renderer.buffer.clone(),
+        ) {
+            Ok(status) => status,
+            Err(err) => {
+                eprintln!("Could not connect to the Pushgateway: {}", err);
+                return;
+            }
+        };
+
+        if !status.is_success() {
+            eprintln!("Pushing metrics to the Pushgateway failed: {:?}", status);
+        }
+    }
+}
+
+/// Like `http://localhost:9091/metrics/job/nightly/instance/publish1`
+fn push_url(gateway_url: &str, job: &str, grouping: &[(String, String)]) -> String {
+    let mut url = format!(
+        "{}/metrics/{}",
+        gateway_url.trim_end_matches('/'),
+        path_segments("job", job)
+    );
+
+    for (name, value) in grouping {
+        url.push('/');
+        url.push_str(&path_segments(name, value));
+    }
+
+    url
+}
+
+/// Values which can't be a path segment as they are, like `/var/log`, are
+/// base64 encoded the way the Pushgateway expects.
+fn path_segments(name: &str, value: &str) -> String {
+    let is_plain = !value.is_empty() &&
+        value.chars().all(|c| {
+            c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' || c == '~'
+        });
+
+    if is_plain {
+        format!("{}/{}", name, value)
+    } else if value.is_empty() {
+        format!("{}@base64/=", name)
+    } else {
+        format!("{}@base64/{}", name, URL_SAFE.encode(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use crate::analyzer;
+    use super::*;
+
+    fn label(name: &str, value: &str) -> (String, String) {
+        (String::from(name), String::from(value))
+    }
+
+    #[test]
+    fn test_push_url() {
+        assert_eq!(
+            push_url("http://localhost:9091/", "request_log_analyzer", &[]),
+            "http://localhost:9091/metrics/job/request_log_analyzer"
+        );
+        assert_eq!(
+            push_url(
+                "http://localhost:9091",
+                "nightly",
+                &[label("instance", "publish1"), label("log", "/var/log/request.log")],
+            ),
+            "http://localhost:9091/metrics/job/nightly/instance/publish1/\
+             log@base64/L3Zhci9sb2cvcmVxdWVzdC5sb2c="
+        );
+        assert_eq!(
+            push_url("http://localhost:9091", "night ly", &[label("env", "")]),
+            "http://localhost:9091/metrics/job@base64/bmlnaHQgbHk=/env@base64/="
+        );
+    }
+
+    #[test]
+    fn test_path_segments_url_safe() {
+        assert_eq!(path_segments("query", "?>?"), "query@base64/Pz4_");
+    }
+
+    /// A stand-in Pushgateway accepting one request, which is returned by the
+    /// thread.
+    fn pushgateway() -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            let mut content_length = 0;
+
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    content_length = value.trim().parse().unwrap();
+                }
+
+                request.push_str(&line);
+
+                if line == "\r\n" {
+                    break;
+                }
+            }
+
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8(body).unwrap());
+
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .unwrap();
+
+            request
+        });
+
+        (url, handle)
+    }
+
+    #[test]
+    fn test_render() {
+        let (url, handle) = pushgateway();
+
+        let mut distribution = analyzer::distribution::Distribution::with_buckets(&[0.01, 1.0]);
+        distribution.series.insert(vec![], analyzer::distribution::Series {
+            counts: vec![2, 0, 0],
+            sum: 14000,
+            statuses: vec![(Some(200), 2)].into_iter().collect(),
+            exemplars: Default::default(),
+        });
+
+        let mut renderer = PushgatewayRenderer::new(
+            &url,
+            "nightly",
+            &[label("instance", "publish1")],
+            PrometheusRenderer::new(&[0.01, 1.0], false, &[], &[]),
+            Duration::from_secs(5),
+        );
+        renderer.render(result::RequestLogAnalyzerResult {
+            count: 2,
+            timing: None,
+            error: None,
+            distribution,
+            files: vec![],
+        });
+
+        let request = handle.join().unwrap();
+
+        assert!(request.starts_with(
+            "PUT /metrics/job/nightly/instance/publish1 HTTP/1.1\r\n",
+        ));
+        assert!(request.to_lowercase().contains("content-type: text/plain; version=0.0.4"));
+        assert!(request.contains("request_duration_seconds_bucket{le=\"0.01\"} 2"));
+        assert!(request.contains("requests_total{status=\"200\"} 2"));
+    }
+
+    #[test]
+    fn test_render_unreachable() {
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+
+        let mut renderer = PushgatewayRenderer::new(
+            &url,
+            "nightly",
+            &[],
+            PrometheusRenderer::new(&[1.0], false, &[], &[]),
+            Duration::from_secs(5),
+        );
+
+        // Like --follow, which renders again after each interval
+        for _ in 0..2 {
+            renderer.render(result::RequestLogAnalyzerResult {
+                count: 0,
+                timing: None,
+                error: None,
+                distribution: analyzer::distribution::Distribution::default(),
+                files: vec![],
+            });
+        }
+    }
+}
@@ -45,6 +45,14 @@ impl<'a> Renderer for TerminalRenderer<'a> {
             }
             None => warn!("No matching log lines for error rate results."),
         }
+
+        for file in result.files {
+            write(format!("input.{}.lines:\t{}", file.filename, file.lines));
+
+            if let Some(error) = file.error {
+                write(format!("input.{}.error:\t{}", file.filename, error));
+            }
+        }
     }
 }
 
@@ -77,18 +85,20 @@ mod tests {
         result::RequestLogAnalyzerResult {
             count: 3,
             timing: Some(analyzer::TimingResult {
-                max: 100,
-                min: 1,
-                avg: 37,
-                median: 10,
-                percentile90: 90,
-                percentile99: 99,
+                max: 100.0,
+                min: 1.0,
+                avg: 37.0,
+                median: 10.0,
+                percentile90: 90.0,
+                percentile99: 99.0,
                 count: 3,
             }),
             error: Some(analyzer::aggregated_error_rates::ErrorRatesResult {
                 client_error_4xx: 0.1,
                 server_error_5xx: 0.2,
             }),
+            distribution: analyzer::distribution::Distribution::default(),
+            files: vec![],
         }
     }
 
@@ -143,6 +153,8 @@ mod tests {
                 count: 0,
                 timing: None,
                 error: None,
+                distribution: analyzer::distribution::Distribution::default(),
+                files: vec![],
             };
 
             renderer.render(result);
@@ -153,4 +165,40 @@ mod tests {
         ));
         assert_eq!(mock_write.write_calls.len(), 1);
     }
+
+    #[test]
+    fn test_terminal_renderer_files() {
+        let mut mock_write = MockWrite { write_calls: vec![] };
+
+        {
+            let mut renderer = TerminalRenderer::new(&mut mock_write);
+            let mut result = get_result_fixture();
+            result.files = vec![
+                result::FileSummary {
+                    filename: String::from("request.log"),
+                    lines: 1200,
+                    error: None,
+                },
+                result::FileSummary {
+                    filename: String::from("missing.log"),
+                    lines: 0,
+                    error: Some(String::from("Can not read missing.log: not found")),
+                },
+            ];
+            renderer.render(result);
+        }
+
+        assert!(mock_write.write_calls.contains(&String::from(
+            "input.request.log.lines:\t1200\n",
+        )));
+        assert!(mock_write.write_calls.contains(&String::from(
+            "input.missing.log.lines:\t0\n",
+        )));
+        assert!(mock_write.write_calls.contains(&String::from(
+            "input.missing.log.error:\tCan not read missing.log: not found\n",
+        )));
+        assert!(!mock_write.write_calls.contains(&String::from(
+            "input.request.log.error",
+        )));
+    }
 }
@@ -1,5 +1,9 @@
+use chrono::*;
 use crate::log_parser::*;
 
+/// How long after `until` responses to earlier requests are still waited for
+const UNTIL_GRACE_PERIOD_MINUTES: i64 = 10;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct RequestResponsePair {
     pub request: log_events::Request,
@@ -10,6 +14,8 @@ pub struct RequestResponsePairIterator<'a> {
     events: &'a mut dyn Iterator<Item = log_events::LogEvent>,
     requests: Vec<log_events::Request>,
     responses: Vec<log_events::Response>,
+    until: Option<DateTime<FixedOffset>>,
+    max_pending_age: Option<Duration>,
 }
 
 impl<'a> RequestResponsePairIterator<'a> {
@@ -18,8 +24,55 @@ impl<'a> RequestResponsePairIterator<'a> {
             events,
             requests: vec![],
             responses: vec![],
+            until: None,
+            max_pending_age: None,
         }
     }
+
+    /// Forget requests without a response once they are `max_pending_age`
+    /// older than the latest request, or a later request has the same id,
+    /// and responses without a request, for logs that are read for ever.
+    ///
+    /// The request of a response has to be read before it.
+    pub fn prune_pending(mut self, max_pending_age: Duration) -> Self {
+        self.max_pending_age = Some(max_pending_age);
+        self
+    }
+
+    /// Stop reading events once a chronological log has passed `until`.
+    ///
+    /// Requests from `until` on are dropped. Reading stops at the first of
+    /// them once all earlier requests have got their response, or at the
+    /// latest after a grace period for requests that never get one.
+    pub fn until(mut self, until: Option<DateTime<FixedOffset>>) -> Self {
+        self.until = until;
+        self
+    }
+
+    /// Whether `request` is past `until`, and if reading should stop there.
+    fn after_until(&self, request: &log_events::Request) -> (bool, bool) {
+        match self.until {
+            Some(until) if request.time >= until => {
+                let grace_period_over =
+                    request.time >= until + Duration::minutes(UNTIL_GRACE_PERIOD_MINUTES);
+
+                (true, self.requests.is_empty() || grace_period_over)
+            }
+            _ => (false, false),
+        }
+    }
+
+    /// The requests and responses that have not been matched (yet), requests
+    /// first.
+    pub fn into_unmatched_events(self) -> Vec<log_events::LogEvent> {
+        self.requests
+            .into_iter()
+            .map(log_events::LogEvent::Request)
+            .chain(self.responses.into_iter().map(
+                log_events::LogEvent::Response,
+            ))
+            .collect()
+    }
 }
 
 impl<'a> Iterator for RequestResponsePairIterator<'a> {
@@ -32,7 +85,22 @@ impl<'a> Iterator for RequestResponsePairIterator<'a> {
             let event = self.events.next();
 
             match event {
-                Some(log_events::LogEvent::Request(request)) => self.requests.push(request),
+                Some(log_events::LogEvent::Request(request)) => {
+                    match self.after_until(&request) {
+                        (true, true) => return None,
+                        (true, false) => continue,
+                        (false, _) => {
+                            if let Some(max_pending_age) = self.max_pending_age {
+                                let oldest_allowed = request.time - max_pending_age;
+                                self.requests.retain(|pending| {
+                                    pending.id != request.id && pending.time >= oldest_allowed
+                                });
+                            }
+
+                            self.requests.push(request);
+                        }
+                    }
+                }
                 Some(log_events::LogEvent::Response(response)) => self.responses.push(response),
                 None => return None,
             }
@@ -41,6 +109,10 @@ impl<'a> Iterator for RequestResponsePairIterator<'a> {
                 &mut self.requests,
                 &mut self.responses,
             );
+
+            if self.max_pending_age.is_some() {
+                self.responses.clear();
+            }
         }
         pair
     }
@@ -93,12 +165,13 @@ mod tests {
                         "08/Apr/2016:09:57:47 +0200",
                         "%d/%b/%Y:%H:%M:%S %z",
                     ).unwrap(),
-                    original_log_line: "whatever".to_string(),
+                    original_log_line: Some("whatever".to_string()),
                 }),
                 log_parser::log_events::LogEvent::Response(log_parser::log_events::Response {
                     id: 1,
                     response_time: Duration::milliseconds(7),
-                    original_log_line: "whatever".to_string(),
+                    original_log_line: Some("whatever".to_string()),
+                    status: None,
                     http_error: None,
                 }),
             ];
@@ -110,6 +183,153 @@ mod tests {
         assert_eq!(result.request.id, 1);
     }
 
+    #[test]
+    fn test_until() {
+        let time = |time: &str| {
+            DateTime::parse_from_str(
+                &format!("08/Apr/2016:{} +0200", time),
+                "%d/%b/%Y:%H:%M:%S %z",
+            ).unwrap()
+        };
+        let request = |id, request_time: &str| {
+            log_parser::log_events::LogEvent::Request(log_parser::log_events::Request {
+                id,
+                time: time(request_time),
+                original_log_line: None,
+            })
+        };
+        let response = |id| {
+            log_parser::log_events::LogEvent::Response(log_parser::log_events::Response {
+                id,
+                response_time: Duration::milliseconds(7),
+                original_log_line: None,
+                status: None,
+                http_error: None,
+            })
+        };
+
+        let events = vec![
+            request(1, "09:59:00"),
+            request(2, "09:59:59"),
+            response(1),
+            request(3, "10:00:00"),
+            response(2),
+            response(3),
+            request(4, "10:00:01"),
+            response(4),
+            request(5, "09:59:59"),
+            response(5),
+        ];
+
+        let mut events_iter = events.into_iter();
+        let ids: Vec<i32> = RequestResponsePairIterator::new(&mut events_iter)
+            .until(Some(time("10:00:00")))
+            .map(|pair| pair.request.id)
+            .collect();
+
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(events_iter.next(), Some(response(4)));
+
+        let events = vec![
+            request(1, "09:59:00"),
+            request(2, "10:05:00"),
+            response(2),
+            request(3, "10:10:00"),
+            response(1),
+        ];
+
+        let mut events_iter = events.into_iter();
+        let iterator = RequestResponsePairIterator::new(&mut events_iter)
+            .until(Some(time("10:00:00")));
+
+        assert_eq!(iterator.count(), 0);
+        assert_eq!(events_iter.next(), Some(response(1)));
+    }
+
+    #[test]
+    fn test_prune_pending() {
+        let request = |id, request_time: &str| {
+            log_parser::log_events::LogEvent::Request(log_parser::log_events::Request {
+                id,
+                time: DateTime::parse_from_str(
+                    &format!("08/Apr/2016:{} +0200", request_time),
+                    "%d/%b/%Y:%H:%M:%S %z",
+                ).unwrap(),
+                original_log_line: None,
+            })
+        };
+        let response = |id| {
+            log_parser::log_events::LogEvent::Response(log_parser::log_events::Response {
+                id,
+                response_time: Duration::milliseconds(7),
+                original_log_line: None,
+                status: None,
+                http_error: None,
+            })
+        };
+
+        let events = vec![
+            request(1, "08:00:00"),
+            request(2, "09:30:00"),
+            // The id is used again, e.g. after a restart
+            request(1, "09:31:00"),
+            response(7),
+            request(3, "09:59:00"),
+            request(4, "10:31:00"),
+            response(1),
+            response(2),
+        ];
+
+        let mut events_iter = events.into_iter();
+        let mut iterator = RequestResponsePairIterator::new(&mut events_iter)
+            .prune_pending(Duration::minutes(60));
+
+        let times: Vec<String> = iterator
+            .by_ref()
+            .map(|pair| pair.request.time.format("%H:%M").to_string())
+            .collect();
+
+        assert_eq!(times, vec!["09:31"]);
+        assert_eq!(
+            iterator.into_unmatched_events(),
+            vec![request(3, "09:59:00"), request(4, "10:31:00")]
+        );
+    }
+
+    #[test]
+    fn test_into_unmatched_events() {
+        let request = log_parser::log_events::Request {
+            id: 1,
+            time: DateTime::parse_from_str("08/Apr/2016:09:57:47 +0200", "%d/%b/%Y:%H:%M:%S %z")
+                .unwrap(),
+            original_log_line: None,
+        };
+        let response = log_parser::log_events::Response {
+            id: 2,
+            response_time: Duration::milliseconds(7),
+            original_log_line: None,
+            status: None,
+            http_error: None,
+        };
+
+        let events = vec![
+            log_parser::log_events::LogEvent::Response(response.clone()),
+            log_parser::log_events::LogEvent::Request(request.clone()),
+        ];
+
+        let mut events_iter = events.into_iter();
+        let mut iterator = RequestResponsePairIterator::new(&mut events_iter);
+
+        assert!(iterator.next().is_none());
+        assert_eq!(
+            iterator.into_unmatched_events(),
+            vec![
+                log_parser::log_events::LogEvent::Request(request),
+                log_parser::log_events::LogEvent::Response(response),
+            ]
+        );
+    }
+
     #[test]
     fn test_timing_trait() {
         let timing: &dyn Timing = &RequestResponsePair {
@@ -119,23 +339,24 @@ mod tests {
                     "08/Apr/2016:09:57:47 +0200",
                     "%d/%b/%Y:%H:%M:%S %z",
                 ).unwrap(),
-                original_log_line: "whatever".to_string(),
+                original_log_line: Some("whatever".to_string()),
             },
             response: log_parser::log_events::Response {
                 id: 1,
                 response_time: Duration::milliseconds(7),
-                original_log_line: "whatever".to_string(),
+                original_log_line: Some("whatever".to_string()),
+                status: None,
                 http_error: None,
             },
         } as &dyn Timing;
 
-        let result: i64 = timing.num_milliseconds();
-        assert_eq!(result, 7);
+        let result: i64 = timing.num_microseconds();
+        assert_eq!(result, 7000);
 
         let boxed_timing = Box::new(timing);
 
-        let result: i64 = boxed_timing.num_milliseconds();
-        assert_eq!(result, 7);
+        let result: i64 = boxed_timing.num_microseconds();
+        assert_eq!(result, 7000);
     }
 
     #[test]
@@ -147,12 +368,13 @@ mod tests {
                     "08/Apr/2016:09:57:47 +0200",
                     "%d/%b/%Y:%H:%M:%S %z",
                 ).unwrap(),
-                original_log_line: "whatever".to_string(),
+                original_log_line: Some("whatever".to_string()),
             },
             response: log_parser::log_events::Response {
                 id: 1,
                 response_time: Duration::milliseconds(7),
-                original_log_line: "whatever".to_string(),
+                original_log_line: Some("whatever".to_string()),
+                status: None,
                 http_error: None,
             },
         } as &dyn HttpErrorState;
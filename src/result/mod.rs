@@ -5,4 +5,15 @@ pub struct RequestLogAnalyzerResult {
     pub count: usize,
     pub timing: Option<analyzer::TimingResult>,
     pub error: Option<analyzer::aggregated_error_rates::ErrorRatesResult>,
+    pub distribution: analyzer::distribution::Distribution,
+    pub files: Vec<FileSummary>,
+}
+
+/// How many lines were read from an input file, and why reading stopped
+/// early.
+#[derive(PartialEq, Debug, Clone)]
+pub struct FileSummary {
+    pub filename: String,
+    pub lines: usize,
+    pub error: Option<String>,
 }
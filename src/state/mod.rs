@@ -0,0 +1,501 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::path::Path;
+use chrono::*;
+use failure::{Error, err_msg};
+use log::{info, warn};
+use crate::log_parser::log_events::Request;
+use crate::multi_file::FileRange;
+
+const HEADER: &str = "# request_log_analyzer state v1";
+
+/// Pending requests older than this (compared to the newest one) are
+/// assumed to never get a response and are dropped.
+const MAX_PENDING_REQUEST_AGE_MINUTES: i64 = 60;
+
+/// How far a file has been analyzed.
+#[derive(PartialEq, Debug, Clone)]
+pub struct FileState {
+    pub filename: String,
+    pub inode: u64,
+    pub offset: u64,
+}
+
+/// What previous runs with the same `--state-file` have analyzed.
+#[derive(PartialEq, Debug, Default)]
+pub struct State {
+    pub files: Vec<FileState>,
+    /// Requests that did not have a response yet
+    pub pending_requests: Vec<Request>,
+}
+
+impl State {
+    /// Load the state, a missing file is the empty state of a first run.
+    pub fn load(path: &str) -> Result<State, Error> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(State::default()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut lines = text.lines();
+
+        if lines.next() != Some(HEADER) {
+            return Err(err_msg(format!("{} is not a state file", path)));
+        }
+
+        let mut state = State::default();
+
+        for line in lines {
+            let mut fields = line.splitn(4, '\t');
+
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some("file"), Some(inode), Some(offset), Some(filename)) => {
+                    state.files.push(FileState {
+                        filename: String::from(filename),
+                        inode: inode.parse()?,
+                        offset: offset.parse()?,
+                    })
+                }
+                (Some("request"), Some(id), Some(time), original_log_line) => {
+                    state.pending_requests.push(Request {
+                        id: id.parse()?,
+                        time: DateTime::parse_from_rfc3339(time)?,
+                        original_log_line: original_log_line
+                            .filter(|line| !line.is_empty())
+                            .map(String::from),
+                    })
+                }
+                _ => return Err(err_msg(format!("Invalid line in state file {}", path))),
+            }
+        }
+
+        Ok(state)
+    }
+
+    /// Save the state, replacing the file only once it is complete.
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        let temporary_path = format!("{}.tmp", path);
+
+        {
+            let mut file = io::BufWriter::new(File::create(&temporary_path)?);
+            writeln!(file, "{}", HEADER)?;
+
+            for file_state in &self.files {
+                writeln!(
+                    file,
+                    "file\t{}\t{}\t{}",
+                    file_state.inode,
+                    file_state.offset,
+                    file_state.filename
+                )?;
+            }
+
+            for request in &self.pending_requests {
+                writeln!(
+                    file,
+                    "request\t{}\t{}\t{}",
+                    request.id,
+                    request.time.to_rfc3339(),
+                    request.original_log_line.as_ref().map_or("", |line| line.as_str())
+                )?;
+            }
+
+            file.flush()?;
+        }
+
+        fs::rename(&temporary_path, path)?;
+        Ok(())
+    }
+
+    /// Work out which parts of the files are new since the previous run.
+    ///
+    /// Returns the byte ranges to read, suitable for
+    /// `MultiFile::new_with_ranges`, and the state of the files after reading
+    /// them. Only complete lines are read, a line that is still being written
+    /// is left for the next run.
+    ///
+    /// Files are recognized by their inode first, so a file that was rotated
+    /// to a new name is read on from where it was left, and every file is read
+    /// once even if it is both rotated and among `filenames`.
+    pub fn plan(&self, filenames: &[String]) -> io::Result<(Vec<FileRange>, Vec<FileState>)> {
+        let mut ranges = vec![];
+        let mut files = vec![];
+        // The inodes of the files to read, and where they end
+        let mut planned: Vec<(u64, u64)> = vec![];
+
+        for filename in filenames {
+            let inode = inode(&fs::metadata(filename)?);
+            let end = last_line_end(filename)?;
+
+            if let Some(planned_end) = planned_end(&planned, inode) {
+                files.push(FileState {
+                    filename: filename.clone(),
+                    inode,
+                    offset: planned_end,
+                });
+                continue;
+            }
+
+            let previous = self.files
+                .iter()
+                .find(|file| inode != 0 && file.inode == inode)
+                .or_else(|| self.files.iter().find(|file| &file.filename == filename));
+
+            let start = match previous {
+                Some(previous) if previous.inode == inode && previous.offset <= end => {
+                    previous.offset
+                }
+                Some(previous) if previous.inode == inode => {
+                    info!("{} was truncated, reading from the start", filename);
+                    0
+                }
+                Some(previous) if planned_end(&planned, previous.inode).is_some() => 0,
+                Some(previous) => {
+                    match find_rotated(filename, previous.inode) {
+                        Some(rotated_filename) => {
+                            info!("{} was rotated to {}", filename, rotated_filename);
+                            let rotated_end = last_line_end(&rotated_filename)?;
+
+                            if previous.offset < rotated_end {
+                                ranges.push((rotated_filename, Some(previous.offset..rotated_end)));
+                            }
+                            planned.push((previous.inode, rotated_end));
+                        }
+                        None => {
+                            warn!(
+                                "{} was rotated, lines written to the old file since the \
+                                 previous run are not analyzed",
+                                filename
+                            )
+                        }
+                    }
+                    0
+                }
+                None => 0,
+            };
+
+            ranges.push((filename.clone(), Some(start..end)));
+            planned.push((inode, end));
+            files.push(FileState {
+                filename: filename.clone(),
+                inode,
+                offset: end,
+            });
+        }
+
+        Ok((ranges, files))
+    }
+}
+
+/// Where a file is read up to if it is already planned to be read. Files
+/// without an inode (outside of Unix) are never recognized.
+fn planned_end(planned: &[(u64, u64)], inode: u64) -> Option<u64> {
+    planned
+        .iter()
+        .find(|&&(planned_inode, _)| inode != 0 && planned_inode == inode)
+        .map(|&(_, end)| end)
+}
+
+/// How much older than the latest request pending requests may get.
+pub fn max_pending_request_age() -> Duration {
+    Duration::minutes(MAX_PENDING_REQUEST_AGE_MINUTES)
+}
+
+/// Drop pending requests that are too old to ever get a response.
+pub fn prune_pending_requests(requests: Vec<Request>) -> Vec<Request> {
+    let newest = match requests.iter().map(|request| request.time).max() {
+        Some(newest) => newest,
+        None => return requests,
+    };
+
+    let oldest_allowed = newest - max_pending_request_age();
+
+    requests
+        .into_iter()
+        .filter(|request| request.time >= oldest_allowed)
+        .collect()
+}
+
+/// The offset right after the last line break of a file.
+fn last_line_end(filename: &str) -> io::Result<u64> {
+    let mut file = File::open(filename)?;
+    let mut end = file.metadata()?.len();
+    let mut buffer = vec![0; 64 * 1024];
+
+    while end > 0 {
+        let start = end.saturating_sub(buffer.len() as u64);
+        let size = (end - start) as usize;
+
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut buffer[..size])?;
+
+        if let Some(position) = buffer[..size].iter().rposition(|byte| *byte == b'\n') {
+            return Ok(start + position as u64 + 1);
+        }
+
+        end = start;
+    }
+
+    Ok(0)
+}
+
+#[cfg(unix)]
+fn inode(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn inode(_: &fs::Metadata) -> u64 {
+    0
+}
+
+/// Find the file that `filename` was rotated to, by its inode.
+#[cfg(unix)]
+fn find_rotated(filename: &str, previous_inode: u64) -> Option<String> {
+    let directory = match Path::new(filename).parent() {
+        Some(directory) if directory != Path::new("") => directory,
+        _ => Path::new("."),
+    };
+
+    fs::read_dir(directory)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .find(|entry| {
+            entry.metadata().map(|metadata| inode(&metadata)).ok() == Some(previous_inode)
+        })
+        .and_then(|entry| entry.path().to_str().map(String::from))
+}
+
+#[cfg(not(unix))]
+fn find_rotated(_: &str, _: u64) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::temp_files::{append, temp_path};
+    use super::*;
+
+    fn request_fixture(time: &str) -> Request {
+        Request {
+            id: 42,
+            time: DateTime::parse_from_str(time, "%d/%b/%Y:%H:%M:%S %z").unwrap(),
+            original_log_line: None,
+        }
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let state = State::load("src/test/non-existent.state").unwrap();
+
+        assert_eq!(state, State::default());
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        assert!(State::load("src/test/simple-1.log").is_err());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = temp_path("state-roundtrip.state");
+
+        let mut request_with_line = request_fixture("08/Apr/2016:09:57:47 +0200");
+        request_with_line.original_log_line = Some(String::from(
+            "08/Apr/2016:09:57:47 +0200 [42] -> GET /content/some/page.html HTTP/1.1",
+        ));
+
+        let state = State {
+            files: vec![
+                FileState {
+                    filename: String::from("logs/request.log"),
+                    inode: 1234,
+                    offset: 5678,
+                },
+            ],
+            pending_requests: vec![request_fixture("08/Apr/2016:09:57:47 -0130"), request_with_line],
+        };
+
+        state.save(&path).unwrap();
+        let loaded = State::load(&path).unwrap();
+
+        assert_eq!(loaded, state);
+        assert_eq!(
+            loaded.pending_requests[0].time.offset(),
+            state.pending_requests[0].time.offset()
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_plan_first_run_and_append() {
+        let path = temp_path("state-append.log");
+        append(&path, "line one\nline tw");
+
+        let (ranges, files) = State::default().plan(std::slice::from_ref(&path)).unwrap();
+
+        assert_eq!(ranges, vec![(path.clone(), Some(0..9))]);
+        assert_eq!(files[0].offset, 9);
+
+        append(&path, "o\nline three\n");
+        let state = State {
+            files,
+            pending_requests: vec![],
+        };
+
+        let (ranges, files) = state.plan(std::slice::from_ref(&path)).unwrap();
+
+        assert_eq!(ranges, vec![(path.clone(), Some(9..29))]);
+        assert_eq!(files[0].offset, 29);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_plan_truncated() {
+        let path = temp_path("state-truncated.log");
+        append(&path, "a long line before truncation\n");
+
+        let (_, files) = State::default().plan(std::slice::from_ref(&path)).unwrap();
+
+        File::create(&path).unwrap();
+        append(&path, "short\n");
+        let state = State {
+            files,
+            pending_requests: vec![],
+        };
+
+        let (ranges, _) = state.plan(std::slice::from_ref(&path)).unwrap();
+
+        assert_eq!(ranges, vec![(path.clone(), Some(0..6))]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_plan_rotated() {
+        let path = temp_path("state-rotated.log");
+        let rotated_path = temp_path("state-rotated.log.1");
+        append(&path, "before\n");
+
+        let (_, files) = State::default().plan(std::slice::from_ref(&path)).unwrap();
+
+        append(&path, "late\n");
+        fs::rename(&path, &rotated_path).unwrap();
+        append(&path, "new file\n");
+        let state = State {
+            files,
+            pending_requests: vec![],
+        };
+
+        let (ranges, files) = state.plan(std::slice::from_ref(&path)).unwrap();
+
+        assert_eq!(
+            ranges,
+            vec![
+                (rotated_path.clone(), Some(7..12)),
+                (path.clone(), Some(0..9)),
+            ]
+        );
+        assert_eq!(files[0].offset, 9);
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&rotated_path).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_plan_rotated_glob() {
+        let path = temp_path("state-glob.log");
+        let rotated_path = temp_path("state-glob.log.1");
+        append(&path, "before\n");
+
+        let (_, files) = State::default().plan(std::slice::from_ref(&path)).unwrap();
+
+        append(&path, "late\n");
+        fs::rename(&path, &rotated_path).unwrap();
+        append(&path, "new file\n");
+        let state = State {
+            files,
+            pending_requests: vec![],
+        };
+
+        // Like a glob, rotated files first
+        let (ranges, files) = state.plan(&[rotated_path.clone(), path.clone()]).unwrap();
+
+        assert_eq!(
+            ranges,
+            vec![
+                (rotated_path.clone(), Some(7..12)),
+                (path.clone(), Some(0..9)),
+            ]
+        );
+        assert_eq!(
+            files.iter().map(|file| file.offset).collect::<Vec<_>>(),
+            vec![12, 9]
+        );
+
+        let (ranges, reversed_files) = state.plan(&[path.clone(), rotated_path.clone()]).unwrap();
+
+        assert_eq!(
+            ranges,
+            vec![
+                (rotated_path.clone(), Some(7..12)),
+                (path.clone(), Some(0..9)),
+            ]
+        );
+        assert_eq!(
+            reversed_files.iter().map(|file| file.offset).collect::<Vec<_>>(),
+            vec![9, 12]
+        );
+
+        // Nothing new in either file
+        let state = State {
+            files,
+            pending_requests: vec![],
+        };
+        let (ranges, _) = state.plan(&[rotated_path.clone(), path.clone()]).unwrap();
+
+        assert_eq!(
+            ranges,
+            vec![
+                (rotated_path.clone(), Some(12..12)),
+                (path.clone(), Some(9..9)),
+            ]
+        );
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&rotated_path).unwrap();
+    }
+
+    #[test]
+    fn test_plan_non_existent() {
+        assert!(
+            State::default()
+                .plan(&[String::from("src/test/non-existent.log")])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_prune_pending_requests() {
+        let requests = vec![
+            request_fixture("08/Apr/2016:08:00:00 +0200"),
+            request_fixture("08/Apr/2016:09:30:00 +0200"),
+            request_fixture("08/Apr/2016:10:00:00 +0200"),
+        ];
+
+        let result = prune_pending_requests(requests);
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(prune_pending_requests(vec![]), vec![]);
+    }
+}
@@ -0,0 +1,33 @@
+//! Files in the temporary directory, for the tests of reading logs which are
+//! appended to, rotated or discovered.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::process;
+
+/// A path in the temporary directory for `name`, unique to this process,
+/// with no file there yet.
+pub fn temp_path(name: &str) -> String {
+    let path = env::temp_dir().join(format!("request_log_analyzer-{}-{}", process::id(), name));
+    let _ = fs::remove_file(&path);
+    path.to_str().unwrap().to_string()
+}
+
+/// An empty directory in the temporary directory for `name`.
+pub fn temp_directory(name: &str) -> String {
+    let path = temp_path(name);
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir(&path).unwrap();
+    path
+}
+
+/// Append `text` to the file at `path`, creating it if needed.
+pub fn append(path: &str, text: &str) {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap();
+    file.write_all(text.as_bytes()).unwrap();
+}